@@ -5,8 +5,9 @@
 //!
 //! タッチパッドに触れる/離す、キーを押す/離すしてイベントを確認
 
-use evdev::{Device, EventType, InputEventKind, Key};
+use evdev::Device;
 use std::fs;
+use way_thumbsense::input::{find_touchpad, DeviceClassifier, DeviceKind};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== 入力デバイス一覧 ===\n");
@@ -14,11 +15,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // /dev/input/event* を列挙
     let mut devices: Vec<_> = fs::read_dir("/dev/input")?
         .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_name()
-                .to_string_lossy()
-                .starts_with("event")
-        })
+        .filter(|e| e.file_name().to_string_lossy().starts_with("event"))
         .collect();
 
     devices.sort_by_key(|e| e.path());
@@ -29,36 +26,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Ok(device) => {
                 let name = device.name().unwrap_or("(unknown)");
 
-                // タッチパッドかキーボードを判定
-                let supported = device.supported_events();
-                let has_abs = supported.contains(EventType::ABSOLUTE);
-                let has_key = supported.contains(EventType::KEY);
-
-                let device_type = if has_abs && has_key {
-                    // ABSとKEY両方あればタッチパッド候補
-                    if let Some(keys) = device.supported_keys() {
-                        if keys.contains(Key::BTN_TOUCH) {
-                            "TOUCHPAD"
-                        } else if keys.contains(Key::KEY_A) {
-                            "KEYBOARD"
-                        } else {
-                            ""
-                        }
-                    } else {
-                        ""
-                    }
-                } else if has_key {
-                    if let Some(keys) = device.supported_keys() {
-                        if keys.contains(Key::KEY_A) {
-                            "KEYBOARD"
-                        } else {
-                            ""
-                        }
-                    } else {
-                        ""
-                    }
-                } else {
-                    ""
+                // way-thumbsense本体と同じ分類
+                let kind = DeviceClassifier::from_device(&device).kind();
+                let device_type = match kind {
+                    DeviceKind::Other => String::new(),
+                    kind => kind.as_str().to_uppercase(),
                 };
 
                 let marker = if !device_type.is_empty() {
@@ -75,26 +47,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    println!("\n=== BTN_TOUCH対応デバイス ===\n");
+    println!("\n=== 自動検出されるタッチパッド ===\n");
 
-    for entry in fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
-        if !path.to_string_lossy().contains("event") {
-            continue;
-        }
-
-        if let Ok(device) = Device::open(&path) {
-            if let Some(keys) = device.supported_keys() {
-                if keys.contains(Key::BTN_TOUCH) {
-                    println!(
-                        "{}: {} (BTN_TOUCH対応)",
-                        path.display(),
-                        device.name().unwrap_or("unknown")
-                    );
-                }
-            }
-        }
+    match find_touchpad() {
+        Ok(device) => println!("{}", device.name().unwrap_or("unknown")),
+        Err(e) => println!("{}", e),
     }
 
     Ok(())
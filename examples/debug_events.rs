@@ -3,11 +3,10 @@
 //! 使い方:
 //!   cargo run --example debug_events
 
-use evdev::{Device, InputEventKind};
-use std::fs;
+use way_thumbsense::input::find_touchpad;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // BTN_TOUCH対応のタッチパッドを検出
+    // 本体と同じ規則でタッチパッドを検出
     let mut touchpad = find_touchpad()?;
     println!("Touchpad: {}", touchpad.name().unwrap_or("unknown"));
     println!("\nTouch the trackpad to see events...\n");
@@ -28,26 +27,3 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
-
-fn find_touchpad() -> Result<Device, Box<dyn std::error::Error>> {
-    use evdev::{EventType, Key};
-
-    for entry in fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if !path.to_string_lossy().contains("event") {
-            continue;
-        }
-
-        if let Ok(device) = Device::open(&path) {
-            if let Some(keys) = device.supported_keys() {
-                if keys.contains(Key::BTN_TOUCH) {
-                    return Ok(device);
-                }
-            }
-        }
-    }
-
-    Err("Touchpad not found".into())
-}
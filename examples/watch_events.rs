@@ -6,7 +6,6 @@
 //! タッチパッドのBTN_TOUCHとキーボードのJ/Kイベントを監視
 
 use evdev::{Device, EventType, InputEventKind, Key};
-use std::os::unix::io::AsRawFd;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
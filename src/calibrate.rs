@@ -0,0 +1,161 @@
+//! キャリブレーション: 実際に届く座標範囲と除外領域の提案
+
+use crate::input::TouchpadDimensions;
+use crate::tracker::ExclusionZones;
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind};
+
+/// 提案する除外領域の上限（向かい合う辺の合計が100%未満になるように）
+const MAX_SUGGESTED_ZONE: f32 = 45.0;
+
+/// なぞった範囲から観測した座標の最小値・最大値を記録
+#[derive(Debug, Default)]
+pub struct RangeRecorder {
+    x: Option<(i32, i32)>,
+    y: Option<(i32, i32)>,
+}
+
+impl RangeRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update_x(&mut self, x: i32) {
+        self.x = Some(extend(self.x, x));
+    }
+
+    pub fn update_y(&mut self, y: i32) {
+        self.y = Some(extend(self.y, y));
+    }
+
+    /// fetch_eventsで得たイベント列から座標を記録
+    pub fn process(&mut self, events: &[InputEvent]) {
+        for ev in events {
+            match ev.kind() {
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X)
+                | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                    self.update_x(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y)
+                | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                    self.update_y(ev.value());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 観測した範囲（両軸とも幅がなければ None）
+    pub fn dimensions(&self) -> Option<TouchpadDimensions> {
+        let (min_x, max_x) = self.x?;
+        let (min_y, max_y) = self.y?;
+        if min_x == max_x || min_y == max_y {
+            return None;
+        }
+        Some(TouchpadDimensions {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+        })
+    }
+}
+
+fn extend(range: Option<(i32, i32)>, value: i32) -> (i32, i32) {
+    match range {
+        Some((min, max)) => (min.min(value), max.max(value)),
+        None => (value, value),
+    }
+}
+
+/// 除外したい位置のタッチから除外領域を提案
+///
+/// 各点を一番近い辺に割り当て、その辺からの距離（%）に `margin` を足した値を
+/// 辺ごとの最大値として、整数%に切り上げる
+pub fn suggest_zones(
+    range: &TouchpadDimensions,
+    points: &[(i32, i32)],
+    margin: f32,
+) -> ExclusionZones {
+    let mut zones = ExclusionZones::none();
+
+    for &(x, y) in points {
+        let x_percent = (x - range.min_x) as f32 / range.width() as f32 * 100.0;
+        let y_percent = (y - range.min_y) as f32 / range.height() as f32 * 100.0;
+        let x_percent = x_percent.clamp(0.0, 100.0);
+        let y_percent = y_percent.clamp(0.0, 100.0);
+
+        let candidates = [
+            (y_percent, &mut zones.top),
+            (100.0 - y_percent, &mut zones.bottom),
+            (x_percent, &mut zones.left),
+            (100.0 - x_percent, &mut zones.right),
+        ];
+        let (distance, zone) = candidates
+            .into_iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("four edges");
+
+        let suggested = (distance + margin).ceil().min(MAX_SUGGESTED_ZONE);
+        *zone = zone.max(suggested);
+    }
+
+    zones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range() -> TouchpadDimensions {
+        TouchpadDimensions {
+            min_x: 0,
+            max_x: 1000,
+            min_y: 0,
+            max_y: 1000,
+        }
+    }
+
+    #[test]
+    fn records_observed_range() {
+        let mut recorder = RangeRecorder::new();
+        assert!(recorder.dimensions().is_none());
+
+        recorder.update_x(120);
+        recorder.update_y(80);
+        assert!(recorder.dimensions().is_none());
+
+        recorder.update_x(900);
+        recorder.update_x(300);
+        recorder.update_y(700);
+
+        let dims = recorder.dimensions().unwrap();
+        assert_eq!((dims.min_x, dims.max_x), (120, 900));
+        assert_eq!((dims.min_y, dims.max_y), (80, 700));
+    }
+
+    #[test]
+    fn suggests_zone_for_nearest_edge() {
+        // 下端から12%と8%、左端から3%
+        let points = [(500, 880), (400, 920), (30, 500)];
+        let zones = suggest_zones(&range(), &points, 5.0);
+
+        assert_eq!(zones, ExclusionZones::new(0.0, 17.0, 8.0, 0.0));
+        assert!(zones.validate().is_ok());
+    }
+
+    #[test]
+    fn suggested_zones_stay_valid() {
+        // 中央付近を触っても向かい合う辺の合計は100%未満
+        let points = [(500, 499), (500, 501)];
+        let zones = suggest_zones(&range(), &points, 5.0);
+
+        assert_eq!(zones.top, MAX_SUGGESTED_ZONE);
+        assert_eq!(zones.bottom, MAX_SUGGESTED_ZONE);
+        assert!(zones.validate().is_ok());
+    }
+
+    #[test]
+    fn no_points_means_no_zones() {
+        assert!(!suggest_zones(&range(), &[], 5.0).is_enabled());
+    }
+}
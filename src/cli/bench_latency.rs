@@ -0,0 +1,251 @@
+//! bench-latency サブコマンド: uinputの仮想デバイスで入力から出力までの遅延を測る
+//!
+//! 仮想タッチパッドとキーボードをデーモンのイベントループにそのまま渡し、遅延はデーモンの集計から読む。
+//! 仮想マウスのクリックやトリガーキーがデスクトップに届かないよう、出力側のノードはgrabしておく
+
+use super::daemon::{Daemon, Devices};
+use crate::Args;
+use anyhow::Context;
+use evdev::uinput::VirtualDeviceBuilder;
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, Key, PropType,
+    UinputAbsSetup,
+};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use way_thumbsense::config::{BackendKind, HooksConfig};
+use way_thumbsense::control::ControlServer;
+use way_thumbsense::core::KeyCode;
+use way_thumbsense::event_loop;
+use way_thumbsense::latency::LatencySummary;
+use way_thumbsense::metrics::Metrics;
+use way_thumbsense::output::{wait_until_ready, VirtualDevice};
+use way_thumbsense::poll::wait_readable;
+use way_thumbsense::signals::{SignalPipe, SIGINT, SIGTERM};
+
+/// 出力が届くまでの待ち時間
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 仮想タッチパッドの座標範囲
+const PAD_MAX: (i32, i32) = (1224, 804);
+
+pub fn run(args: &Args, iterations: usize, interval_ms: u64) -> anyhow::Result<()> {
+    let mut config = super::load_config(args)?;
+    // 実行中のデーモンの仮想デバイスと区別する
+    config.backend.kind = BackendKind::Uinput;
+    config.virtual_device.mouse.name = "way-thumbsense bench mouse".to_string();
+    config.virtual_device.keyboard.name = "way-thumbsense bench output keyboard".to_string();
+    // フックやメトリクスのソケットなど、計測の外に影響する設定は使わない
+    config.hooks = HooksConfig::default();
+    config.metrics.socket = None;
+
+    let output = VirtualDevice::new(&config).context("Cannot create output devices")?;
+    let outputs = Outputs {
+        clicks: open_grabbed(&output.mouse_node().devnode)?,
+        triggers: open_grabbed(&output.keyboard_node().devnode)?,
+    };
+
+    let deadline = Instant::now() + Duration::from_millis(config.virtual_device.ready_timeout_ms);
+    let mut pad = create_touchpad()?;
+    let pad_input = open_grabbed(&wait_until_ready(&mut pad, deadline)?.devnode)?;
+    let mut keyboard = create_keyboard()?;
+    let keyboard_input = open_grabbed(&wait_until_ready(&mut keyboard, deadline)?.devnode)?;
+    let mapped_key = config.mappings().first().map(|m| m.key);
+
+    // 計測が終われば shutdown で止める。Ctrl+C でも中断できる
+    let shutdown = Arc::new(Notify::new());
+    let signals = SignalPipe::install(&[SIGTERM, SIGINT])?;
+    let socket =
+        std::env::temp_dir().join(format!("way-thumbsense-bench-{}.sock", std::process::id()));
+    let control = ControlServer::bind(&socket)
+        .with_context(|| format!("Cannot create control socket {}", socket.display()))?;
+
+    let runtime = event_loop::runtime()?;
+    let _runtime = runtime.enter();
+    let metrics = Arc::new(Mutex::new(Metrics::new()));
+    let devices = Devices {
+        touchpad: Some(pad_input),
+        keyboard: Some(keyboard_input),
+        backend: Box::new(output),
+    };
+    let mut daemon = Daemon::with_devices(args, config, devices, true, Arc::clone(&metrics), None)?;
+
+    println!(
+        "Measuring {} touches{} ({}ms apart)...",
+        iterations,
+        if mapped_key.is_some() {
+            " and mapped key presses"
+        } else {
+            ""
+        },
+        interval_ms
+    );
+    let interval = Duration::from_millis(interval_ms);
+    let done = Arc::clone(&shutdown);
+    let driver = std::thread::spawn(move || {
+        let result = drive(
+            &mut pad,
+            &mut keyboard,
+            outputs,
+            mapped_key,
+            iterations,
+            interval,
+        );
+        // 失敗してもイベントループを止める
+        done.notify_one();
+        // 切断として扱われないよう、ループを抜けるまで仮想デバイスを残す
+        (result, pad, keyboard)
+    });
+    let exit = runtime.block_on(daemon.run(&control, &signals, None, None, Some(&shutdown)));
+    let (driven, _pad, _keyboard) = driver
+        .join()
+        .map_err(|_| anyhow::anyhow!("Input thread panicked"))?;
+    driven?;
+    exit?;
+
+    let snapshot = metrics.lock().unwrap().snapshot();
+    println!();
+    println!(
+        "{:<16} {:>6} {:>9} {:>9} {:>9} {:>9}",
+        "path", "count", "p50", "p90", "p99", "max"
+    );
+    print_row("touch -> trigger", &snapshot.touch_latency);
+    if mapped_key.is_some() {
+        print_row("key -> click", &snapshot.key_latency);
+    }
+    Ok(())
+}
+
+fn print_row(label: &str, summary: &LatencySummary) {
+    let us = |v: u64| format!("{}us", v);
+    println!(
+        "{:<16} {:>6} {:>9} {:>9} {:>9} {:>9}",
+        label,
+        summary.count,
+        us(summary.p50_us),
+        us(summary.p90_us),
+        us(summary.p99_us),
+        us(summary.max_us)
+    );
+}
+
+/// デーモンの出力を読むノード（grab済み）
+struct Outputs {
+    clicks: Device,
+    triggers: Device,
+}
+
+/// 入力を送り、デーモンが出力するのを待ってから次を送る
+fn drive(
+    pad: &mut evdev::uinput::VirtualDevice,
+    keyboard: &mut evdev::uinput::VirtualDevice,
+    mut outputs: Outputs,
+    mapped_key: Option<KeyCode>,
+    iterations: usize,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    for i in 0..iterations {
+        let tracking_id = i as i32;
+        pad.emit(&touch_events(tracking_id, PAD_MAX.0 / 2, PAD_MAX.1 / 2))?;
+        wait_output(&mut outputs.triggers)?;
+
+        if let Some(code) = mapped_key {
+            for value in [1, 0] {
+                keyboard.emit(&[InputEvent::new(EventType::KEY, code.0, value)])?;
+                wait_output(&mut outputs.clicks)?;
+            }
+        }
+
+        pad.emit(&lift_events())?;
+        wait_output(&mut outputs.triggers)?;
+        std::thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// 出力ノードに書き込まれるまで待ち、読める分を捨てる
+fn wait_output(device: &mut Device) -> anyhow::Result<()> {
+    if !wait_readable(&[device.as_raw_fd()], Some(READ_TIMEOUT))?[0] {
+        anyhow::bail!(
+            "No output on {} (is the touch in an exclusion zone?)",
+            device.name().unwrap_or("virtual device")
+        );
+    }
+    device.fetch_events()?.for_each(drop);
+    Ok(())
+}
+
+/// 他のプログラム（keydやコンポジター）に届かないようにgrabして開く
+fn open_grabbed(path: &std::path::Path) -> anyhow::Result<Device> {
+    let mut device =
+        Device::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    device
+        .grab()
+        .with_context(|| format!("Cannot grab {}", path.display()))?;
+    Ok(device)
+}
+
+fn create_touchpad() -> anyhow::Result<evdev::uinput::VirtualDevice> {
+    let keys: AttributeSet<Key> = [Key::BTN_LEFT, Key::BTN_TOUCH, Key::BTN_TOOL_FINGER]
+        .into_iter()
+        .collect();
+    let props: AttributeSet<PropType> = [PropType::POINTER, PropType::BUTTONPAD]
+        .into_iter()
+        .collect();
+
+    let mut builder = VirtualDeviceBuilder::new()?
+        .name("way-thumbsense bench touchpad")
+        .with_keys(&keys)?
+        .with_properties(&props)?;
+    for (axis, max) in [
+        (AbsoluteAxisType::ABS_X, PAD_MAX.0),
+        (AbsoluteAxisType::ABS_Y, PAD_MAX.1),
+        (AbsoluteAxisType::ABS_MT_SLOT, 4),
+        (AbsoluteAxisType::ABS_MT_POSITION_X, PAD_MAX.0),
+        (AbsoluteAxisType::ABS_MT_POSITION_Y, PAD_MAX.1),
+        (AbsoluteAxisType::ABS_MT_TRACKING_ID, 65535),
+    ] {
+        builder = builder
+            .with_absolute_axis(&UinputAbsSetup::new(axis, AbsInfo::new(0, 0, max, 0, 0, 0)))?;
+    }
+    Ok(builder.build()?)
+}
+
+fn create_keyboard() -> anyhow::Result<evdev::uinput::VirtualDevice> {
+    let keys: AttributeSet<Key> = (Key::KEY_ESC.code()..=Key::KEY_KPDOT.code())
+        .map(Key::new)
+        .collect();
+    Ok(VirtualDeviceBuilder::new()?
+        .name("way-thumbsense bench input keyboard")
+        .with_keys(&keys)?
+        .build()?)
+}
+
+/// 1本指で触れる（emitがSYN_REPORTを付ける）
+fn touch_events(tracking_id: i32, x: i32, y: i32) -> Vec<InputEvent> {
+    vec![
+        abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+        abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, tracking_id),
+        abs(AbsoluteAxisType::ABS_MT_POSITION_X, x),
+        abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y),
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 1),
+        InputEvent::new(EventType::KEY, Key::BTN_TOOL_FINGER.code(), 1),
+        abs(AbsoluteAxisType::ABS_X, x),
+        abs(AbsoluteAxisType::ABS_Y, y),
+    ]
+}
+
+fn lift_events() -> Vec<InputEvent> {
+    vec![
+        abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+        abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 0),
+        InputEvent::new(EventType::KEY, Key::BTN_TOOL_FINGER.code(), 0),
+    ]
+}
+
+fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
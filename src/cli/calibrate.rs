@@ -0,0 +1,112 @@
+//! calibrate サブコマンド: 座標範囲と除外領域を測って設定ファイルへ書き込む
+
+use crate::Args;
+use evdev::Device;
+use std::io::BufRead;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use way_thumbsense::calibrate::{suggest_zones, RangeRecorder};
+use way_thumbsense::config::{
+    update_touchpad_profile, write_touchpad_profile, Config, TouchpadProfile,
+};
+use way_thumbsense::engine::{Engine, TouchEvent};
+use way_thumbsense::poll::wait_readable;
+use way_thumbsense::tracker::{ExclusionZones, TouchTracker};
+
+pub fn run(args: &Args, margin: f32, dry_run: bool) -> anyhow::Result<()> {
+    // 書き込み先のファイルはまだなくてもよい
+    let path = Config::resolve_path(args.config.as_deref());
+    let config = match path.as_deref().filter(|p| p.exists()) {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let mut touchpad = super::open_touchpad(&config)?;
+    let device = touchpad.name().unwrap_or("unknown").to_string();
+    println!("Calibrating: {}", device);
+
+    // 1. 端をなぞって実際に届く範囲を測る
+    println!("\nTrace along all four edges of the touchpad, then press Enter.");
+    let mut recorder = RangeRecorder::new();
+    read_until_enter(&mut touchpad, |events| recorder.process(events))?;
+    let range = recorder
+        .dimensions()
+        .ok_or_else(|| anyhow::anyhow!("No movement recorded on the touchpad"))?;
+    println!(
+        "Observed range: X({} to {}), Y({} to {})",
+        range.min_x, range.max_x, range.min_y, range.max_y
+    );
+
+    // 2. 除外したい場所を触ってもらう
+    println!("\nTouch each area that should not activate the layer (e.g. where your palm rests),");
+    println!("then press Enter. Press Enter without touching to keep the current zones.");
+    let mut engine = Engine::new(TouchTracker::new(range, ExclusionZones::none()));
+    let mut points = Vec::new();
+    read_until_enter(&mut touchpad, |events| {
+        for event in engine.process(events) {
+            if let TouchEvent::Activated {
+                x: Some(x),
+                y: Some(y),
+            } = event
+            {
+                println!("  {}", engine.tracker().debug_info());
+                points.push((x, y));
+            }
+        }
+    })?;
+
+    let zones = (!points.is_empty()).then(|| suggest_zones(&range, &points, margin));
+    match &zones {
+        Some(zones) => println!(
+            "Suggested zones: top={}%, bottom={}%, left={}%, right={}%",
+            zones.top, zones.bottom, zones.left, zones.right
+        ),
+        None => println!("No exclusion zones recorded, keeping the current ones"),
+    }
+    let zones = zones.or_else(|| config.touchpad_profile(&device).and_then(|p| p.zones));
+
+    let profile = TouchpadProfile {
+        name: device,
+        range: Some(range),
+        zones,
+    };
+    save(path, &profile, dry_run)
+}
+
+/// Enterが押されるまでタッチパッドのイベントを渡し続ける
+fn read_until_enter(
+    touchpad: &mut Device,
+    mut on_events: impl FnMut(&[evdev::InputEvent]),
+) -> anyhow::Result<()> {
+    let stdin = std::io::stdin();
+    loop {
+        let ready = wait_readable(&[touchpad.as_raw_fd(), stdin.as_raw_fd()], None)?;
+
+        if ready[0] {
+            let events: Vec<_> = touchpad.fetch_events()?.collect();
+            on_events(&events);
+        }
+
+        if ready[1] {
+            let mut line = String::new();
+            stdin.lock().read_line(&mut line)?;
+            return Ok(());
+        }
+    }
+}
+
+fn save(path: Option<PathBuf>, profile: &TouchpadProfile, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        println!("\n{}", update_touchpad_profile("", profile)?);
+        return Ok(());
+    }
+
+    let path =
+        path.ok_or_else(|| anyhow::anyhow!("Cannot determine the configuration file path"))?;
+    write_touchpad_profile(&path, profile)?;
+    println!(
+        "\nSaved calibration for \"{}\" to {}",
+        profile.name,
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! `ctl` サブコマンド: 制御ソケット経由でデーモンを操作
+
+use crate::Args;
+use clap::Subcommand;
+use way_thumbsense::control::{send_request, Request};
+use way_thumbsense::tracker::ExclusionZones;
+
+#[derive(Subcommand, Debug)]
+pub enum CtlAction {
+    /// 現在の状態を表示
+    Status,
+    /// ThumbSenseを有効化
+    Enable,
+    /// ThumbSenseを無効化（有効中のレイヤーは解放）
+    Disable,
+    /// 有効/無効を切り替え
+    Toggle,
+    /// 除外領域を変更
+    SetZones {
+        #[arg(long, default_value_t = 0.0)]
+        top: f32,
+        #[arg(long, default_value_t = 0.0)]
+        bottom: f32,
+        #[arg(long, default_value_t = 0.0)]
+        left: f32,
+        #[arg(long, default_value_t = 0.0)]
+        right: f32,
+    },
+    /// 設定ファイルを再読み込み
+    Reload,
+    /// 有効化・除外・手のひら・再接続の回数とトリガーを押していた時間
+    Metrics {
+        /// Prometheusのテキスト形式で出力
+        #[arg(long)]
+        prometheus: bool,
+    },
+}
+
+impl CtlAction {
+    fn request(&self) -> Request {
+        match *self {
+            CtlAction::Status => Request::Status,
+            CtlAction::Enable => Request::Enable,
+            CtlAction::Disable => Request::Disable,
+            CtlAction::Toggle => Request::Toggle,
+            CtlAction::SetZones {
+                top,
+                bottom,
+                left,
+                right,
+            } => Request::SetZones {
+                zones: ExclusionZones::new(top, bottom, left, right),
+            },
+            CtlAction::Reload => Request::Reload,
+            CtlAction::Metrics { .. } => Request::Metrics,
+        }
+    }
+}
+
+pub fn run(args: &Args, action: &CtlAction) -> anyhow::Result<()> {
+    let path = super::socket_path(args)?;
+    let response = send_request(&path, &action.request())
+        .map_err(|e| anyhow::anyhow!("Cannot talk to daemon at {}: {}", path.display(), e))?;
+
+    if !response.ok {
+        anyhow::bail!(response
+            .error
+            .unwrap_or_else(|| "request failed".to_string()));
+    }
+
+    if let CtlAction::Metrics { prometheus } = *action {
+        let metrics = response
+            .metrics
+            .ok_or_else(|| anyhow::anyhow!("daemon did not return metrics"))?;
+        if prometheus {
+            print!("{}", metrics.to_prometheus());
+        } else {
+            println!("{}", serde_json::to_string_pretty(&metrics)?);
+        }
+        return Ok(());
+    }
+
+    if let Some(status) = response.status {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,801 @@
+//! デーモン本体: タッチパッドを監視してレイヤーを切り替える
+
+use crate::Args;
+use anyhow::Context;
+use evdev::{AttributeSet, Device, EventType, InputEvent, Key};
+use log::{debug, error, info, trace, warn};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+use way_thumbsense::config::{BackendKind, Config};
+use way_thumbsense::control::{ControlServer, Request, Response, State, StateChange, Status};
+use way_thumbsense::core::{KeyCode, KeyEvent, OutputAction};
+use way_thumbsense::engine::{Engine, TouchEvent};
+use way_thumbsense::event_loop::{self, maybe, InputStream, ReadableFd};
+use way_thumbsense::hooks::{HookContext, HookRunner};
+use way_thumbsense::input::{get_touchpad_dimensions, Frame, TouchSnapshot, TouchpadDimensions};
+use way_thumbsense::latency;
+use way_thumbsense::metrics::{Metrics, MetricsServer};
+use way_thumbsense::output::{KanataBackend, KeydBackend, LayerBackend, VirtualDevice};
+use way_thumbsense::signals::{SignalPipe, SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+use way_thumbsense::tracker::{ExclusionZones, TouchTracker};
+use way_thumbsense::watch::{DirWatcher, FileWatcher};
+
+/// 切断されたタッチパッドを開き直す間隔（失敗するたびに倍にする）
+const RECONNECT_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_MAX: Duration = Duration::from_secs(5);
+
+/// 実行中のデーモンの状態
+///
+/// SIGHUPで再オープンするときは丸ごと作り直し、開けた場合だけ差し替える
+pub(super) struct Daemon<'a> {
+    args: &'a Args,
+    config: Config,
+    /// 切断されている間はNone
+    touchpad: Option<InputStream>,
+    /// 次にタッチパッドを開き直す時刻と、その次までの間隔
+    reconnect: Option<(tokio::time::Instant, Duration)>,
+    /// `[device] keyboard`（切断されたらNone。接続されたら開き直す）
+    keyboard: Option<InputStream>,
+    /// 押したまま出力しているキーと、それを離す出力（取りこぼしたときに離す）
+    held: Vec<(Key, OutputAction)>,
+    device: String,
+    dimensions: TouchpadDimensions,
+    engine: Engine,
+    backend: Box<dyn LayerBackend>,
+    hooks: HookRunner,
+    /// 再オープンをまたいで集計する
+    metrics: Arc<Mutex<Metrics>>,
+    /// 最後に購読者へ通知した状態
+    published: Option<State>,
+    /// 直近の設定再読み込みのエラー
+    config_error: Option<String>,
+    /// 次のループで抜ける理由（設定変更による再オープンなど）
+    pending_exit: Option<Exit>,
+}
+
+/// イベントループを抜けた理由
+pub(super) enum Exit {
+    /// 終了（SIGTERM/SIGINT）
+    Shutdown,
+    /// 設定とデバイスを開き直す（SIGHUP）
+    Reopen,
+}
+
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    info!("way-thumbsense starting...");
+
+    let config = super::load_config(args)?;
+
+    // シグナルはイベントループで処理する
+    let signals = SignalPipe::install(&[SIGHUP, SIGUSR1, SIGTERM, SIGINT])?;
+
+    // 制御ソケット（再オープンをまたいで維持）
+    let socket = super::socket_path(args)?;
+    let control = ControlServer::bind(&socket)
+        .with_context(|| format!("Cannot create control socket {}", socket.display()))?;
+    info!("Control socket: {}", control.path().display());
+
+    // 設定ファイルの変更を監視（ディレクトリがなければ監視しない）
+    let watcher =
+        Config::resolve_path(args.config.as_deref()).and_then(|path| FileWatcher::new(&path).ok());
+    if watcher.is_none() {
+        warn!("Configuration file is not watched");
+    }
+
+    // デバイスの接続（キーボードの再接続に使う）
+    let hotplug = DirWatcher::new(Path::new("/dev/input"), libc::IN_CREATE | libc::IN_ATTRIB)
+        .map_err(|e| warn!("Input devices are not watched for hotplug: {}", e))
+        .ok();
+
+    let metrics = Arc::new(Mutex::new(Metrics::new()));
+    let _metrics_server = match &config.metrics.socket {
+        Some(path) => {
+            let server = MetricsServer::bind(path, Arc::clone(&metrics))
+                .with_context(|| format!("Cannot create metrics socket {}", path.display()))?;
+            info!("Metrics socket: {}", server.path().display());
+            Some(server)
+        }
+        None => None,
+    };
+
+    let runtime = event_loop::runtime()?;
+    // デバイスをループに登録できるよう、開く前からランタイムに入っておく
+    let _runtime = runtime.enter();
+    // 起動時に開けなければ終了する（開き直しの失敗では終了しない）
+    let mut daemon = Daemon::open(args, config, true, Arc::clone(&metrics))?;
+    info!("Running... (Ctrl+C to exit)");
+    info!("Touch trackpad to activate the mousenav layer");
+    loop {
+        daemon.publish_state(&control);
+
+        let exit = runtime.block_on(daemon.run(
+            &control,
+            &signals,
+            watcher.as_ref(),
+            hotplug.as_ref(),
+            None,
+        ));
+
+        // 押しっぱなしのまま終了/再オープンしない
+        let events = daemon.engine.release();
+        daemon.dispatch(events, None);
+
+        match exit? {
+            Exit::Shutdown => break,
+            Exit::Reopen => {
+                let config = match super::reload_config(args) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        warn!("Keeping previous configuration: {}", e);
+                        daemon.config.clone()
+                    }
+                };
+                info!("Reopening devices...");
+                daemon = daemon.reopen(config);
+            }
+        }
+    }
+
+    info!("way-thumbsense stopped");
+    Ok(())
+}
+
+impl<'a> Daemon<'a> {
+    /// タッチパッドと出力先を開く
+    fn open(
+        args: &'a Args,
+        config: Config,
+        enabled: bool,
+        metrics: Arc<Mutex<Metrics>>,
+    ) -> anyhow::Result<Self> {
+        let devices = open_devices(&config, false, None)?;
+        Self::with_devices(args, config, devices, enabled, metrics, None)
+    }
+
+    /// 読み直した設定でデバイスと出力先を開き直す
+    ///
+    /// 切断中ならタッチパッドなしで開いて再接続を待つ。
+    /// 開けなければエラーを記録し、今の設定とデバイスのまま続ける
+    fn reopen(mut self, config: Config) -> Self {
+        // 同じキーボードを新しい設定でもgrabできるよう、いったん手放す
+        if let Some(keyboard) = &mut self.keyboard {
+            let _ = keyboard.device_mut().ungrab();
+        }
+        let enabled = self.engine.is_enabled();
+        let disconnected = self
+            .touchpad
+            .is_none()
+            .then(|| (self.device.clone(), self.dimensions));
+        let was_disconnected = disconnected.is_some();
+        let reopened = open_devices(&config, was_disconnected, Some(self.backend.as_ref()))
+            .and_then(|devices| {
+                let metrics = Arc::clone(&self.metrics);
+                Self::with_devices(self.args, config, devices, enabled, metrics, disconnected)
+            });
+        match reopened {
+            Ok(mut daemon) => {
+                // 実行中のフックを数え続けるよう、今のHookRunnerを引き継ぐ
+                std::mem::swap(&mut daemon.hooks, &mut self.hooks);
+                let context = hook_context(&daemon.device, daemon.dimensions);
+                daemon
+                    .hooks
+                    .reconfigure(daemon.config.hooks.clone(), context);
+                // 切断中に別のタッチパッドがつながった場合もここで数える
+                if was_disconnected && daemon.touchpad.is_some() {
+                    daemon.metrics.lock().unwrap().record_reconnect();
+                }
+                daemon
+            }
+            Err(e) => {
+                error!(
+                    "Devices not reopened, keeping previous configuration: {:#}",
+                    e
+                );
+                if let Some(keyboard) = &mut self.keyboard {
+                    if let Err(e) = keyboard.device_mut().grab() {
+                        warn!("Cannot grab the keyboard again: {}", e);
+                    }
+                }
+                self.config_error = Some(format!("{:#}", e));
+                self
+            }
+        }
+    }
+
+    /// 開いたデバイスと出力先で作る（bench-latencyは仮想デバイスを渡す）
+    ///
+    /// タッチパッドがなければ `disconnected` の名前と寸法で作り、再接続を待つ
+    pub(super) fn with_devices(
+        args: &'a Args,
+        config: Config,
+        devices: Devices,
+        enabled: bool,
+        metrics: Arc<Mutex<Metrics>>,
+        disconnected: Option<(String, TouchpadDimensions)>,
+    ) -> anyhow::Result<Self> {
+        let (device, dimensions) = match &devices.touchpad {
+            Some(touchpad) => {
+                let device = touchpad.name().unwrap_or("unknown").to_string();
+                // タッチパッドの寸法を取得
+                // キャリブレーション済みならその範囲を使う
+                let dimensions = get_touchpad_dimensions(touchpad)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to get touchpad dimensions"))?;
+                let dimensions = config.dimensions_for(&device, dimensions);
+                (device, dimensions)
+            }
+            None => disconnected.context("Touchpad is not connected")?,
+        };
+        info!(
+            "Touchpad dimensions: X({} to {}), Y({} to {})",
+            dimensions.min_x, dimensions.max_x, dimensions.min_y, dimensions.max_y
+        );
+        let zones = config.zones_for(&device);
+        log_zones(&zones);
+
+        // 指を置いたまま開いた場合に備えて、読み始める前の状態を取っておく
+        let snapshot = devices
+            .touchpad
+            .as_ref()
+            .map(TouchSnapshot::read)
+            .transpose()
+            .context("Cannot read touchpad state")?;
+
+        let touchpad = devices.touchpad.map(InputStream::new).transpose()?;
+        let keyboard = devices.keyboard.map(InputStream::new).transpose()?;
+        let reconnect = touchpad
+            .is_none()
+            .then(|| (tokio::time::Instant::now() + RECONNECT_MIN, RECONNECT_MIN));
+
+        let mut engine = Engine::new(TouchTracker::new(dimensions, zones));
+        engine.set_mappings(config.mappings());
+        engine.set_enabled(enabled);
+
+        let mut daemon = Self {
+            args,
+            engine,
+            hooks: HookRunner::new(config.hooks.clone(), hook_context(&device, dimensions)),
+            metrics,
+            config,
+            touchpad,
+            reconnect,
+            keyboard,
+            held: Vec::new(),
+            device,
+            dimensions,
+            backend: devices.backend,
+            published: None,
+            config_error: None,
+            pending_exit: None,
+        };
+        if let Some(snapshot) = snapshot {
+            daemon.sync(&snapshot);
+        }
+        Ok(daemon)
+    }
+
+    /// デバイスの現在の状態からタッチ状態とレイヤーを合わせる
+    fn sync(&mut self, snapshot: &TouchSnapshot) {
+        if snapshot.touching {
+            info!(
+                "Touchpad is already touched ({} finger(s), {} contact(s))",
+                snapshot.finger_count(),
+                snapshot.contacts.len()
+            );
+        }
+        let touch_events = self.engine.resync(snapshot);
+        self.dispatch(touch_events, None);
+    }
+
+    /// タッチパッド・キーボード・制御ソケット・シグナル・設定ファイル・デバイスの接続を待ち受ける
+    ///
+    /// `shutdown` に通知されるとシグナルと同じく終了する（別スレッドから止める場合）
+    pub(super) async fn run(
+        &mut self,
+        control: &ControlServer,
+        signals: &SignalPipe,
+        watcher: Option<&FileWatcher>,
+        hotplug: Option<&DirWatcher>,
+        shutdown: Option<&Notify>,
+    ) -> anyhow::Result<Exit> {
+        let control_fd = ReadableFd::new(control.wake_fd())?;
+        let signal_fd = ReadableFd::new(signals.fd())?;
+        let watch_fd = watcher.map(|w| ReadableFd::new(w.fd())).transpose()?;
+        let hotplug_fd = hotplug.map(|w| ReadableFd::new(w.fd())).transpose()?;
+
+        loop {
+            tokio::select! {
+                frame = maybe(self.touchpad.as_mut(), InputStream::next_frame) => match frame {
+                    Ok(Frame::Events(events)) => {
+                        let touch_events = self.engine.process_frame(&events);
+                        self.dispatch(touch_events, latency::touch_timestamp(&events));
+                    }
+                    Ok(Frame::Dropped) => {
+                        warn!("Touchpad events were dropped, resynchronizing from device state");
+                        let snapshot = self.touchpad.as_ref().map(|t| TouchSnapshot::read(t.device()));
+                        match snapshot {
+                            Some(Ok(snapshot)) => {
+                                let touch_events = self.engine.resync(&snapshot);
+                                self.dispatch(touch_events, None);
+                            }
+                            Some(Err(error)) => self.disconnected(error),
+                            None => {}
+                        }
+                    }
+                    Err(error) => self.disconnected(error),
+                },
+                _ = maybe(self.reconnect.map(|(at, _)| at), tokio::time::sleep_until) => {
+                    self.retry_reconnect();
+                }
+                frame = maybe(self.keyboard.as_mut(), InputStream::next_frame) => match frame {
+                    Ok(Frame::Events(events)) => self.handle_keys(&events),
+                    Ok(Frame::Dropped) => {
+                        warn!("Keyboard events were dropped, releasing keys no longer held");
+                        let down = self.keyboard.as_ref().map(|k| k.device().get_key_state());
+                        self.release_keys(&down.and_then(Result::ok).unwrap_or_default());
+                    }
+                    Err(error) => {
+                        warn!("Keyboard disconnected: {}", error);
+                        self.keyboard = None;
+                        self.release_keys(&AttributeSet::new());
+                    }
+                },
+                ready = control_fd.readable() => {
+                    ready?;
+                    for pending in control.drain() {
+                        let response = self.handle_request(pending.request.clone());
+                        pending.reply(response);
+                    }
+                }
+                ready = signal_fd.readable() => {
+                    ready?;
+                    for signo in signals.drain() {
+                        match signo {
+                            SIGUSR1 => self.set_enabled(!self.engine.is_enabled())?,
+                            SIGHUP => return Ok(Exit::Reopen),
+                            _ => {
+                                info!("Received signal {}, shutting down", signo);
+                                return Ok(Exit::Shutdown);
+                            }
+                        }
+                    }
+                }
+                _ = maybe(shutdown, Notify::notified) => {
+                    info!("Shutdown requested");
+                    return Ok(Exit::Shutdown);
+                }
+                ready = maybe(watch_fd.as_ref(), ReadableFd::readable) => {
+                    ready?;
+                    if watcher.is_some_and(FileWatcher::changed) {
+                        info!("Configuration file changed");
+                        // エラーは reload() 内で報告済み
+                        let _ = self.reload();
+                    }
+                }
+                ready = maybe(hotplug_fd.as_ref(), ReadableFd::readable) => {
+                    ready?;
+                    let added = hotplug.map(DirWatcher::drain).unwrap_or_default();
+                    self.devices_added(&added);
+                }
+            }
+
+            if let Some(exit) = self.pending_exit.take() {
+                return Ok(exit);
+            }
+
+            self.publish_state(control);
+        }
+    }
+
+    /// タッチパッドを読めなくなった（切断など）
+    ///
+    /// レイヤーを解放し、つながるまで間隔を空けながら開き直す
+    fn disconnected(&mut self, error: std::io::Error) {
+        error!("Touchpad disconnected: {}", error);
+        self.touchpad = None;
+        let events = self.engine.release();
+        self.dispatch(events, None);
+        self.reconnect = Some((tokio::time::Instant::now() + RECONNECT_MIN, RECONNECT_MIN));
+    }
+
+    /// 予定の時刻になったので開き直す（失敗したら次の間隔を延ばす）
+    fn retry_reconnect(&mut self) {
+        let Some((_, delay)) = self.reconnect.take() else {
+            return;
+        };
+        if let Err(e) = self.reopen_touchpad() {
+            let delay = (delay * 2).min(RECONNECT_MAX);
+            debug!("Touchpad not reopened, retrying in {:?}: {}", delay, e);
+            self.reconnect = Some((tokio::time::Instant::now() + delay, delay));
+        }
+    }
+
+    /// 切断されたタッチパッドを開き、その時点の状態から再開する
+    ///
+    /// 別のタッチパッドだった場合は寸法や除外領域が変わるので全体を開き直す
+    fn reopen_touchpad(&mut self) -> anyhow::Result<()> {
+        let touchpad = super::open_touchpad(&self.config)?;
+        let device = touchpad.name().unwrap_or("unknown").to_string();
+        let dimensions = get_touchpad_dimensions(&touchpad)
+            .map(|d| self.config.dimensions_for(&device, d))
+            .ok_or_else(|| anyhow::anyhow!("Failed to get touchpad dimensions"))?;
+        if device != self.device || dimensions != self.dimensions {
+            info!(
+                "Another touchpad was connected ({}), reopening devices",
+                device
+            );
+            self.reconnect = None;
+            self.pending_exit = Some(Exit::Reopen);
+            return Ok(());
+        }
+
+        let snapshot = TouchSnapshot::read(&touchpad).context("Cannot read touchpad state")?;
+        self.touchpad = Some(InputStream::new(touchpad)?);
+        self.reconnect = None;
+        self.metrics.lock().unwrap().record_reconnect();
+        info!("Touchpad reconnected: {}", device);
+        self.sync(&snapshot);
+        Ok(())
+    }
+
+    /// `/dev/input` にノードが現れた（切断されたタッチパッド・キーボードを開き直す）
+    fn devices_added(&mut self, names: &[OsString]) {
+        let nodes = names
+            .iter()
+            .filter(|name| name.to_string_lossy().starts_with("event"));
+        if nodes.count() == 0 {
+            return;
+        }
+        if self.touchpad.is_none() {
+            // 予定の時刻を待たずに試す（失敗しても間隔は延ばさない）
+            if let Err(e) = self.reopen_touchpad() {
+                debug!("Touchpad not reopened: {}", e);
+            }
+        }
+        if self.keyboard.is_some() || self.config.device.keyboard.is_none() {
+            return;
+        }
+        match super::open_keyboard(&self.config, Some(self.backend.as_ref())) {
+            Ok(Some(keyboard)) => {
+                info!(
+                    "Keyboard reconnected: {}",
+                    keyboard.name().unwrap_or("unknown")
+                );
+                match InputStream::new(keyboard) {
+                    Ok(keyboard) => self.keyboard = Some(keyboard),
+                    Err(e) => error!("Cannot read keyboard: {}", e),
+                }
+            }
+            Ok(None) => {}
+            Err(e) => debug!("Keyboard not reopened: {}", e),
+        }
+    }
+
+    /// キーボードのフレームをマッピングに従って出力する
+    fn handle_keys(&mut self, events: &[InputEvent]) {
+        for ev in events.iter().filter(|ev| ev.event_type() == EventType::KEY) {
+            let code = KeyCode(ev.code());
+            let action = match ev.value() {
+                0 => self.engine.map_key(KeyEvent::Release(code)),
+                1 => self.engine.map_key(KeyEvent::Press(code)),
+                // オートリピートはクリックにしない
+                _ => match self.engine.map_key(KeyEvent::Press(code)) {
+                    action @ OutputAction::PassThrough(_) => action,
+                    _ => continue,
+                },
+            };
+            let key = Key::new(ev.code());
+            if let Err(e) = self.backend.send_key(action, key, ev.value()) {
+                error!("Cannot send key: {}", e);
+                continue;
+            }
+            match action {
+                OutputAction::MouseClick(button) => {
+                    let latency = latency::since(ev.timestamp());
+                    self.metrics.lock().unwrap().record_key_latency(latency);
+                    trace!("Key to click: {:?}", latency);
+                    self.held.push((key, OutputAction::MouseRelease(button)));
+                }
+                OutputAction::MouseRelease(_) => {
+                    let latency = latency::since(ev.timestamp());
+                    self.metrics.lock().unwrap().record_key_latency(latency);
+                    self.held.retain(|(held, _)| *held != key);
+                }
+                OutputAction::PassThrough(KeyEvent::Press(code)) if ev.value() == 1 => {
+                    let release = OutputAction::PassThrough(KeyEvent::Release(code));
+                    self.held.push((key, release));
+                }
+                OutputAction::PassThrough(KeyEvent::Release(_)) => {
+                    self.held.retain(|(held, _)| *held != key);
+                }
+                OutputAction::PassThrough(_) => {}
+            }
+        }
+    }
+
+    /// 押したまま出力しているキーのうち、`down` にないものを離す
+    ///
+    /// SYN_DROPPEDや切断で離す変化を取りこぼしても、クリックやキーが押されたままにならないようにする
+    fn release_keys(&mut self, down: &AttributeSet<Key>) {
+        let (held, released): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|(key, _)| down.contains(*key));
+        self.held = held;
+        for (key, action) in released {
+            debug!("Releasing a key whose release was lost");
+            if let Err(e) = self.backend.send_key(action, key, 0) {
+                error!("Cannot release key: {}", e);
+            }
+        }
+    }
+
+    /// エンジンの状態変化を出力先とフックへ伝える
+    ///
+    /// `touched_at` はBTN_TOUCHのカーネルのタイムスタンプ（出力までの遅延の計測用）
+    ///
+    /// 出力先のエラー（keydが再起動中など）ではデーモンを止めず、ログに残して続ける
+    fn dispatch(&mut self, events: Vec<TouchEvent>, touched_at: Option<SystemTime>) {
+        for event in events {
+            self.metrics.lock().unwrap().observe(&event, Instant::now());
+            match event {
+                TouchEvent::Activated { .. } => {
+                    // 除外領域外でのタッチ → レイヤー有効化
+                    debug!("[Touch] {} -> activate", self.engine.tracker().debug_info());
+                    match self.backend.activate() {
+                        Ok(()) => self.record_latency(touched_at),
+                        Err(e) => error!("Cannot activate layer: {}", e),
+                    }
+                }
+                TouchEvent::Excluded { edge, .. } => {
+                    debug!(
+                        "[Touch] {} -> not activated (in {} exclusion zone)",
+                        self.engine.tracker().debug_info(),
+                        edge.as_str()
+                    );
+                }
+                TouchEvent::PalmRejected { .. } => {
+                    debug!(
+                        "[Touch] {} -> not activated (palm)",
+                        self.engine.tracker().debug_info()
+                    );
+                }
+                TouchEvent::Deactivated => {
+                    // タッチ終了 → レイヤー無効化
+                    debug!("[Touch] -> deactivate");
+                    match self.backend.deactivate() {
+                        Ok(()) => self.record_latency(touched_at),
+                        Err(e) => error!("Cannot deactivate layer: {}", e),
+                    }
+                }
+            }
+            self.hooks.fire(&event);
+        }
+    }
+
+    fn record_latency(&self, touched_at: Option<SystemTime>) {
+        if let Some(at) = touched_at {
+            let latency = latency::since(at);
+            self.metrics.lock().unwrap().record_touch_latency(latency);
+            trace!("Touch to output: {:?}", latency);
+        }
+    }
+
+    /// 状態が変わっていれば購読者へ通知
+    fn publish_state(&mut self, control: &ControlServer) {
+        let state = self.status().state();
+        if self.published == Some(state) {
+            return;
+        }
+        self.published = Some(state);
+        control.publish(StateChange {
+            state,
+            device: self.device.clone(),
+        });
+    }
+
+    /// 制御ソケットからのリクエストを処理
+    fn handle_request(&mut self, request: Request) -> Response {
+        debug!("[Control] {:?}", request);
+
+        let result = match request {
+            Request::Status => Ok(()),
+            Request::Enable => self.set_enabled(true),
+            Request::Disable => self.set_enabled(false),
+            Request::Toggle => self.set_enabled(!self.engine.is_enabled()),
+            Request::SetZones { zones } => zones
+                .validate()
+                .map(|()| self.engine.set_exclusion_zones(zones))
+                .map_err(anyhow::Error::msg),
+            Request::Reload => self.reload(),
+            Request::Metrics => {
+                let metrics = self.metrics.lock().unwrap().snapshot();
+                return Response::ok(self.status()).with_metrics(metrics);
+            }
+            // 購読はControlServer側で処理される
+            Request::Subscribe => Err(anyhow::anyhow!("subscribe is handled by the server")),
+        };
+
+        match result {
+            Ok(()) => Response::ok(self.status()),
+            Err(e) => Response::error(e.to_string()),
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        info!(
+            "ThumbSense {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        let events = self.engine.set_enabled(enabled);
+        self.dispatch(events, None);
+        Ok(())
+    }
+
+    /// 設定ファイルを読み直して実行中の設定を差し替える
+    ///
+    /// 新しい設定が不正なら現在の設定のまま、エラーを記録して返す
+    fn reload(&mut self) -> anyhow::Result<()> {
+        let config = match super::reload_config(self.args) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Configuration not reloaded: {}", e);
+                self.config_error = Some(e.to_string());
+                return Err(e);
+            }
+        };
+        self.config_error = None;
+
+        if self.config.requires_reopen(&config) {
+            // デバイス・出力先の設定が変わったので開き直す
+            info!("Configuration changed, reopening devices");
+            self.pending_exit = Some(Exit::Reopen);
+            return Ok(());
+        }
+
+        // 除外領域・マッピング・フックをまとめて差し替える
+        self.engine
+            .set_exclusion_zones(config.zones_for(&self.device));
+        self.engine.set_mappings(config.mappings());
+        self.hooks.reconfigure(
+            config.hooks.clone(),
+            hook_context(&self.device, self.dimensions),
+        );
+        self.config = config;
+        info!("Configuration reloaded");
+        log_zones(self.engine.tracker().exclusion_zones());
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            enabled: self.engine.is_enabled(),
+            active: self.engine.is_active(),
+            touching: self.engine.is_touching(),
+            zones: *self.engine.tracker().exclusion_zones(),
+            device: self.device.clone(),
+            backend: format!("{:?}", self.config.backend.kind).to_lowercase(),
+            config_error: self.config_error.clone(),
+            disconnected: self.touchpad.is_none(),
+        }
+    }
+}
+
+/// デーモンが読むデバイスと出力先
+pub(super) struct Devices {
+    /// 開き直すときに切断されていればNone
+    pub touchpad: Option<Device>,
+    pub keyboard: Option<Device>,
+    pub backend: Box<dyn LayerBackend>,
+}
+
+/// 設定のタッチパッド・キーボード・出力先を開く
+///
+/// `allow_missing_touchpad` なら、タッチパッドが見つからなくてもNoneで続ける。
+/// 開き直すときは今の出力先 `own` の仮想デバイスをキーボードとして選ばない
+fn open_devices(
+    config: &Config,
+    allow_missing_touchpad: bool,
+    own: Option<&dyn LayerBackend>,
+) -> anyhow::Result<Devices> {
+    // タッチパッドを検出
+    let touchpad = match super::open_touchpad(config) {
+        Ok(touchpad) => {
+            info!("Touchpad: {}", touchpad.name().unwrap_or("unknown"));
+            Some(touchpad)
+        }
+        Err(e) if allow_missing_touchpad => {
+            warn!("Touchpad not found, waiting for it: {:#}", e);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    // 仮想キーボードを作る前に開く
+    let keyboard = super::open_keyboard(config, own)?;
+    if let Some(keyboard) = &keyboard {
+        info!("Keyboard: {}", keyboard.name().unwrap_or("unknown"));
+    }
+
+    // レイヤー切り替えの出力先を作成
+    let backend = create_backend(config)?;
+
+    Ok(Devices {
+        touchpad,
+        keyboard,
+        backend,
+    })
+}
+
+fn hook_context(device: &str, dimensions: TouchpadDimensions) -> HookContext {
+    HookContext {
+        device: device.to_string(),
+        dimensions,
+    }
+}
+
+fn log_zones(zones: &ExclusionZones) {
+    if zones.is_enabled() {
+        info!(
+            "Exclusion zones: top={}%, bottom={}%, left={}%, right={}%",
+            zones.top, zones.bottom, zones.left, zones.right
+        );
+    } else {
+        info!("Exclusion zones: disabled");
+    }
+}
+
+/// 設定に応じたレイヤー切り替えの出力先を作成
+pub fn create_backend(config: &Config) -> anyhow::Result<Box<dyn LayerBackend>> {
+    match config.backend.kind {
+        BackendKind::Uinput => {
+            let vdev = VirtualDevice::new(config)?;
+            let identity = &config.virtual_device.keyboard;
+            info!(
+                "Virtual keyboard created: {} ({}:{:04x}:{:04x}) at {}",
+                identity.name,
+                identity.bus,
+                identity.vendor,
+                identity.product,
+                vdev.keyboard_node().devnode.display()
+            );
+            debug!(
+                "Virtual keyboard syspath: {}",
+                vdev.keyboard_node().syspath.display()
+            );
+            debug!(
+                "Virtual mouse: {} ({})",
+                vdev.mouse_node().devnode.display(),
+                vdev.mouse_node().syspath.display()
+            );
+            info!("Trigger key: {:?}", config.trigger.key);
+            Ok(Box::new(vdev))
+        }
+        BackendKind::Keyd => {
+            let keyd = &config.backend.keyd;
+            let backend = KeydBackend::new(keyd);
+            backend.check().map_err(|e| {
+                anyhow::anyhow!("Cannot connect to keyd at {}: {}", keyd.socket.display(), e)
+            })?;
+            info!(
+                "keyd backend: {} (layer: {})",
+                keyd.socket.display(),
+                keyd.layer
+            );
+            Ok(Box::new(backend))
+        }
+        BackendKind::Kanata => {
+            let kanata = &config.backend.kanata;
+            let mut backend = KanataBackend::new(kanata);
+            backend.check().map_err(|e| {
+                anyhow::anyhow!("Cannot connect to kanata at {}: {}", kanata.address, e)
+            })?;
+            info!(
+                "kanata backend: {} (layer: {})",
+                kanata.address, kanata.layer
+            );
+            Ok(Box::new(backend))
+        }
+    }
+}
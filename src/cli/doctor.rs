@@ -0,0 +1,391 @@
+//! doctor サブコマンド: READMEのセットアップが済んでいるかを確認する
+
+use crate::Args;
+use std::ffi::{CStr, CString};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+use way_thumbsense::config::{BackendKind, Config};
+use way_thumbsense::doctor::{
+    defines_layer, inspect_keyd_config, read_keyd_configs, Check, Status, KEYD_CONFIG_DIR,
+};
+use way_thumbsense::input::inventory::{scan, Access, DeviceReport, Role};
+use way_thumbsense::input::SysRoot;
+use way_thumbsense::output::KeydBackend;
+use way_thumbsense::snippet::keyd_key_name;
+
+/// uinputのudevルールを探す場所
+const UDEV_RULE_DIRS: &[&str] = &[
+    "/etc/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+    "/lib/udev/rules.d",
+];
+
+const INPUT_GROUP_HINT: &str = "sudo usermod -aG input $USER, then log out and back in";
+
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    let mut checks = Vec::new();
+
+    // 設定が読めなくても残りの項目は調べる
+    let config = match super::load_config(args) {
+        Ok(config) => {
+            checks.push(Check::pass("config", "configuration is valid"));
+            config
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "config",
+                e.to_string(),
+                "fix the configuration file; defaults are used for the remaining checks",
+            ));
+            Config::default()
+        }
+    };
+
+    checks.push(check_input_group());
+
+    let reports = scan(
+        Path::new("/dev/input"),
+        &SysRoot::default(),
+        config.device.touchpad.as_deref(),
+    );
+    checks.push(check_permissions(&reports));
+    let reports = reports.unwrap_or_default();
+    checks.push(check_selected(&reports, Role::Touchpad));
+    checks.push(check_selected(&reports, Role::Keyboard));
+
+    let uses_trigger_key = config.backend.kind == BackendKind::Uinput;
+    if uses_trigger_key {
+        checks.push(check_uinput());
+        checks.push(check_udev_rule());
+    } else {
+        checks.push(Check::skip("uinput", "not used by this backend"));
+    }
+
+    match config.backend.kind {
+        BackendKind::Uinput | BackendKind::Keyd => checks.push(check_keyd_running(&config)),
+        BackendKind::Kanata => checks.push(check_kanata(&config)),
+    }
+    checks.push(check_keyd_config(&config, Path::new(KEYD_CONFIG_DIR)));
+
+    for check in &checks {
+        print_check(check);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} checks failed", failed, checks.len());
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+fn print_check(check: &Check) {
+    let label = match check.status {
+        Status::Pass => " ok ",
+        Status::Warn => "warn",
+        Status::Fail => "FAIL",
+        Status::Skip => "skip",
+    };
+    println!("[{}] {}: {}", label, check.name, check.message);
+    if let Some(hint) = &check.hint {
+        println!("       hint: {}", hint);
+    }
+}
+
+/// 実行中のプロセスがinputグループに入っているか
+fn check_input_group() -> Check {
+    const NAME: &str = "input group";
+
+    if unsafe { libc::geteuid() } == 0 {
+        return Check::pass(NAME, "running as root");
+    }
+
+    let group = CString::new("input").unwrap();
+    let entry = unsafe { libc::getgrnam(group.as_ptr()) };
+    if entry.is_null() {
+        return Check::fail(
+            NAME,
+            "the input group does not exist",
+            "create it with sudo groupadd input",
+        );
+    }
+    let gid = unsafe { (*entry).gr_gid };
+    let members = unsafe { group_members(entry) };
+
+    if process_groups().contains(&gid) {
+        return Check::pass(NAME, "the current session is in the input group");
+    }
+
+    // /etc/group にはあるがログインし直していない
+    let user = std::env::var("USER").unwrap_or_default();
+    if members.contains(&user) {
+        Check::fail(
+            NAME,
+            format!(
+                "{} was added to the input group but this session predates it",
+                user
+            ),
+            "log out and back in (or reboot)",
+        )
+    } else {
+        Check::fail(NAME, "not a member of the input group", INPUT_GROUP_HINT)
+    }
+}
+
+/// getgrnam の gr_mem（NULL終端の文字列配列）
+unsafe fn group_members(entry: *const libc::group) -> Vec<String> {
+    let mut members = Vec::new();
+    let mut member = (*entry).gr_mem;
+    while !member.is_null() && !(*member).is_null() {
+        members.push(CStr::from_ptr(*member).to_string_lossy().into_owned());
+        member = member.add(1);
+    }
+    members
+}
+
+fn process_groups() -> Vec<libc::gid_t> {
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0; count.max(0) as usize];
+    let count = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups.push(unsafe { libc::getegid() });
+    groups
+}
+
+fn check_permissions(reports: &io::Result<Vec<DeviceReport>>) -> Check {
+    const NAME: &str = "device permissions";
+
+    let reports = match reports {
+        Ok(reports) => reports,
+        Err(e) => {
+            return Check::fail(
+                NAME,
+                format!("cannot read /dev/input: {}", e),
+                "run on the machine with the touchpad (not inside a container)",
+            )
+        }
+    };
+
+    let denied = reports
+        .iter()
+        .filter(|r| r.access == Access::PermissionDenied)
+        .count();
+    if denied == 0 {
+        Check::pass(NAME, format!("{} input devices readable", reports.len()))
+    } else {
+        Check::fail(
+            NAME,
+            format!(
+                "{} of {} input devices are not readable",
+                denied,
+                reports.len()
+            ),
+            INPUT_GROUP_HINT,
+        )
+    }
+}
+
+/// 自動検出（または設定の名前指定）でデバイスが選ばれるか
+fn check_selected(reports: &[DeviceReport], role: Role) -> Check {
+    let name = role.as_str();
+    let selected = reports
+        .iter()
+        .find(|r| r.selection.iter().any(|s| s.role == role && s.selected));
+
+    match (selected, role) {
+        (Some(report), _) => Check::pass(
+            name,
+            format!(
+                "{} ({})",
+                report.name.as_deref().unwrap_or("unknown"),
+                report.path.display()
+            ),
+        ),
+        (None, Role::Touchpad) => Check::fail(
+            name,
+            "no touchpad found",
+            "run list-devices to see why each device was rejected, or set [device] touchpad",
+        ),
+        // キーボードは record でしか使わない
+        (None, Role::Keyboard) => Check::warn(
+            name,
+            "no keyboard found",
+            "record needs a keyboard with KEY_J and KEY_A; run list-devices",
+        ),
+    }
+}
+
+fn check_uinput() -> Check {
+    const NAME: &str = "uinput";
+
+    match OpenOptions::new().write(true).open("/dev/uinput") {
+        Ok(_) => Check::pass(NAME, "/dev/uinput is writable"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Check::fail(
+            NAME,
+            "/dev/uinput does not exist",
+            "load the module with sudo modprobe uinput",
+        ),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Check::fail(
+            NAME,
+            "/dev/uinput is not writable",
+            "install the udev rule from the README and join the input group",
+        ),
+        Err(e) => Check::fail(NAME, format!("cannot open /dev/uinput: {}", e), "see dmesg"),
+    }
+}
+
+/// `KERNEL=="uinput"` を含むudevルールがあるか
+fn check_udev_rule() -> Check {
+    const NAME: &str = "udev rule";
+
+    let rule = UDEV_RULE_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| {
+            fs::read_to_string(path).is_ok_and(|text| {
+                text.lines()
+                    .any(|l| !l.trim_start().starts_with('#') && l.contains("KERNEL==\"uinput\""))
+            })
+        });
+
+    match rule {
+        Some(path) => Check::pass(NAME, format!("uinput rule in {}", path.display())),
+        None => Check::warn(
+            NAME,
+            "no udev rule for uinput",
+            "create /etc/udev/rules.d/99-uinput.rules with KERNEL==\"uinput\", MODE=\"0660\", GROUP=\"input\"",
+        ),
+    }
+}
+
+fn check_keyd_running(config: &Config) -> Check {
+    const NAME: &str = "keyd";
+
+    let socket = &config.backend.keyd.socket;
+    match KeydBackend::new(&config.backend.keyd).check() {
+        Ok(()) => Check::pass(NAME, format!("keyd is listening on {}", socket.display())),
+        Err(e) => {
+            let message = format!("cannot connect to {}: {}", socket.display(), e);
+            let hint = "start keyd with sudo systemctl enable --now keyd";
+            // uinputバックエンドはkeyd以外のリマッパーでも使える
+            if config.backend.kind == BackendKind::Keyd {
+                Check::fail(NAME, message, hint)
+            } else {
+                Check::warn(NAME, message, hint)
+            }
+        }
+    }
+}
+
+fn check_kanata(config: &Config) -> Check {
+    const NAME: &str = "kanata";
+
+    let address = &config.backend.kanata.address;
+    let connected = address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .map(|addr| TcpStream::connect_timeout(&addr, Duration::from_secs(1)));
+
+    match connected {
+        Some(Ok(_)) => Check::pass(NAME, format!("kanata is listening on {}", address)),
+        Some(Err(e)) => Check::fail(
+            NAME,
+            format!("cannot connect to {}: {}", address, e),
+            format!("start kanata with --port {}", address),
+        ),
+        None => Check::fail(
+            NAME,
+            format!("invalid address {}", address),
+            "fix [backend.kanata] address",
+        ),
+    }
+}
+
+/// keydの設定でトリガーキー（keydバックエンドならレイヤー）が使えるか
+fn check_keyd_config(config: &Config, dir: &Path) -> Check {
+    const NAME: &str = "keyd config";
+
+    if config.backend.kind == BackendKind::Kanata {
+        return Check::skip(NAME, "not used by this backend");
+    }
+
+    let files = match read_keyd_configs(dir) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) | Err(_) => {
+            return Check::fail(
+                NAME,
+                format!("no configuration in {}", dir.display()),
+                "generate one with way-thumbsense gen-config --target keyd",
+            )
+        }
+    };
+
+    if config.backend.kind == BackendKind::Keyd {
+        let layer = &config.backend.keyd.layer;
+        return match files.iter().find(|(_, text)| defines_layer(text, layer)) {
+            Some((path, _)) => Check::pass(NAME, format!("[{}] in {}", layer, path.display())),
+            None => Check::fail(
+                NAME,
+                format!("no [{}] layer in {}", layer, dir.display()),
+                format!("define [{}] or change [backend.keyd] layer", layer),
+            ),
+        };
+    }
+
+    let key = keyd_key_name(config.trigger.key);
+    let identity = &config.virtual_device.keyboard;
+    let reports: Vec<_> = files
+        .iter()
+        .map(|(path, text)| {
+            (
+                path,
+                inspect_keyd_config(text, &key, identity.vendor, identity.product),
+            )
+        })
+        .collect();
+
+    if let Some((path, report)) = reports.iter().find(|(_, r)| r.is_ok()) {
+        let (section, layer) = report.binding.as_ref().unwrap();
+        return Check::pass(
+            NAME,
+            format!("{} = {} in [{}] of {}", key, layer, section, path.display()),
+        );
+    }
+
+    // 一番近い設定ファイルについて足りないものを示す
+    let vendor_product = format!("{:04x}:{:04x}", identity.vendor, identity.product);
+    match reports.iter().find(|(_, r)| r.binding.is_some()) {
+        Some((path, report)) if !report.layer_defined => {
+            let (_, layer) = report.binding.as_ref().unwrap();
+            Check::fail(
+                NAME,
+                format!(
+                    "{} binds {} to undefined layer {}",
+                    path.display(),
+                    key,
+                    layer
+                ),
+                format!("add a [{}] section with the mouse button mappings", layer),
+            )
+        }
+        Some((path, _)) => Check::fail(
+            NAME,
+            format!(
+                "[ids] in {} does not match the virtual keyboard",
+                path.display()
+            ),
+            format!("add * or {} to [ids]", vendor_product),
+        ),
+        None => Check::fail(
+            NAME,
+            format!("{} is not bound to a layer in {}", key, dir.display()),
+            format!("add {} = layer(mousenav) to [main]", key),
+        ),
+    }
+}
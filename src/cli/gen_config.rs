@@ -0,0 +1,10 @@
+//! gen-config サブコマンド: keyd / kanata の設定断片を出力する
+
+use crate::Args;
+use way_thumbsense::snippet::{generate, Target};
+
+pub fn run(args: &Args, target: Target) -> anyhow::Result<()> {
+    let config = super::load_config(args)?;
+    print!("{}", generate(&config, target));
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! list-devices サブコマンド: 入力デバイスの一覧と自動検出の結果を表示する
+
+use crate::Args;
+use anyhow::Context;
+use std::path::Path;
+use way_thumbsense::config::Config;
+use way_thumbsense::input::inventory::{any_permission_denied, scan, Access, DeviceReport};
+use way_thumbsense::input::SysRoot;
+
+/// 表示するキー名の上限（キーボードは数百あるため）
+const MAX_KEYS_SHOWN: usize = 12;
+
+pub fn run(args: &Args, json: bool) -> anyhow::Result<()> {
+    // 設定が読めなくても一覧は出す
+    let config = super::load_config(args).unwrap_or_else(|e| {
+        eprintln!("Ignoring configuration: {}", e);
+        Config::default()
+    });
+    let reports = scan(
+        Path::new("/dev/input"),
+        &SysRoot::default(),
+        config.device.touchpad.as_deref(),
+    )
+    .context("Cannot read /dev/input")?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
+    for report in &reports {
+        print_report(report);
+    }
+    if any_permission_denied(&reports) {
+        println!("Some devices could not be opened; add yourself to the input group (see README)");
+    }
+    Ok(())
+}
+
+fn print_report(report: &DeviceReport) {
+    let path = report.path.display();
+    match report.access {
+        Access::Ok => {}
+        Access::PermissionDenied => {
+            println!("{}: permission denied\n", path);
+            return;
+        }
+        Access::Error => {
+            println!(
+                "{}: {}\n",
+                path,
+                report.error.as_deref().unwrap_or("cannot open")
+            );
+            return;
+        }
+    }
+
+    let class = report.class.map_or("", |c| c.as_str());
+    println!(
+        "{}: \"{}\" [{}]",
+        path,
+        report.name.as_deref().unwrap_or("unknown"),
+        class
+    );
+
+    if let Some(ids) = &report.ids {
+        println!(
+            "  id: bus={} vendor={:04x} product={:04x} version={:04x}",
+            ids.bus, ids.vendor, ids.product, ids.version
+        );
+    }
+    if let Some(phys) = report.phys.as_deref().filter(|p| !p.is_empty()) {
+        println!("  phys: {}", phys);
+    }
+    if let Some(udev) = &report.udev {
+        // ID_INPUT_TOUCHPAD=1 などの判定と、セレクタに使える値
+        let flags: Vec<_> = udev
+            .properties
+            .iter()
+            .filter(|(key, value)| key.starts_with("ID_INPUT_") && *value == "1")
+            .map(|(key, _)| key.as_str())
+            .collect();
+        println!(
+            "  udev: {} ID_PATH={} ID_SEAT={}",
+            flags.join(" "),
+            udev.id_path().unwrap_or("-"),
+            udev.seat()
+        );
+    }
+
+    if !report.keys.is_empty() {
+        let mut names: Vec<_> = report
+            .keys
+            .iter()
+            .take(MAX_KEYS_SHOWN)
+            .map(|k| format!("{:?}", k))
+            .collect();
+        if report.keys.len() > MAX_KEYS_SHOWN {
+            names.push(format!("... ({} keys)", report.keys.len()));
+        }
+        println!("  keys: {}", names.join(" "));
+    }
+    for axis in &report.axes {
+        println!(
+            "  {:?}: {} to {} (resolution {})",
+            axis.axis, axis.min, axis.max, axis.resolution
+        );
+    }
+
+    for selection in &report.selection {
+        println!(
+            "  {} as {}: {}",
+            if selection.selected {
+                "=> selected"
+            } else {
+                "not selected"
+            },
+            selection.role.as_str(),
+            selection.reason
+        );
+    }
+    println!();
+}
@@ -0,0 +1,123 @@
+//! サブコマンドの実装
+
+pub mod bench_latency;
+pub mod calibrate;
+pub mod ctl;
+pub mod daemon;
+pub mod doctor;
+pub mod gen_config;
+pub mod list_devices;
+pub mod monitor;
+pub mod record;
+pub mod replay;
+pub mod status;
+
+use crate::Args;
+use anyhow::Context;
+use evdev::Device;
+use std::path::PathBuf;
+use way_thumbsense::config::Config;
+use way_thumbsense::control::default_socket_path;
+use way_thumbsense::input::{find_device, find_device_except, find_touchpad};
+use way_thumbsense::logging::{self, LogFilter, LogFormat, LogOptions};
+use way_thumbsense::output::LayerBackend;
+use way_thumbsense::tracker::ExclusionZones;
+
+/// 設定ファイルを読み込み、コマンドライン引数で上書き
+pub fn load_config(args: &Args) -> anyhow::Result<Config> {
+    apply_overrides(args, Config::load_or_default(args.config.as_deref())?)
+}
+
+/// 実行中に設定ファイルを読み直す（ファイルがない・空ならエラー）
+pub fn reload_config(args: &Args) -> anyhow::Result<Config> {
+    apply_overrides(args, Config::reload(args.config.as_deref())?)
+}
+
+fn apply_overrides(args: &Args, mut config: Config) -> anyhow::Result<Config> {
+    if let Some(kind) = args.backend {
+        config.backend.kind = kind;
+    }
+
+    // コマンドラインの指定はデバイスごとの設定よりも優先する
+    override_zones(args, &mut config.zones);
+    for profile in &mut config.touchpads {
+        if let Some(zones) = &mut profile.zones {
+            override_zones(args, zones);
+        }
+    }
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// ロガーを設定（コマンドライン引数が設定ファイルの `[logging]` より優先）
+pub fn init_logging(args: &Args) -> anyhow::Result<()> {
+    // 設定ファイルの誤りは各サブコマンドが報告するので、ここではデフォルトで続ける
+    let config = Config::load_or_default(args.config.as_deref())
+        .map(|c| c.logging)
+        .unwrap_or_default();
+
+    let level = match (&args.log_level, args.debug) {
+        (Some(level), _) => level.clone(),
+        (None, true) => "debug".to_string(),
+        (None, false) => config.level,
+    };
+    logging::init(LogOptions {
+        filter: LogFilter::parse(&level)?,
+        format: args
+            .log_format
+            .or(config.format)
+            .unwrap_or_else(LogFormat::detect),
+        file: args.log_file.clone().or(config.file),
+        log_keys: args.log_keys || config.log_keys,
+    })?;
+    Ok(())
+}
+
+fn override_zones(args: &Args, zones: &mut ExclusionZones) {
+    for (arg, zone) in [
+        (args.exclude_top, &mut zones.top),
+        (args.exclude_bottom, &mut zones.bottom),
+        (args.exclude_left, &mut zones.left),
+        (args.exclude_right, &mut zones.right),
+    ] {
+        if let Some(value) = arg {
+            *zone = value;
+        }
+    }
+}
+
+/// 設定に従ってタッチパッドを開く（名前の指定があればそれを優先）
+pub fn open_touchpad(config: &Config) -> anyhow::Result<Device> {
+    Ok(match &config.device.touchpad {
+        Some(name) => find_device(name)?,
+        None => find_touchpad()?,
+    })
+}
+
+/// `[device] keyboard` のキーボードをgrabして開く（指定がなければNone）
+///
+/// `backend` が作成したイベントノード（自分の仮想デバイス）は選ばない
+pub fn open_keyboard(
+    config: &Config,
+    backend: Option<&dyn LayerBackend>,
+) -> anyhow::Result<Option<Device>> {
+    let Some(selector) = &config.device.keyboard else {
+        return Ok(None);
+    };
+    let mut keyboard = find_device_except(selector, |path| {
+        backend.is_some_and(|b| b.owns_devnode(path))
+    })?;
+    keyboard
+        .grab()
+        .context("Cannot grab the keyboard (is keyd or another remapper reading it?)")?;
+    Ok(Some(keyboard))
+}
+
+/// 制御ソケットのパス
+pub fn socket_path(args: &Args) -> anyhow::Result<PathBuf> {
+    match &args.socket {
+        Some(path) => Ok(path.clone()),
+        None => default_socket_path().context("Cannot prepare the control socket directory"),
+    }
+}
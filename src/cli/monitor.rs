@@ -0,0 +1,139 @@
+//! monitor サブコマンド: タッチパッドと除外領域を端末に表示する
+
+use crate::Args;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use way_thumbsense::config::Config;
+use way_thumbsense::input::get_touchpad_dimensions;
+use way_thumbsense::monitor::Monitor;
+use way_thumbsense::poll::wait_readable;
+use way_thumbsense::signals::{SignalPipe, SIGINT, SIGTERM, SIGWINCH};
+use way_thumbsense::watch::FileWatcher;
+
+pub fn run(args: &Args) -> anyhow::Result<()> {
+    let config = super::load_config(args)?;
+    let mut touchpad = super::open_touchpad(&config)?;
+    let device = touchpad.name().unwrap_or("unknown").to_string();
+    let dimensions = get_touchpad_dimensions(&touchpad)
+        .ok_or_else(|| anyhow::anyhow!("Failed to get touchpad dimensions"))?;
+    let dimensions = config.dimensions_for(&device, dimensions);
+
+    let signals = SignalPipe::install(&[SIGINT, SIGTERM, SIGWINCH])?;
+    // 設定ファイルを保存すると除外領域の表示も変わる
+    let watcher =
+        Config::resolve_path(args.config.as_deref()).and_then(|path| FileWatcher::new(&path).ok());
+
+    let mut monitor = Monitor::new(&device, dimensions, config.zones_for(&device));
+    let terminal = Terminal::enter()?;
+    let stdin = io::stdin();
+
+    loop {
+        terminal.draw(&monitor)?;
+
+        let ready = wait_readable(
+            &[
+                touchpad.as_raw_fd(),
+                stdin.as_raw_fd(),
+                signals.fd(),
+                watcher.as_ref().map_or(-1, FileWatcher::fd),
+            ],
+            None,
+        )?;
+
+        if ready[0] {
+            let events: Vec<_> = touchpad.fetch_events()?.collect();
+            monitor.process(&events);
+        }
+
+        if ready[1] {
+            let mut buf = [0u8; 32];
+            let n = stdin.lock().read(&mut buf)?;
+            if n == 0 || buf[..n].iter().any(|&b| b == b'q' || b == 0x1b) {
+                break;
+            }
+        }
+
+        // SIGWINCHは再描画するだけ
+        if ready[2] && signals.drain().iter().any(|&s| s != SIGWINCH) {
+            break;
+        }
+
+        if ready[3] && watcher.as_ref().is_some_and(FileWatcher::changed) {
+            // 不正な設定は無視して表示を続ける
+            if let Ok(config) = super::reload_config(args) {
+                monitor.set_exclusion_zones(config.zones_for(&device));
+            }
+        }
+    }
+
+    drop(terminal);
+    Ok(())
+}
+
+/// 非カノニカルモードと代替画面（Dropで元に戻す）
+struct Terminal {
+    original: Option<libc::termios>,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        // 端末でなければ（パイプなど）モードは変えない
+        let original = if unsafe { libc::tcgetattr(fd, &mut termios) } == 0 {
+            let original = termios;
+            // 1キーずつ読む。ISIGは残すのでCtrl+CはSIGINTになる
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Some(original)
+        } else {
+            None
+        };
+
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Self { original })
+    }
+
+    fn draw(&self, monitor: &Monitor) -> io::Result<()> {
+        let (cols, rows) = window_size();
+        // 画面全体を消さずに上書きしてちらつきを抑える
+        let mut screen = String::from("\x1b[H");
+        for line in monitor.render(cols, rows).iter().take(rows) {
+            screen.extend(line.chars().take(cols));
+            screen.push_str("\x1b[K\r\n");
+        }
+        screen.push_str("\x1b[J");
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        if let Some(original) = &self.original {
+            unsafe { libc::tcsetattr(io::stdin().as_raw_fd(), libc::TCSANOW, original) };
+        }
+    }
+}
+
+/// 端末の大きさ（取得できなければ80x24）
+fn window_size() -> (usize, usize) {
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    let ok = unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
@@ -0,0 +1,105 @@
+//! record サブコマンド: タッチパッドとキーボードのイベントをファイルに記録する
+
+use crate::Args;
+use anyhow::Context;
+use evdev::{Device, EventType, Key};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use way_thumbsense::input::{find_keyboard, get_touchpad_dimensions};
+use way_thumbsense::poll::wait_readable;
+use way_thumbsense::recording::{DeviceInfo, RecordingWriter, Source};
+use way_thumbsense::signals::{SignalPipe, SIGINT, SIGTERM};
+
+pub fn run(args: &Args, output: &Path, all_keys: bool) -> anyhow::Result<()> {
+    let config = super::load_config(args)?;
+    let mut touchpad = super::open_touchpad(&config)?;
+    let name = touchpad.name().unwrap_or("unknown").to_string();
+    let dimensions = get_touchpad_dimensions(&touchpad)
+        .map(|dimensions| config.dimensions_for(&name, dimensions));
+    println!("Touchpad: {}", name);
+
+    let mut devices = vec![DeviceInfo {
+        source: Source::Touchpad,
+        name,
+        dimensions,
+    }];
+
+    // キーボードがなくてもタッチパッドだけ記録する
+    let mut keyboard = match find_keyboard() {
+        Ok(keyboard) => {
+            let name = keyboard.name().unwrap_or("unknown").to_string();
+            println!("Keyboard: {}", name);
+            devices.push(DeviceInfo {
+                source: Source::Keyboard,
+                name,
+                dimensions: None,
+            });
+            Some(keyboard)
+        }
+        Err(e) => {
+            eprintln!("Keyboard not recorded: {}", e);
+            None
+        }
+    };
+
+    // 入力内容を残さないよう、既定ではマッピングのキーだけ記録する
+    let keys: HashSet<u16> = config.mappings().iter().map(|m| m.key.0).collect();
+    if !all_keys {
+        let mut names: Vec<_> = keys
+            .iter()
+            .map(|&code| format!("{:?}", Key::new(code)))
+            .collect();
+        names.sort();
+        println!("Recording keys: {}", names.join(", "));
+    }
+
+    let file =
+        File::create(output).with_context(|| format!("Cannot create {}", output.display()))?;
+    let mut writer = RecordingWriter::new(BufWriter::new(file), devices)?;
+    let signals = SignalPipe::install(&[SIGINT, SIGTERM])?;
+
+    println!("\nRecording to {}... (Ctrl+C to stop)", output.display());
+    let mut count = 0usize;
+    loop {
+        let ready = wait_readable(
+            &[
+                touchpad.as_raw_fd(),
+                keyboard.as_ref().map_or(-1, Device::as_raw_fd),
+                signals.fd(),
+            ],
+            None,
+        )?;
+
+        if ready[0] {
+            for event in touchpad.fetch_events()? {
+                writer.write(Source::Touchpad, &event)?;
+                count += 1;
+            }
+        }
+
+        if ready[1] {
+            if let Some(keyboard) = keyboard.as_mut() {
+                for event in keyboard.fetch_events()? {
+                    if event.event_type() == EventType::KEY
+                        && (all_keys || keys.contains(&event.code()))
+                    {
+                        writer.write(Source::Keyboard, &event)?;
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        if ready[2] && !signals.drain().is_empty() {
+            break;
+        }
+        writer.flush()?;
+    }
+
+    writer.flush()?;
+    println!("Saved {} events to {}", count, output.display());
+    Ok(())
+}
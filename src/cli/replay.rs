@@ -0,0 +1,121 @@
+//! replay サブコマンド: 記録をエンジンに流して出力を表示する（実際に出力もできる）
+//!
+//! recordの記録のほか、evemu-recordの記録も読み込める
+
+use crate::Args;
+use anyhow::Context;
+use evdev::Key;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use way_thumbsense::core::{KeyEvent, OutputAction};
+use way_thumbsense::engine::{Engine, TouchEvent};
+use way_thumbsense::input::evemu::{is_evemu, EvemuDevice};
+use way_thumbsense::recording::{replay, Recording, ReplayAction, Source};
+use way_thumbsense::tracker::TouchTracker;
+
+pub fn run(args: &Args, input: &Path, emit: bool, recreate: bool) -> anyhow::Result<()> {
+    let config = super::load_config(args)?;
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Cannot open {}", input.display()))?;
+
+    // evemu-recordの記録も受け付ける
+    let recording = if is_evemu(&text) {
+        let device = EvemuDevice::parse(&text)?;
+        if recreate {
+            return recreate_device(&device);
+        }
+        device.to_recording()
+    } else {
+        if recreate {
+            anyhow::bail!("--recreate needs an evemu-record file");
+        }
+        Recording::read(text.as_bytes())?
+    };
+
+    let touchpad = recording
+        .device(Source::Touchpad)
+        .ok_or_else(|| anyhow::anyhow!("Recording has no touchpad"))?;
+    let dimensions = touchpad
+        .dimensions
+        .ok_or_else(|| anyhow::anyhow!("Recording has no touchpad dimensions"))?;
+    println!("Touchpad: {}", touchpad.name);
+
+    // 除外領域とマッピングは現在の設定を使う
+    let mut engine = Engine::new(TouchTracker::new(
+        dimensions,
+        config.zones_for(&touchpad.name),
+    ));
+    engine.set_mappings(config.mappings());
+    let outputs = replay(&recording, &mut engine);
+
+    // 実際に出力する場合は記録時の間隔を再現する
+    let mut backend = if emit {
+        Some(super::daemon::create_backend(&config)?)
+    } else {
+        None
+    };
+    let start = Instant::now();
+
+    for output in &outputs {
+        if let Some(backend) = backend.as_mut() {
+            let at = start + Duration::from_micros(output.time_us);
+            std::thread::sleep(at.saturating_duration_since(Instant::now()));
+            match output.action {
+                ReplayAction::Touch(TouchEvent::Activated { .. }) => backend.activate()?,
+                ReplayAction::Touch(TouchEvent::Deactivated) => backend.deactivate()?,
+                _ => {}
+            }
+        }
+        println!(
+            "{:>10.3}s  {}",
+            output.time_us as f64 / 1_000_000.0,
+            describe(output.action)
+        );
+    }
+
+    if engine.is_active() {
+        println!("Layer is still active at the end of the recording");
+        if let Some(backend) = backend.as_mut() {
+            backend.deactivate()?;
+        }
+    }
+    Ok(())
+}
+
+/// 記録と同じ仮想タッチパッドを作ってイベントを送る（実行中のデーモンの確認用）
+fn recreate_device(device: &EvemuDevice) -> anyhow::Result<()> {
+    let mut vdev = device
+        .create_device()
+        .context("Cannot create the device through /dev/uinput")?;
+    println!("Created virtual device: {}", device.name);
+
+    // 他のプロセスがデバイスを見つけるまで少し待つ
+    std::thread::sleep(Duration::from_millis(500));
+    println!("Playing {} events...", device.events.len());
+    device.play(&mut vdev)?;
+    println!("Done");
+    Ok(())
+}
+
+fn describe(action: ReplayAction) -> String {
+    match action {
+        ReplayAction::Touch(TouchEvent::Activated { x, y }) => {
+            format!("activate (x={:?}, y={:?})", x, y)
+        }
+        ReplayAction::Touch(TouchEvent::Excluded { x, y, edge }) => {
+            format!("not activated (x={}, y={}, {} zone)", x, y, edge.as_str())
+        }
+        ReplayAction::Touch(TouchEvent::PalmRejected { x, y }) => {
+            format!("not activated (x={:?}, y={:?}, palm)", x, y)
+        }
+        ReplayAction::Touch(TouchEvent::Deactivated) => "deactivate".to_string(),
+        ReplayAction::Key(OutputAction::MouseClick(button)) => format!("{:?} click", button),
+        ReplayAction::Key(OutputAction::MouseRelease(button)) => {
+            format!("{:?} release", button)
+        }
+        ReplayAction::Key(OutputAction::PassThrough(event)) => match event {
+            KeyEvent::Press(key) => format!("{:?} pass through (press)", Key::new(key.0)),
+            KeyEvent::Release(key) => format!("{:?} pass through (release)", Key::new(key.0)),
+        },
+    }
+}
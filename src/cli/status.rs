@@ -0,0 +1,71 @@
+//! `status` サブコマンド: ステータスバー向けに状態を出力
+
+use crate::Args;
+use clap::ValueEnum;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+use way_thumbsense::control::{self, Request, State, StateChange};
+
+/// デーモンに接続できないときの再接続間隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// waybarの custom モジュール（return-type: json）
+    Waybar,
+    /// StateChangeをそのままJSONで出力
+    Json,
+}
+
+pub fn run(args: &Args, follow: bool, format: Format) -> anyhow::Result<()> {
+    let path = super::socket_path(args)?;
+
+    if !follow {
+        let change = match control::send_request(&path, &Request::Status) {
+            Ok(response) => match response.status {
+                Some(status) => StateChange {
+                    state: status.state(),
+                    device: status.device,
+                },
+                None => stopped(),
+            },
+            Err(_) => stopped(),
+        };
+        return print(&change, format);
+    }
+
+    // デーモンの再起動をまたいで出力を続ける
+    loop {
+        if let Ok(changes) = control::subscribe(&path) {
+            for change in changes {
+                match change {
+                    Ok(change) => print(&change, format)?,
+                    Err(_) => break,
+                }
+            }
+        }
+        print(&stopped(), format)?;
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+fn stopped() -> StateChange {
+    StateChange {
+        state: State::Stopped,
+        device: String::new(),
+    }
+}
+
+/// 1行出力してすぐにフラッシュ（パイプ先のwaybarに即座に届ける）
+fn print(change: &StateChange, format: Format) -> anyhow::Result<()> {
+    let line = match format {
+        Format::Waybar => serde_json::to_string(&change.to_waybar())?,
+        Format::Json => serde_json::to_string(change)?,
+    };
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()?;
+    Ok(())
+}
@@ -0,0 +1,874 @@
+//! 設定ファイル（TOML）の読み込み
+
+use crate::core::{KeyCode, Mapping, MouseButton};
+use crate::input::TouchpadDimensions;
+use crate::logging::{LogFilter, LogFormat};
+use crate::tracker::ExclusionZones;
+use evdev::{BusType, Key};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// uinputデバイス名の最大長（UINPUT_MAX_NAME_SIZE - 終端NUL）
+const MAX_NAME_LEN: usize = 79;
+
+/// 設定全体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device: DeviceConfig,
+    pub zones: ExclusionZones,
+    /// デバイスごとのキャリブレーション結果
+    pub touchpads: Vec<TouchpadProfile>,
+    pub trigger: TriggerConfig,
+    pub mappings: Vec<MappingConfig>,
+    pub virtual_device: VirtualDeviceConfig,
+    pub backend: BackendConfig,
+    pub hooks: HooksConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            device: DeviceConfig::default(),
+            zones: ExclusionZones::none(),
+            touchpads: Vec::new(),
+            trigger: TriggerConfig::default(),
+            mappings: vec![
+                MappingConfig {
+                    key: Key::KEY_J,
+                    button: MouseButton::Left,
+                },
+                MappingConfig {
+                    key: Key::KEY_K,
+                    button: MouseButton::Right,
+                },
+            ],
+            virtual_device: VirtualDeviceConfig::default(),
+            backend: BackendConfig::default(),
+            hooks: HooksConfig::default(),
+            logging: LoggingConfig::default(),
+            metrics: MetricsConfig::default(),
+        }
+    }
+}
+
+/// 入力デバイスの選択
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// タッチパッド名の一部、または `ID_PATH=...` のようなudevのプロパティ
+    /// （省略時は自動検出）
+    pub touchpad: Option<String>,
+    /// マッピングをデーモン自身で行うときに読むキーボード（指定の形式はtouchpadと同じ）
+    ///
+    /// grabして読むので、keydなどキーボードを読む他のプログラムとは併用できない。uinputのみ。読めるのは1台だけ
+    pub keyboard: Option<String>,
+}
+
+/// タッチパッドごとの設定（`calibrate` が書き込む）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TouchpadProfile {
+    /// デバイス名（完全一致）
+    pub name: String,
+    /// 実際に指が届く座標範囲（AbsInfoの値の代わりに使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<TouchpadDimensions>,
+    /// このデバイス用の除外領域（`[zones]` の代わりに使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zones: Option<ExclusionZones>,
+}
+
+/// タッチ中に押し続けるトリガーキー
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriggerConfig {
+    pub key: Key,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self { key: Key::KEY_F24 }
+    }
+}
+
+/// キー → マウスボタンの対応
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MappingConfig {
+    pub key: Key,
+    pub button: MouseButton,
+}
+
+/// 仮想デバイスの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtualDeviceConfig {
+    pub mouse: DeviceIdentity,
+    pub keyboard: DeviceIdentity,
+    /// イベントノードが開けるようになるまでの待ち時間の上限
+    pub ready_timeout_ms: u64,
+}
+
+impl Default for VirtualDeviceConfig {
+    fn default() -> Self {
+        Self {
+            mouse: DeviceIdentity::new("way-thumbsense mouse", 0x0001),
+            keyboard: DeviceIdentity::new("way-thumbsense keyboard", 0x0002),
+            ready_timeout_ms: 2000,
+        }
+    }
+}
+
+/// uinputデバイスの識別情報（udevルール等でのマッチ用）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceIdentity {
+    pub name: String,
+    #[serde(default)]
+    pub vendor: u16,
+    #[serde(default)]
+    pub product: u16,
+    #[serde(default = "default_version")]
+    pub version: u16,
+    #[serde(default = "default_bus")]
+    pub bus: BusType,
+}
+
+impl DeviceIdentity {
+    fn new(name: &str, product: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            vendor: 0,
+            product,
+            version: default_version(),
+            bus: default_bus(),
+        }
+    }
+}
+
+fn default_version() -> u16 {
+    1
+}
+
+fn default_bus() -> BusType {
+    BusType::BUS_VIRTUAL
+}
+
+/// レイヤー切り替えの出力方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// 仮想キーボードでトリガーキーを押す
+    #[default]
+    Uinput,
+    /// keydの制御ソケットでレイヤーを切り替える
+    Keyd,
+    /// kanataのTCPサーバーでレイヤーを切り替える
+    Kanata,
+}
+
+/// 出力バックエンドの設定
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    pub keyd: KeydConfig,
+    pub kanata: KanataConfig,
+}
+
+/// keyd IPCバックエンドの設定
+///
+/// `activate` / `deactivate` はkeydに実行させる式で、`{layer}` がレイヤー名に置換される。
+/// 既定値はどちらも一度clearしてから切り替えるので、取りこぼしや手動のtoggleがあっても状態が反転したままにならない
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeydConfig {
+    pub socket: PathBuf,
+    pub layer: String,
+    pub activate: String,
+    pub deactivate: String,
+}
+
+impl Default for KeydConfig {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from("/var/run/keyd.socket"),
+            layer: "mousenav".to_string(),
+            activate: "clear() toggle({layer})".to_string(),
+            deactivate: "clear()".to_string(),
+        }
+    }
+}
+
+impl KeydConfig {
+    /// レイヤー有効化の式
+    pub fn activate_expr(&self) -> String {
+        self.activate.replace("{layer}", &self.layer)
+    }
+
+    /// レイヤー無効化の式
+    pub fn deactivate_expr(&self) -> String {
+        self.deactivate.replace("{layer}", &self.layer)
+    }
+}
+
+/// kanata TCPバックエンドの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KanataConfig {
+    /// kanataの `--port` で開いたTCPサーバーのアドレス
+    pub address: String,
+    pub layer: String,
+    /// 現在のレイヤーを取得できなかった場合に戻すレイヤー
+    pub default_layer: String,
+}
+
+impl Default for KanataConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:10000".to_string(),
+            layer: "mousenav".to_string(),
+            default_layer: "base".to_string(),
+        }
+    }
+}
+
+/// 状態変化時に実行するシェルコマンド
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub on_activate: Option<String>,
+    pub on_deactivate: Option<String>,
+    pub on_excluded: Option<String>,
+    /// これを超えたコマンドは強制終了
+    pub timeout_ms: u64,
+    /// 同時に実行するコマンド数の上限（超えた分は実行しない）
+    pub max_concurrent: usize,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_activate: None,
+            on_deactivate: None,
+            on_excluded: None,
+            timeout_ms: 5000,
+            max_concurrent: 4,
+        }
+    }
+}
+
+/// ログ出力（コマンドラインの `--log-*` が優先）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `info` や `warn,way_thumbsense::engine=trace` のようなレベルの指定
+    pub level: String,
+    /// 省略時はsystemdから起動されていればjournald、それ以外はplain
+    pub format: Option<LogFormat>,
+    /// 標準エラーの代わりに追記するファイル
+    pub file: Option<PathBuf>,
+    /// キーボードのキーコードをログに出す（デバッグ用。通常は伏せる）
+    pub log_keys: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: None,
+            file: None,
+            log_keys: false,
+        }
+    }
+}
+
+/// 集計値の公開（`ctl metrics` は常に使える）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Prometheusのテキスト形式を返すUnixソケット（変更は再起動で反映）
+    pub socket: Option<PathBuf>,
+}
+
+impl Config {
+    /// デフォルトの設定ファイルパス（$XDG_CONFIG_HOME/way-thumbsense/config.toml）
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("way-thumbsense").join("config.toml"))
+    }
+
+    /// 実際に読み込む設定ファイルのパス（指定がなければデフォルトパス）
+    pub fn resolve_path(path: Option<&Path>) -> Option<PathBuf> {
+        path.map(Path::to_path_buf).or_else(Self::default_path)
+    }
+
+    /// 設定ファイルを読み込む
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text)
+    }
+
+    /// 指定パス、なければデフォルトパスから読み込む
+    ///
+    /// パス未指定でデフォルトパスにファイルがなければデフォルト設定を返す
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, ConfigError> {
+        if let Some(path) = path {
+            return Self::load(path);
+        }
+
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// 変更を検知した設定ファイルを読み直す
+    ///
+    /// 書き込み途中や削除された状態で読むと既定値に戻ってしまうので、
+    /// ファイルがない・空のときはエラーにする（呼び出し側は前の設定を使い続ける）
+    pub fn reload(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let path = Self::resolve_path(path)
+            .ok_or_else(|| ConfigError::Invalid("no configuration file to reload".to_string()))?;
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        if text.trim().is_empty() {
+            return Err(ConfigError::Invalid(format!("{} is empty", path.display())));
+        }
+        Self::parse(&text)
+    }
+
+    /// TOML文字列をパースして検証
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 設定値の整合性をチェック
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.zones.validate().map_err(ConfigError::Invalid)?;
+        LogFilter::parse(&self.logging.level).map_err(|e| ConfigError::Invalid(e.to_string()))?;
+
+        for profile in &self.touchpads {
+            if let Some(zones) = &profile.zones {
+                zones
+                    .validate()
+                    .map_err(|e| ConfigError::Invalid(format!("{}: {}", profile.name, e)))?;
+            }
+            if let Some(range) = &profile.range {
+                if range.width() <= 0 || range.height() <= 0 {
+                    return Err(ConfigError::Invalid(format!(
+                        "{}: empty touchpad range",
+                        profile.name
+                    )));
+                }
+            }
+        }
+
+        for identity in [&self.virtual_device.mouse, &self.virtual_device.keyboard] {
+            if identity.name.is_empty() || identity.name.len() > MAX_NAME_LEN {
+                return Err(ConfigError::Invalid(format!(
+                    "virtual device name must be 1-{} bytes: '{}'",
+                    MAX_NAME_LEN, identity.name
+                )));
+            }
+        }
+
+        if self.device.keyboard.is_some() && self.backend.kind != BackendKind::Uinput {
+            return Err(ConfigError::Invalid(
+                "device.keyboard requires the uinput backend".to_string(),
+            ));
+        }
+
+        if self.backend.kind == BackendKind::Keyd && self.backend.keyd.layer.is_empty() {
+            return Err(ConfigError::Invalid("keyd layer name is empty".to_string()));
+        }
+
+        if self.backend.kind == BackendKind::Kanata {
+            let kanata = &self.backend.kanata;
+            if kanata.layer.is_empty() || kanata.default_layer.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "kanata layer name is empty".to_string(),
+                ));
+            }
+        }
+
+        // マッピングがなければキーボードを読んでも何もできない
+        if self.mappings.is_empty() {
+            return Err(ConfigError::Invalid(
+                "at least one [[mappings]] entry is required".to_string(),
+            ));
+        }
+
+        for (i, mapping) in self.mappings.iter().enumerate() {
+            if mapping.key == self.trigger.key {
+                return Err(ConfigError::Invalid(format!(
+                    "mapping key {:?} is the trigger key",
+                    mapping.key
+                )));
+            }
+            if self.mappings[..i].iter().any(|m| m.key == mapping.key) {
+                return Err(ConfigError::Invalid(format!(
+                    "duplicate mapping for {:?}",
+                    mapping.key
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// デバイス名に対応するプロファイル
+    pub fn touchpad_profile(&self, name: &str) -> Option<&TouchpadProfile> {
+        self.touchpads.iter().find(|p| p.name == name)
+    }
+
+    /// デバイスに適用する座標範囲（プロファイルがなければデバイスの値）
+    pub fn dimensions_for(&self, name: &str, device: TouchpadDimensions) -> TouchpadDimensions {
+        self.touchpad_profile(name)
+            .and_then(|p| p.range)
+            .unwrap_or(device)
+    }
+
+    /// デバイスに適用する除外領域（プロファイルがなければ `[zones]`）
+    pub fn zones_for(&self, name: &str) -> ExclusionZones {
+        self.touchpad_profile(name)
+            .and_then(|p| p.zones)
+            .unwrap_or(self.zones)
+    }
+
+    /// `other` へ切り替えるのにデバイスや出力先の開き直しが必要か
+    ///
+    /// 除外領域・マッピング・フックは実行中に差し替えられるが、
+    /// 仮想デバイスの広告キーはマッピングから決まるため、uinputではマッピング変更も再オープン扱い
+    pub fn requires_reopen(&self, other: &Config) -> bool {
+        self.device != other.device
+            || self
+                .touchpads
+                .iter()
+                .map(|p| (&p.name, p.range))
+                .ne(other.touchpads.iter().map(|p| (&p.name, p.range)))
+            || self.trigger != other.trigger
+            || self.virtual_device != other.virtual_device
+            || self.backend != other.backend
+            || (self.backend.kind == BackendKind::Uinput && self.mappings != other.mappings)
+    }
+
+    /// コアロジック用のマッピングテーブル
+    pub fn mappings(&self) -> Vec<Mapping> {
+        self.mappings
+            .iter()
+            .map(|m| Mapping {
+                key: KeyCode(m.key.code()),
+                button: m.button,
+            })
+            .collect()
+    }
+}
+
+/// 設定ファイル中のタッチパッドプロファイルを追加・更新する
+///
+/// コメントや他の設定はそのまま残し、書き込み前に全体を検証する。
+/// ファイルがなければ作成する
+pub fn write_touchpad_profile(path: &Path, profile: &TouchpadProfile) -> Result<(), ConfigError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // シンボリックリンクならリンク先を書き換える
+    let path = match std::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+    };
+    let (text, mode) = match std::fs::read_to_string(&path) {
+        Ok(text) => {
+            let metadata =
+                std::fs::metadata(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+            (text, Some(metadata.permissions().mode()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (String::new(), None),
+        Err(e) => return Err(ConfigError::Io(path, e)),
+    };
+    let text = update_touchpad_profile(&text, profile)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| ConfigError::Io(dir.to_path_buf(), e))?;
+    }
+    // 監視中のデーモンが書きかけを読まないよう rename で置き換える
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, text).map_err(|e| ConfigError::Io(tmp.clone(), e))?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| ConfigError::Io(tmp.clone(), e))?;
+    }
+    std::fs::rename(&tmp, &path).map_err(|e| ConfigError::Io(path, e))
+}
+
+/// TOML文字列中の `[[touchpads]]` を更新した結果を返す
+pub fn update_touchpad_profile(
+    text: &str,
+    profile: &TouchpadProfile,
+) -> Result<String, ConfigError> {
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e: toml_edit::TomlError| ConfigError::Parse(e.to_string()))?;
+
+    let serialized =
+        toml_edit::ser::to_document(profile).map_err(|e| ConfigError::Invalid(e.to_string()))?;
+    let table = serialized.as_table().clone();
+
+    let touchpads = doc
+        .entry("touchpads")
+        .or_insert(toml_edit::Item::ArrayOfTables(Default::default()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| ConfigError::Invalid("touchpads must be [[touchpads]]".to_string()))?;
+
+    let existing = touchpads
+        .iter_mut()
+        .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(profile.name.as_str()));
+    match existing {
+        Some(existing) => *existing = table,
+        None => touchpads.push(table),
+    }
+
+    let text = doc.to_string();
+    Config::parse(&text)?;
+    Ok(text)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(msg) => write!(f, "Invalid config: {}", msg),
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_rejects_missing_and_empty_files() {
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-reload-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(
+            Config::reload(Some(&path)),
+            Err(ConfigError::Io(..))
+        ));
+
+        std::fs::write(&path, "\n  \n").unwrap();
+        assert!(matches!(
+            Config::reload(Some(&path)),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        std::fs::write(&path, "[zones]\nbottom = 20\n").unwrap();
+        assert_eq!(Config::reload(Some(&path)).unwrap().zones.bottom, 20.0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.trigger.key, Key::KEY_F24);
+        assert_eq!(config.virtual_device.mouse.bus, BusType::BUS_VIRTUAL);
+    }
+
+    #[test]
+    fn parse_identity_and_mappings() {
+        let config = Config::parse(
+            r#"
+            [trigger]
+            key = "KEY_F23"
+
+            [[mappings]]
+            key = "KEY_L"
+            button = "middle"
+
+            [virtual_device.keyboard]
+            name = "thumbsense kbd"
+            vendor = 0x1234
+            product = 0x5678
+            bus = "BUS_USB"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.trigger.key, Key::KEY_F23);
+        assert_eq!(
+            config.mappings(),
+            vec![Mapping {
+                key: KeyCode(Key::KEY_L.code()),
+                button: MouseButton::Middle,
+            }]
+        );
+
+        let kbd = &config.virtual_device.keyboard;
+        assert_eq!(kbd.name, "thumbsense kbd");
+        assert_eq!((kbd.vendor, kbd.product, kbd.version), (0x1234, 0x5678, 1));
+        assert_eq!(kbd.bus, BusType::BUS_USB);
+        assert_eq!(config.virtual_device.mouse.name, "way-thumbsense mouse");
+    }
+
+    #[test]
+    fn parse_zones() {
+        let config = Config::parse(
+            r#"
+            [zones]
+            bottom = 20.0
+            left = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.zones, ExclusionZones::new(0.0, 20.0, 5.0, 0.0));
+
+        assert!(Config::parse("zones = { top = 150.0 }").is_err());
+    }
+
+    #[test]
+    fn parse_keyd_backend() {
+        let config = Config::parse(
+            r#"
+            [backend]
+            kind = "keyd"
+
+            [backend.keyd]
+            layer = "nav"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.backend.kind, BackendKind::Keyd);
+        assert_eq!(config.backend.keyd.activate_expr(), "clear() toggle(nav)");
+        assert_eq!(config.backend.keyd.deactivate_expr(), "clear()");
+        assert_eq!(
+            config.backend.keyd.socket,
+            PathBuf::from("/var/run/keyd.socket")
+        );
+    }
+
+    #[test]
+    fn live_changes_do_not_require_reopen() {
+        let old = Config::default();
+
+        let mut new = old.clone();
+        new.zones = ExclusionZones::new(0.0, 20.0, 0.0, 0.0);
+        new.hooks.on_activate = Some("true".to_string());
+        assert!(!old.requires_reopen(&new));
+
+        new.device.touchpad = Some("Magic Trackpad".to_string());
+        assert!(old.requires_reopen(&new));
+    }
+
+    #[test]
+    fn mapping_change_requires_reopen_only_for_uinput() {
+        let mut old = Config::default();
+        let mut new = old.clone();
+        new.mappings[0].button = MouseButton::Middle;
+        assert!(old.requires_reopen(&new));
+
+        old.backend.kind = BackendKind::Keyd;
+        new.backend.kind = BackendKind::Keyd;
+        assert!(!old.requires_reopen(&new));
+    }
+
+    #[test]
+    fn touchpad_profile_overrides_range_and_zones() {
+        let config = Config::parse(
+            r#"
+            [zones]
+            bottom = 10
+
+            [[touchpads]]
+            name = "Magic Trackpad"
+            range = { min_x = -3000, max_x = 3000, min_y = -2000, max_y = 2000 }
+            zones = { left = 5 }
+            "#,
+        )
+        .unwrap();
+        let device = TouchpadDimensions {
+            min_x: -4000,
+            max_x: 4000,
+            min_y: -2500,
+            max_y: 2500,
+        };
+
+        assert_eq!(
+            config.zones_for("Magic Trackpad"),
+            ExclusionZones::new(0.0, 0.0, 5.0, 0.0)
+        );
+        assert_eq!(config.dimensions_for("Magic Trackpad", device).min_x, -3000);
+        assert_eq!(
+            config.zones_for("Other"),
+            ExclusionZones::new(0.0, 10.0, 0.0, 0.0)
+        );
+        assert_eq!(config.dimensions_for("Other", device), device);
+    }
+
+    #[test]
+    fn update_profile_keeps_comments_and_replaces_entry() {
+        let original = r#"# my settings
+[trigger]
+key = "KEY_F23" # for keyd
+
+[[touchpads]]
+name = "Magic Trackpad"
+zones = { bottom = 50 }
+"#;
+        let profile = TouchpadProfile {
+            name: "Magic Trackpad".to_string(),
+            range: None,
+            zones: Some(ExclusionZones::new(0.0, 15.0, 0.0, 0.0)),
+        };
+
+        let updated = update_touchpad_profile(original, &profile).unwrap();
+        assert!(updated.contains("# my settings"));
+        assert!(updated.contains("# for keyd"));
+
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(config.touchpads, vec![profile]);
+        assert_eq!(config.trigger.key, Key::KEY_F23);
+    }
+
+    #[test]
+    fn update_profile_appends_new_device() {
+        let profile = TouchpadProfile {
+            name: "SYNA0001:00".to_string(),
+            range: Some(TouchpadDimensions {
+                min_x: 0,
+                max_x: 1200,
+                min_y: 0,
+                max_y: 800,
+            }),
+            zones: None,
+        };
+
+        let updated = update_touchpad_profile("", &profile).unwrap();
+        assert_eq!(Config::parse(&updated).unwrap().touchpads, vec![profile]);
+    }
+
+    #[test]
+    fn write_profile_follows_symlink_and_keeps_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("way-thumbsense-profile-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("real.toml");
+        let link = dir.join("config.toml");
+        std::fs::write(&target, "# dotfiles\n").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let profile = TouchpadProfile {
+            name: "SYNA0001:00".to_string(),
+            range: None,
+            zones: None,
+        };
+        write_touchpad_profile(&link, &profile).unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let text = std::fs::read_to_string(&target).unwrap();
+        assert!(text.contains("# dotfiles"));
+        assert_eq!(Config::parse(&text).unwrap().touchpads, vec![profile]);
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keyboard_requires_uinput_backend() {
+        let config = Config::parse("[device]\nkeyboard = \"ID_INPUT_KEYBOARD=1\"").unwrap();
+        assert_eq!(
+            config.device.keyboard.as_deref(),
+            Some("ID_INPUT_KEYBOARD=1")
+        );
+
+        let result = Config::parse(
+            r#"
+            [device]
+            keyboard = "AT Translated"
+            [backend]
+            kind = "keyd"
+            "#,
+        );
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn reject_mapping_on_trigger_key() {
+        let result = Config::parse(
+            r#"
+            [[mappings]]
+            key = "KEY_F24"
+            button = "left"
+            "#,
+        );
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn reject_empty_mappings() {
+        let result = Config::parse(
+            r#"
+            mappings = []
+            [trigger]
+            key = "KEY_F23"
+            "#,
+        );
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn reject_unknown_key_name() {
+        let result = Config::parse(
+            r#"
+            [trigger]
+            key = "KEY_NOPE"
+            "#,
+        );
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn parse_logging() {
+        let config = Config::parse(
+            r#"
+            [logging]
+            level = "warn,way_thumbsense::engine=trace"
+            format = "journald"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.logging.format, Some(LogFormat::Journald));
+        assert!(!config.logging.log_keys);
+
+        let result = Config::parse("logging = { level = \"loud\" }");
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+}
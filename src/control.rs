@@ -0,0 +1,594 @@
+//! 実行中のデーモンを操作する制御ソケット
+//!
+//! 1行1JSONのリクエスト/レスポンス:
+//!
+//! ```text
+//! > {"cmd":"toggle"}
+//! < {"ok":true,"status":{"enabled":false,...}}
+//! ```
+//!
+//! `{"cmd":"subscribe"}` を送ると、以降は状態が変わるたびに
+//! `{"state":"active",...}` が1行ずつ届く
+
+use crate::metrics::MetricsSnapshot;
+use crate::tracker::ExclusionZones;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// クライアント側の応答待ちタイムアウト
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 制御コマンド
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Enable,
+    Disable,
+    Toggle,
+    SetZones {
+        zones: ExclusionZones,
+    },
+    Reload,
+    /// 集計値（応答の `metrics`）
+    Metrics,
+    /// 状態変化の購読（接続を保ったまま StateChange を流す）
+    Subscribe,
+}
+
+/// ステータスバー向けの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    /// レイヤー有効化中
+    Active,
+    /// 待機中
+    Inactive,
+    /// 除外領域でのタッチ中
+    Excluded,
+    /// 無効化されている
+    Disabled,
+    /// タッチパッドが切断された
+    Disconnected,
+    /// デーモンに接続できない（クライアント側でのみ使用）
+    Stopped,
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Active => "active",
+            State::Inactive => "inactive",
+            State::Excluded => "excluded",
+            State::Disabled => "disabled",
+            State::Disconnected => "disconnected",
+            State::Stopped => "stopped",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            State::Active => "mouse layer engaged",
+            State::Inactive => "waiting for touch",
+            State::Excluded => "touch in exclusion zone",
+            State::Disabled => "disabled",
+            State::Disconnected => "touchpad disconnected",
+            State::Stopped => "daemon not running",
+        }
+    }
+}
+
+/// 状態変化の通知
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateChange {
+    pub state: State,
+    pub device: String,
+}
+
+impl StateChange {
+    /// waybarの custom モジュール（return-type: json）形式
+    pub fn to_waybar(&self) -> WaybarOutput {
+        let mut tooltip = format!("ThumbSense: {}", self.state.description());
+        if !self.device.is_empty() {
+            tooltip.push_str(&format!("\n{}", self.device));
+        }
+
+        WaybarOutput {
+            text: self.state.as_str().to_string(),
+            alt: self.state.as_str().to_string(),
+            class: self.state.as_str().to_string(),
+            tooltip,
+        }
+    }
+}
+
+/// waybarの custom モジュールが読む1行分のJSON
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaybarOutput {
+    pub text: String,
+    pub alt: String,
+    pub class: String,
+    pub tooltip: String,
+}
+
+/// デーモンの状態
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub enabled: bool,
+    /// レイヤーが有効化されているか
+    pub active: bool,
+    pub touching: bool,
+    pub zones: ExclusionZones,
+    pub device: String,
+    pub backend: String,
+    /// 直近の設定再読み込みで検出したエラー（古い設定のまま動作中）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_error: Option<String>,
+    /// タッチパッドが切断され、再接続を待っている
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disconnected: bool,
+}
+
+impl Status {
+    /// ステータスバー向けの状態
+    pub fn state(&self) -> State {
+        if self.disconnected {
+            State::Disconnected
+        } else if !self.enabled {
+            State::Disabled
+        } else if self.active {
+            State::Active
+        } else if self.touching {
+            // 有効中にタッチしてもレイヤーが有効でない = 除外領域
+            State::Excluded
+        } else {
+            State::Inactive
+        }
+    }
+}
+
+/// 制御コマンドへの応答
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsSnapshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(status: Status) -> Self {
+        Self {
+            ok: true,
+            status: Some(status),
+            metrics: None,
+            error: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: MetricsSnapshot) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            status: None,
+            metrics: None,
+            error: Some(message.into()),
+        }
+    }
+}
+
+/// デフォルトのソケットパス（$XDG_RUNTIME_DIR/way-thumbsense.sock）
+///
+/// `XDG_RUNTIME_DIR` がなければ、一時ディレクトリに自分だけが使える
+/// `way-thumbsense-<uid>`（0700）を作ってその中に置く
+pub fn default_socket_path() -> io::Result<PathBuf> {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            let dir = std::env::temp_dir().join(format!("way-thumbsense-{}", uid));
+            ensure_private_dir(&dir, uid)?;
+            dir
+        }
+    };
+    Ok(dir.join("way-thumbsense.sock"))
+}
+
+/// 自分が所有し、他のユーザーが読み書きできないディレクトリを用意する
+///
+/// 他のユーザーが先に作ったディレクトリやシンボリックリンクは使わない
+fn ensure_private_dir(dir: &Path, uid: libc::uid_t) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a private directory owned by uid {} (set XDG_RUNTIME_DIR or --socket)",
+                dir.display(),
+                uid
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// イベントループへ渡すリクエストと応答の返信先
+pub struct Pending {
+    pub request: Request,
+    reply: mpsc::Sender<Response>,
+}
+
+impl Pending {
+    pub fn reply(self, response: Response) {
+        // クライアントが先に切断していても無視
+        let _ = self.reply.send(response);
+    }
+}
+
+/// 制御ソケットのサーバー
+///
+/// 接続の読み書きは別スレッドで行い、リクエストをチャネルでイベントループへ渡す。
+/// リクエストが届くと `wake_fd` が読み込み可能になる
+pub struct ControlServer {
+    path: PathBuf,
+    requests: mpsc::Receiver<Pending>,
+    wake: UnixStream,
+    subscribers: Arc<Subscribers>,
+}
+
+/// 購読中の接続と最新の状態
+#[derive(Default)]
+struct Subscribers {
+    senders: Mutex<Vec<mpsc::Sender<StateChange>>>,
+    last: Mutex<Option<StateChange>>,
+}
+
+impl Subscribers {
+    /// 購読を登録し、最新の状態があれば最初に流す
+    fn add(&self) -> mpsc::Receiver<StateChange> {
+        let (tx, rx) = mpsc::channel();
+        if let Some(last) = self.last.lock().unwrap().clone() {
+            let _ = tx.send(last);
+        }
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+impl ControlServer {
+    /// ソケットを作成して受け付けを開始
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        // 前回の残骸を削除（動作中のデーモンがいればエラー）
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        // --socket で共有ディレクトリを指定されても他のユーザーから操作させない
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        let (wake, notify) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        let (tx, requests) = mpsc::channel();
+        let subscribers = Arc::new(Subscribers::default());
+
+        let subs = Arc::clone(&subscribers);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                let notify = notify.try_clone();
+                let subs = Arc::clone(&subs);
+                thread::spawn(move || {
+                    if let Ok(notify) = notify {
+                        let _ = serve_connection(stream, &tx, &notify, &subs);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            requests,
+            wake,
+            subscribers,
+        })
+    }
+
+    /// 購読者に状態変化を通知（切断済みの購読者は削除）
+    pub fn publish(&self, change: StateChange) {
+        self.subscribers
+            .senders
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(change.clone()).is_ok());
+        *self.subscribers.last.lock().unwrap() = Some(change);
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// リクエスト到着を知らせるfd（poll用）
+    pub fn wake_fd(&self) -> std::os::unix::io::RawFd {
+        std::os::unix::io::AsRawFd::as_raw_fd(&self.wake)
+    }
+
+    /// 届いているリクエストをすべて取り出す
+    pub fn drain(&self) -> Vec<Pending> {
+        // 通知用のバイトを読み捨てる
+        let mut buf = [0u8; 64];
+        while matches!(io::Read::read(&mut &self.wake, &mut buf), Ok(n) if n > 0) {}
+
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// 1接続分のリクエストを処理
+fn serve_connection(
+    stream: UnixStream,
+    requests: &mpsc::Sender<Pending>,
+    mut notify: &UnixStream,
+    subscribers: &Subscribers,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Subscribe) => {
+                // 以降はこの接続を状態変化の配信専用にする
+                for change in subscribers.add() {
+                    write_line(&mut writer, &change)?;
+                }
+                return Ok(());
+            }
+            Ok(request) => {
+                let (reply, rx) = mpsc::channel();
+                if requests.send(Pending { request, reply }).is_err() {
+                    return Ok(());
+                }
+                notify.write_all(&[1])?;
+                match rx.recv() {
+                    Ok(response) => response,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Err(e) => Response::error(format!("invalid request: {}", e)),
+        };
+
+        write_line(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+/// 1行のJSONとして書き込む
+fn write_line(writer: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+    let mut json = serde_json::to_string(value).map_err(io::Error::other)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes())
+}
+
+/// デーモンにリクエストを1つ送って応答を受け取る
+pub fn send_request(path: &Path, request: &Request) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    write_line(&mut stream, request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// 状態変化を購読する（デーモンが終了するとイテレータも終わる）
+pub fn subscribe(path: &Path) -> io::Result<impl Iterator<Item = io::Result<StateChange>>> {
+    let mut stream = UnixStream::connect(path)?;
+    write_line(&mut stream, &Request::Subscribe)?;
+
+    Ok(BufReader::new(stream).lines().map(|line| {
+        serde_json::from_str(&line?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(enabled: bool) -> Status {
+        Status {
+            enabled,
+            active: false,
+            touching: false,
+            zones: ExclusionZones::none(),
+            device: "Test Touchpad".to_string(),
+            backend: "uinput".to_string(),
+            config_error: None,
+            disconnected: false,
+        }
+    }
+
+    #[test]
+    fn socket_directory_must_be_private() {
+        let uid = unsafe { libc::getuid() };
+        let dir =
+            std::env::temp_dir().join(format!("way-thumbsense-private-{}", std::process::id()));
+        let _ = std::fs::remove_dir(&dir);
+
+        ensure_private_dir(&dir, uid).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // 既にあっても使える
+        ensure_private_dir(&dir, uid).unwrap();
+
+        // 他のユーザーが書き込めるディレクトリは使わない
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let err = ensure_private_dir(&dir, uid).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // 所有者の違うディレクトリも使わない
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(ensure_private_dir(&dir, uid + 1).is_err());
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn request_wire_format() {
+        assert_eq!(
+            serde_json::to_string(&Request::Toggle).unwrap(),
+            r#"{"cmd":"toggle"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"cmd":"set-zones","zones":{"bottom":20}}"#)
+                .unwrap(),
+            Request::SetZones {
+                zones: ExclusionZones::new(0.0, 20.0, 0.0, 0.0)
+            }
+        );
+    }
+
+    #[test]
+    fn disconnected_touchpad_takes_precedence() {
+        let mut status = status(false);
+        assert_eq!(status.state(), State::Disabled);
+        assert!(!serde_json::to_string(&status)
+            .unwrap()
+            .contains("disconnected"));
+
+        status.disconnected = true;
+        assert_eq!(status.state(), State::Disconnected);
+        let json = serde_json::to_string(&status).unwrap();
+        assert_eq!(serde_json::from_str::<Status>(&json).unwrap(), status);
+    }
+
+    #[test]
+    fn client_server_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-ctl-{}.sock", std::process::id()));
+        let server = ControlServer::bind(&path).unwrap();
+
+        // イベントループ役
+        let handle = thread::spawn(move || {
+            let mut enabled = true;
+            loop {
+                for pending in server.drain() {
+                    match pending.request {
+                        Request::Toggle => {
+                            enabled = !enabled;
+                            pending.reply(Response::ok(status(enabled)));
+                            return;
+                        }
+                        _ => pending.reply(Response::error("unexpected")),
+                    }
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let response = send_request(&path, &Request::Toggle).unwrap();
+        handle.join().unwrap();
+
+        assert!(response.ok);
+        assert_eq!(response.status, Some(status(false)));
+        assert!(
+            !path.exists(),
+            "socket is removed when the server is dropped"
+        );
+    }
+
+    #[test]
+    fn subscriber_receives_last_and_new_states() {
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-sub-{}.sock", std::process::id()));
+        let server = ControlServer::bind(&path).unwrap();
+        let change = |state| StateChange {
+            state,
+            device: "Test Touchpad".to_string(),
+        };
+
+        server.publish(change(State::Inactive));
+        let mut stream = subscribe(&path).unwrap();
+        assert_eq!(stream.next().unwrap().unwrap(), change(State::Inactive));
+
+        // 購読の登録を待ってから通知
+        while server.subscribers.senders.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        server.publish(change(State::Active));
+        assert_eq!(stream.next().unwrap().unwrap(), change(State::Active));
+    }
+
+    #[test]
+    fn waybar_output_format() {
+        let output = StateChange {
+            state: State::Excluded,
+            device: "Magic Trackpad".to_string(),
+        }
+        .to_waybar();
+
+        assert_eq!(
+            serde_json::to_value(&output).unwrap(),
+            serde_json::json!({
+                "text": "excluded",
+                "alt": "excluded",
+                "class": "excluded",
+                "tooltip": "ThumbSense: touch in exclusion zone\nMagic Trackpad",
+            })
+        );
+    }
+
+    #[test]
+    fn socket_is_owner_only() {
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-mode-{}.sock", std::process::id()));
+        let _server = ControlServer::bind(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn second_instance_cannot_bind() {
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-busy-{}.sock", std::process::id()));
+        let _server = ControlServer::bind(&path).unwrap();
+
+        let err = ControlServer::bind(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+}
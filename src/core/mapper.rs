@@ -1,19 +1,46 @@
 use super::types::*;
 
+/// デフォルトのキーマッピング（J → 左クリック, K → 右クリック）
+pub const DEFAULT_MAPPINGS: &[Mapping] = &[
+    Mapping {
+        key: KeyCode::J,
+        button: MouseButton::Left,
+    },
+    Mapping {
+        key: KeyCode::K,
+        button: MouseButton::Right,
+    },
+];
+
 /// キーイベントをタッチ状態に基づいて変換
 ///
 /// - タッチ中: J → 左クリック, K → 右クリック
 /// - 非タッチ: そのままパススルー
 pub fn map_key_event(event: KeyEvent, touch: &TouchState) -> OutputAction {
+    map_key_event_with(event, touch, DEFAULT_MAPPINGS)
+}
+
+/// 任意のマッピングテーブルでキーイベントを変換
+///
+/// テーブルにないキーはタッチ中でもパススルー
+pub fn map_key_event_with(
+    event: KeyEvent,
+    touch: &TouchState,
+    mappings: &[Mapping],
+) -> OutputAction {
     if !touch.is_touching() {
         return OutputAction::PassThrough(event);
     }
 
-    match event {
-        KeyEvent::Press(KeyCode::J) => OutputAction::MouseClick(MouseButton::Left),
-        KeyEvent::Release(KeyCode::J) => OutputAction::MouseRelease(MouseButton::Left),
-        KeyEvent::Press(KeyCode::K) => OutputAction::MouseClick(MouseButton::Right),
-        KeyEvent::Release(KeyCode::K) => OutputAction::MouseRelease(MouseButton::Right),
+    let (key, pressed) = match event {
+        KeyEvent::Press(key) => (key, true),
+        KeyEvent::Release(key) => (key, false),
+    };
+
+    match mappings.iter().find(|m| m.key == key) {
+        Some(m) if pressed => OutputAction::MouseClick(m.button),
+        Some(m) => OutputAction::MouseRelease(m.button),
+        None => OutputAction::PassThrough(event),
     }
 }
 
@@ -103,4 +130,31 @@ mod tests {
             OutputAction::MouseClick(MouseButton::Left)
         );
     }
+
+    // === カスタムマッピングのテスト ===
+
+    #[test]
+    fn custom_mapping_to_middle_click() {
+        let touch = TouchState { finger_count: 1 };
+        let mappings = [Mapping {
+            key: KeyCode(38), // KEY_L
+            button: MouseButton::Middle,
+        }];
+
+        assert_eq!(
+            map_key_event_with(KeyEvent::Press(KeyCode(38)), &touch, &mappings),
+            OutputAction::MouseClick(MouseButton::Middle)
+        );
+    }
+
+    #[test]
+    fn unmapped_key_passthrough_when_touching() {
+        let touch = TouchState { finger_count: 1 };
+        let event = KeyEvent::Press(KeyCode(30)); // KEY_A
+
+        assert_eq!(
+            map_key_event(event, &touch),
+            OutputAction::PassThrough(event)
+        );
+    }
 }
@@ -1,5 +1,5 @@
 pub mod mapper;
 pub mod types;
 
-pub use mapper::map_key_event;
+pub use mapper::{map_key_event, map_key_event_with, DEFAULT_MAPPINGS};
 pub use types::*;
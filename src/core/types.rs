@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 /// タッチパッドの状態
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct TouchState {
@@ -10,11 +12,13 @@ impl TouchState {
     }
 }
 
-/// キーコード
+/// キーコード（Linux input-event-codes.h の値）
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum KeyCode {
-    J,
-    K,
+pub struct KeyCode(pub u16);
+
+impl KeyCode {
+    pub const J: Self = Self(36);
+    pub const K: Self = Self(37);
 }
 
 /// 入力イベント（キーボードから）
@@ -25,10 +29,19 @@ pub enum KeyEvent {
 }
 
 /// マウスボタン
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MouseButton {
     Left,
     Right,
+    Middle,
+}
+
+/// キー → マウスボタンの対応
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub key: KeyCode,
+    pub button: MouseButton,
 }
 
 /// 出力アクション
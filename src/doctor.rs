@@ -0,0 +1,264 @@
+//! doctor サブコマンド用の診断結果とkeyd設定ファイルの解析
+
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// keydの設定ファイルを置くディレクトリ
+pub const KEYD_CONFIG_DIR: &str = "/etc/keyd";
+
+/// レイヤー名を第1引数に取るkeydのアクション
+const LAYER_ACTIONS: &[&str] = &[
+    "layer",
+    "layerm",
+    "oneshot",
+    "oneshotm",
+    "toggle",
+    "togglem",
+    "swap",
+    "swapm",
+    "overload",
+    "overloadt",
+    "overloadt2",
+];
+
+/// 1項目の判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    /// 動作はするが確認が必要
+    Warn,
+    Fail,
+    /// 設定上この項目は関係ない
+    Skip,
+}
+
+/// 1項目の診断結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+    /// 直し方
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Self::new(name, Status::Pass, message, None)
+    }
+
+    pub fn warn(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Warn, message, Some(hint.into()))
+    }
+
+    pub fn fail(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Fail, message, Some(hint.into()))
+    }
+
+    pub fn skip(name: &'static str, message: impl Into<String>) -> Self {
+        Self::new(name, Status::Skip, message, None)
+    }
+
+    fn new(
+        name: &'static str,
+        status: Status,
+        message: impl Into<String>,
+        hint: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            message: message.into(),
+            hint,
+        }
+    }
+}
+
+/// keydの設定ファイル1つを調べた結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeydConfigReport {
+    /// トリガーキーを割り当てたセクションとレイヤー
+    pub binding: Option<(String, String)>,
+    /// 割り当て先のレイヤーのセクションがあるか
+    pub layer_defined: bool,
+    /// `[ids]` が仮想キーボードに一致するか
+    pub matches_device: bool,
+}
+
+impl KeydConfigReport {
+    pub fn is_ok(&self) -> bool {
+        self.binding.is_some() && self.layer_defined && self.matches_device
+    }
+}
+
+/// keydの設定ファイルでトリガーキーがレイヤーに割り当てられているか調べる
+///
+/// `key` はkeydのキー名、`vendor` / `product` は仮想キーボードのID
+pub fn inspect_keyd_config(text: &str, key: &str, vendor: u16, product: u16) -> KeydConfigReport {
+    let sections = parse_sections(text);
+    let device_id = format!("{:04x}:{:04x}", vendor, product);
+
+    let mut matched = false;
+    let mut excluded = false;
+    for id in sections
+        .iter()
+        .filter(|(name, _)| name == "ids")
+        .flat_map(|(_, lines)| lines)
+    {
+        // keyd v2.5以降の k: / m: 接頭辞は読み飛ばす
+        let id = id.to_lowercase();
+        let id = id
+            .strip_prefix("k:")
+            .or_else(|| id.strip_prefix("m:"))
+            .unwrap_or(&id);
+        match id.strip_prefix('-') {
+            Some(negated) if negated == device_id => excluded = true,
+            Some(_) => {}
+            None if id == "*" || id == device_id => matched = true,
+            None => {}
+        }
+    }
+
+    let binding = sections
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "ids" | "aliases" | "global"))
+        .find_map(|(section, lines)| {
+            lines.iter().find_map(|line| {
+                let (lhs, rhs) = line.split_once('=')?;
+                if !lhs.trim().eq_ignore_ascii_case(key) {
+                    return None;
+                }
+                Some((section.clone(), layer_action(rhs.trim())?))
+            })
+        });
+
+    let layer_defined = binding
+        .as_ref()
+        .is_some_and(|(_, layer)| defines_layer(text, layer));
+
+    KeydConfigReport {
+        binding,
+        layer_defined,
+        matches_device: matched && !excluded,
+    }
+}
+
+/// `[layer]` または `[layer:C]` のセクションがあるか
+pub fn defines_layer(text: &str, layer: &str) -> bool {
+    parse_sections(text)
+        .iter()
+        .any(|(name, _)| name.split(':').next() == Some(layer))
+}
+
+/// `layer(nav)` や `overload(nav, esc)` からレイヤー名を取り出す
+fn layer_action(action: &str) -> Option<String> {
+    let (function, args) = action.split_once('(')?;
+    if !LAYER_ACTIONS.contains(&function.trim()) {
+        return None;
+    }
+    let layer = args.split([',', ')']).next()?.trim();
+    (!layer.is_empty()).then(|| layer.to_string())
+}
+
+/// セクション名と、その中の空行・コメントを除いた行
+fn parse_sections(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.trim().to_string(), Vec::new()));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line.to_string());
+        }
+    }
+    sections
+}
+
+/// `dir` の `*.conf` を名前順に読む
+pub fn read_keyd_configs(dir: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| fs::read_to_string(&path).map(|text| (path, text)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+[ids]
+*
+
+[main]
+# タッチパッドに触れている間だけ
+f24 = layer(mousenav)
+capslock = overload(control, esc)
+
+[mousenav:C]
+j = leftmouse
+";
+
+    #[test]
+    fn find_trigger_binding() {
+        let report = inspect_keyd_config(CONFIG, "f24", 0x0000, 0x0002);
+        assert_eq!(
+            report.binding,
+            Some(("main".to_string(), "mousenav".to_string()))
+        );
+        assert!(report.layer_defined);
+        assert!(report.matches_device);
+        assert!(report.is_ok());
+
+        let report = inspect_keyd_config(CONFIG, "capslock", 0x0000, 0x0002);
+        assert_eq!(
+            report.binding,
+            Some(("main".to_string(), "control".to_string()))
+        );
+        // controlは組み込みのレイヤーなのでセクションはない
+        assert!(!report.layer_defined);
+    }
+
+    #[test]
+    fn report_missing_binding_and_layer() {
+        let text = "[ids]\n*\n[main]\n# f24 = layer(mousenav)\nf24 = esc\n";
+        let report = inspect_keyd_config(text, "f24", 0, 2);
+        assert_eq!(report.binding, None);
+        assert!(!report.is_ok());
+
+        let text = "[ids]\n*\n[main]\nf24 = toggle(nav)\n";
+        let report = inspect_keyd_config(text, "f24", 0, 2);
+        assert_eq!(
+            report.binding,
+            Some(("main".to_string(), "nav".to_string()))
+        );
+        assert!(!report.layer_defined);
+    }
+
+    #[test]
+    fn match_virtual_keyboard_ids() {
+        let body = "[main]\nf24 = layer(nav)\n[nav]\n";
+        let matches = |ids: &str| {
+            inspect_keyd_config(&format!("[ids]\n{}\n{}", ids, body), "f24", 0x0000, 0x0002)
+                .matches_device
+        };
+        assert!(matches("*"));
+        assert!(matches("0000:0002"));
+        assert!(matches("k:0000:0002"));
+        assert!(!matches("046d:c52b"));
+        assert!(!matches("*\n-0000:0002"));
+    }
+}
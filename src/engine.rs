@@ -0,0 +1,588 @@
+//! タッチパッドのイベントからレイヤー切り替えを決める状態機械
+
+use crate::core::{
+    map_key_event_with, KeyEvent, Mapping, OutputAction, TouchState, DEFAULT_MAPPINGS,
+};
+use crate::input::frames::{self, Frame};
+use crate::input::TouchSnapshot;
+use crate::logging;
+use crate::tracker::{Edge, ExclusionZones, TouchTracker};
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key};
+use log::trace;
+
+/// エンジンが出す状態変化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchEvent {
+    /// 除外領域外でタッチ開始 → レイヤー有効化
+    Activated { x: Option<i32>, y: Option<i32> },
+    /// 有効化中のタッチ終了 → レイヤー無効化
+    Deactivated,
+    /// 除外領域内でタッチ開始（レイヤーは有効化しない）
+    Excluded { x: i32, y: i32, edge: Edge },
+    /// カーネルが手のひらと判定した接触（MT_TOOL_PALM）でタッチ開始
+    PalmRejected { x: Option<i32>, y: Option<i32> },
+}
+
+/// ABS_MT_TOOL_TYPE の手のひら
+const MT_TOOL_PALM: i32 = 2;
+
+/// MTスロットごとの接触
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    /// tracking IDが割り当てられている
+    active: bool,
+    /// 直近の ABS_MT_TOOL_TYPE が手のひらか（値が変わったときだけ届くので保持する）
+    palm: bool,
+}
+
+/// タッチ状態とレイヤー状態の追跡
+#[derive(Debug)]
+pub struct Engine {
+    tracker: TouchTracker,
+    is_touching: bool,
+    layer_active: bool,
+    enabled: bool,
+    mappings: Vec<Mapping>,
+    /// 現在のスロット（ABS_MT_SLOT）
+    slot: usize,
+    slots: Vec<Slot>,
+}
+
+impl Engine {
+    pub fn new(tracker: TouchTracker) -> Self {
+        Self {
+            tracker,
+            is_touching: false,
+            layer_active: false,
+            enabled: true,
+            mappings: DEFAULT_MAPPINGS.to_vec(),
+            slot: 0,
+            slots: vec![Slot::default()],
+        }
+    }
+
+    pub fn tracker(&self) -> &TouchTracker {
+        &self.tracker
+    }
+
+    /// レイヤーが有効化されているか
+    pub fn is_active(&self) -> bool {
+        self.layer_active
+    }
+
+    /// 指が触れているか
+    pub fn is_touching(&self) -> bool {
+        self.is_touching
+    }
+
+    /// ThumbSenseが有効か（無効中はタッチしてもレイヤーを有効化しない）
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 有効/無効を切り替える
+    ///
+    /// 有効化中に無効にした場合はレイヤーを解放する
+    pub fn set_enabled(&mut self, enabled: bool) -> Vec<TouchEvent> {
+        self.enabled = enabled;
+        if !enabled {
+            return self.release();
+        }
+        Vec::new()
+    }
+
+    /// 有効化中のレイヤーを解放（終了・再オープン時用）
+    pub fn release(&mut self) -> Vec<TouchEvent> {
+        if self.layer_active {
+            self.layer_active = false;
+            return vec![TouchEvent::Deactivated];
+        }
+        Vec::new()
+    }
+
+    /// キーマッピングを差し替え
+    pub fn set_mappings(&mut self, mappings: Vec<Mapping>) {
+        self.mappings = mappings;
+    }
+
+    /// 現在のタッチ状態でキーイベントを変換
+    ///
+    /// レイヤーが有効な間だけマッピングを適用する（除外領域のタッチではパススルー）
+    pub fn map_key(&self, event: KeyEvent) -> OutputAction {
+        let touch = TouchState {
+            finger_count: self.layer_active as u8,
+        };
+        let action = map_key_event_with(event, &touch, &self.mappings);
+        if log::log_enabled!(log::Level::Trace) {
+            // キーコードは logging::key で伏せる（PassThroughのDebug出力にも含まれる）
+            let (kind, code) = match event {
+                KeyEvent::Press(code) => ("press", code),
+                KeyEvent::Release(code) => ("release", code),
+            };
+            let result = match action {
+                OutputAction::MouseClick(button) => format!("click {:?}", button),
+                OutputAction::MouseRelease(button) => format!("release {:?}", button),
+                OutputAction::PassThrough(_) => "pass through".to_string(),
+            };
+            trace!(
+                "Key {} {} -> {} (layer {})",
+                kind,
+                logging::key(code.0),
+                result,
+                if self.layer_active {
+                    "active"
+                } else {
+                    "inactive"
+                }
+            );
+        }
+        action
+    }
+
+    /// 除外領域を差し替え（次のタッチ開始から適用）
+    pub fn set_exclusion_zones(&mut self, zones: ExclusionZones) {
+        self.tracker.set_exclusion_zones(zones);
+    }
+
+    /// fetch_eventsで得たイベント列をSYN_REPORTごとに処理し、状態変化を返す
+    ///
+    /// SYN_DROPPEDで取りこぼした区間は捨てる（デバイスの状態からの再同期は呼び出し側で行う）
+    pub fn process(&mut self, events: &[InputEvent]) -> Vec<TouchEvent> {
+        let mut out = Vec::new();
+        for frame in frames::split(events) {
+            match frame {
+                Frame::Events(frame) => out.extend(self.process_frame(&frame)),
+                Frame::Dropped => trace!("Dropped events skipped"),
+            }
+        }
+        out
+    }
+
+    /// デバイスから読んだ状態に合わせる（起動時・SYN_DROPPEDの後・再接続時）
+    ///
+    /// 取りこぼした離す変化が残らないよう、スロットの表は作り直す
+    pub fn resync(&mut self, snapshot: &TouchSnapshot) -> Vec<TouchEvent> {
+        self.slot = 0;
+        self.slots = vec![Slot::default()];
+        self.process_frame(&snapshot.to_frame())
+    }
+
+    /// SYN_REPORTまでの1フレームを処理し、状態変化を返す
+    ///
+    /// フレーム内の座標を反映してからBTN_TOUCHを見るので、
+    /// タッチ開始の除外判定にはそのフレームの座標を使う
+    pub fn process_frame(&mut self, events: &[InputEvent]) -> Vec<TouchEvent> {
+        let mut out = Vec::new();
+
+        // 1st pass: 座標を更新
+        for ev in events {
+            match ev.kind() {
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X)
+                | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                    self.tracker.update_x(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y)
+                | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                    self.tracker.update_y(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => {
+                    self.slot = ev.value().max(0) as usize;
+                    if self.slots.len() <= self.slot {
+                        self.slots.resize(self.slot + 1, Slot::default());
+                    }
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                    self.slots[self.slot].active = ev.value() >= 0;
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TOOL_TYPE) => {
+                    self.slots[self.slot].palm = ev.value() == MT_TOOL_PALM;
+                }
+                _ => {}
+            }
+        }
+
+        // 2nd pass: BTN_TOUCHを処理
+        for ev in events {
+            if ev.kind() != InputEventKind::Key(Key::BTN_TOUCH) {
+                continue;
+            }
+
+            let now_touching = ev.value() == 1;
+            if now_touching == self.is_touching {
+                continue;
+            }
+            self.is_touching = now_touching;
+
+            if now_touching && !self.enabled {
+                trace!("Touch start ignored (disabled)");
+                continue;
+            }
+
+            if now_touching {
+                // タッチ開始
+                let (x, y) = self.tracker.position();
+                if self.palm_only() {
+                    trace!("Touch start at ({:?}, {:?}) by palm -> rejected", x, y);
+                    out.push(TouchEvent::PalmRejected { x, y });
+                    continue;
+                }
+                match (self.tracker.excluded_edge(), x, y) {
+                    (Some(edge), Some(x), Some(y)) => {
+                        trace!(
+                            "Touch start at ({}, {}) in {} exclusion zone -> excluded",
+                            x,
+                            y,
+                            edge.as_str()
+                        );
+                        out.push(TouchEvent::Excluded { x, y, edge });
+                    }
+                    _ => {
+                        trace!("Touch start at ({:?}, {:?}) -> activate", x, y);
+                        self.layer_active = true;
+                        out.push(TouchEvent::Activated { x, y });
+                    }
+                }
+            } else {
+                // タッチ終了
+                if self.layer_active {
+                    trace!("Touch end -> deactivate");
+                    self.layer_active = false;
+                    out.push(TouchEvent::Deactivated);
+                } else {
+                    trace!("Touch end (layer was not active)");
+                }
+                self.tracker.reset();
+                for slot in &mut self.slots {
+                    slot.active = false;
+                }
+            }
+        }
+
+        out
+    }
+
+    /// 触れている接触がすべて手のひらか
+    ///
+    /// tracking IDを送らないデバイスは現在のスロットのツールの種類で判定する
+    fn palm_only(&self) -> bool {
+        let mut active = self.slots.iter().filter(|slot| slot.active).peekable();
+        if active.peek().is_none() {
+            return self.slots[self.slot].palm;
+        }
+        active.all(|slot| slot.palm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::TouchpadDimensions;
+    use crate::tracker::ExclusionZones;
+    use evdev::EventType;
+
+    fn engine() -> Engine {
+        let dims = TouchpadDimensions {
+            min_x: 0,
+            max_x: 1000,
+            min_y: 0,
+            max_y: 1000,
+        };
+        let zones = ExclusionZones::new(0.0, 20.0, 0.0, 0.0);
+        Engine::new(TouchTracker::new(dims, zones))
+    }
+
+    fn touch(x: i32, y: i32, value: i32) -> Vec<InputEvent> {
+        vec![
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, x),
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, y),
+            InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), value),
+            InputEvent::new(EventType::SYNCHRONIZATION, 0, 0),
+        ]
+    }
+
+    #[test]
+    fn touch_activates_and_release_deactivates() {
+        let mut engine = engine();
+
+        assert_eq!(
+            engine.process(&touch(500, 500, 1)),
+            vec![TouchEvent::Activated {
+                x: Some(500),
+                y: Some(500)
+            }]
+        );
+        assert!(engine.is_active());
+
+        assert_eq!(
+            engine.process(&touch(500, 500, 0)),
+            vec![TouchEvent::Deactivated]
+        );
+        assert!(!engine.is_active());
+    }
+
+    #[test]
+    fn touch_in_exclusion_zone_is_reported() {
+        let mut engine = engine();
+
+        assert_eq!(
+            engine.process(&touch(500, 900, 1)),
+            vec![TouchEvent::Excluded {
+                x: 500,
+                y: 900,
+                edge: Edge::Bottom
+            }]
+        );
+        assert!(!engine.is_active());
+
+        // 有効化していないので無効化も出さない
+        assert_eq!(engine.process(&touch(500, 900, 0)), vec![]);
+    }
+
+    #[test]
+    fn palm_touch_is_rejected() {
+        let mut engine = engine();
+        let tool = |value| {
+            InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_MT_TOOL_TYPE.0,
+                value,
+            )
+        };
+
+        let mut events = vec![tool(MT_TOOL_PALM)];
+        events.extend(touch(500, 500, 1));
+        assert_eq!(
+            engine.process(&events),
+            vec![TouchEvent::PalmRejected {
+                x: Some(500),
+                y: Some(500)
+            }]
+        );
+        assert_eq!(engine.process(&touch(500, 500, 0)), vec![]);
+
+        // ツールの種類は変化したときだけ届く
+        assert!(matches!(
+            engine.process(&touch(500, 500, 1))[..],
+            [TouchEvent::PalmRejected { .. }]
+        ));
+        engine.process(&touch(500, 500, 0));
+
+        let mut events = vec![tool(0)];
+        events.extend(touch(500, 500, 1));
+        assert!(matches!(
+            engine.process(&events)[..],
+            [TouchEvent::Activated { .. }]
+        ));
+    }
+
+    #[test]
+    fn palm_is_tracked_per_slot() {
+        let mut engine = engine();
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let contact = |slot, tracking_id, tool| {
+            vec![
+                abs(AbsoluteAxisType::ABS_MT_SLOT, slot),
+                abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, tracking_id),
+                abs(AbsoluteAxisType::ABS_MT_TOOL_TYPE, tool),
+            ]
+        };
+
+        // 手のひらと指が同時に触れたら指で有効化する
+        let mut events = contact(0, 1, MT_TOOL_PALM);
+        events.extend(contact(1, 2, 0));
+        events.extend(touch(500, 500, 1));
+        assert!(matches!(
+            engine.process(&events)[..],
+            [TouchEvent::Activated { .. }]
+        ));
+        let mut events = vec![
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 1),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+        ];
+        events.extend(touch(500, 500, 0));
+        assert_eq!(engine.process(&events), vec![TouchEvent::Deactivated]);
+
+        // スロット0の手のひらは、ツールの種類が届かないスロット1の指に影響しない
+        let mut events = vec![
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 1),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 3),
+        ];
+        events.extend(touch(500, 500, 1));
+        assert!(matches!(
+            engine.process(&events)[..],
+            [TouchEvent::Activated { .. }]
+        ));
+        let mut events = vec![abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1)];
+        events.extend(touch(500, 500, 0));
+        engine.process(&events);
+
+        // スロット0には手のひらの種類が残っている
+        let mut events = vec![
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 4),
+        ];
+        events.extend(touch(500, 500, 1));
+        assert!(matches!(
+            engine.process(&events)[..],
+            [TouchEvent::PalmRejected { .. }]
+        ));
+    }
+
+    #[test]
+    fn disable_releases_active_layer() {
+        let mut engine = engine();
+
+        engine.process(&touch(500, 500, 1));
+        assert_eq!(engine.set_enabled(false), vec![TouchEvent::Deactivated]);
+
+        // 無効中のタッチでは有効化しない
+        assert_eq!(engine.process(&touch(500, 500, 0)), vec![]);
+        assert_eq!(engine.process(&touch(500, 500, 1)), vec![]);
+
+        engine.process(&touch(500, 500, 0));
+        assert_eq!(engine.set_enabled(true), vec![]);
+        assert_eq!(engine.process(&touch(500, 500, 1)).len(), 1);
+    }
+
+    #[test]
+    fn map_key_follows_layer_state() {
+        use crate::core::{KeyCode, MouseButton};
+
+        let mut engine = engine();
+        let j = KeyEvent::Press(KeyCode::J);
+        assert_eq!(engine.map_key(j), OutputAction::PassThrough(j));
+
+        engine.process(&touch(500, 500, 1));
+        assert_eq!(
+            engine.map_key(j),
+            OutputAction::MouseClick(MouseButton::Left)
+        );
+
+        engine.set_mappings(vec![Mapping {
+            key: KeyCode::J,
+            button: MouseButton::Middle,
+        }]);
+        assert_eq!(
+            engine.map_key(j),
+            OutputAction::MouseClick(MouseButton::Middle)
+        );
+    }
+
+    #[test]
+    fn touch_start_uses_position_of_its_own_frame() {
+        let mut engine = engine();
+
+        // 1回の読み込みに触れた瞬間と、除外領域へ動いた後のフレームが入っている
+        let mut events = touch(500, 500, 1);
+        events.extend(touch(500, 900, 1));
+        assert_eq!(
+            engine.process(&events),
+            vec![TouchEvent::Activated {
+                x: Some(500),
+                y: Some(500)
+            }]
+        );
+    }
+
+    #[test]
+    fn snapshot_of_finger_already_on_pad() {
+        use crate::input::{Contact, TouchSnapshot};
+
+        let snapshot = |y, tool_type| TouchSnapshot {
+            touching: true,
+            tools: vec![Key::BTN_TOOL_FINGER],
+            contacts: vec![Contact {
+                slot: 0,
+                tracking_id: 1,
+                x: Some(500),
+                y: Some(y),
+                tool_type: Some(tool_type),
+            }],
+            ..Default::default()
+        };
+
+        let mut pad = engine();
+        assert_eq!(
+            pad.process_frame(&snapshot(500, 0).to_frame()),
+            vec![TouchEvent::Activated {
+                x: Some(500),
+                y: Some(500)
+            }]
+        );
+        // 離した変化は通常どおり届く
+        assert_eq!(
+            pad.process(&touch(500, 500, 0)),
+            vec![TouchEvent::Deactivated]
+        );
+
+        let mut pad = engine();
+        assert!(matches!(
+            pad.process_frame(&snapshot(900, 0).to_frame())[..],
+            [TouchEvent::Excluded { .. }]
+        ));
+
+        let mut pad = engine();
+        assert!(matches!(
+            pad.process_frame(&snapshot(500, MT_TOOL_PALM).to_frame())[..],
+            [TouchEvent::PalmRejected { .. }]
+        ));
+
+        // 触れていなければ何もしない
+        let mut pad = engine();
+        assert_eq!(
+            pad.process_frame(&TouchSnapshot::default().to_frame()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn dropped_events_are_skipped() {
+        use evdev::Synchronization;
+
+        let mut engine = engine();
+        let mut events = vec![InputEvent::new(
+            EventType::SYNCHRONIZATION,
+            Synchronization::SYN_DROPPED.0,
+            0,
+        )];
+        events.extend(touch(500, 500, 1));
+        assert_eq!(engine.process(&events), vec![]);
+        assert!(!engine.is_touching());
+    }
+
+    #[test]
+    fn resync_clears_contacts_lost_in_a_drop() {
+        let mut engine = engine();
+        let mut events = vec![
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_SLOT.0, 2),
+            InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_MT_TRACKING_ID.0,
+                7,
+            ),
+        ];
+        events.extend(touch(500, 500, 1));
+        assert!(engine.process(&events).len() == 1 && engine.is_active());
+
+        // 離した変化は取りこぼし、デバイスにはもう接触がない
+        assert_eq!(
+            engine.resync(&TouchSnapshot::default()),
+            vec![TouchEvent::Deactivated]
+        );
+        assert!(!engine.is_active());
+        assert!(!engine.is_touching());
+        assert!(engine.slots.iter().all(|slot| !slot.active && !slot.palm));
+        assert_eq!(engine.slot, 0);
+    }
+
+    #[test]
+    fn repeated_touch_state_is_ignored() {
+        let mut engine = engine();
+
+        engine.process(&touch(500, 500, 1));
+        assert_eq!(engine.process(&touch(510, 510, 1)), vec![]);
+    }
+}
@@ -0,0 +1,249 @@
+//! tokioのイベントループで待ち受ける入力デバイスとfd
+//!
+//! evdevのデバイスと、制御ソケット・シグナル・inotifyのfdを `AsyncFd` に登録し、
+//! 呼び出し側が1つの `tokio::select!` でタイマーと合わせて待つ。
+//! デバイスはSYN_REPORTごとのフレームにして返す
+//!
+//! ```ignore
+//! let mut touchpad = InputStream::new(device)?;
+//! let control = ReadableFd::new(server.wake_fd())?;
+//! loop {
+//!     tokio::select! {
+//!         frame = touchpad.next_frame() => { /* ... */ }
+//!         ready = control.readable() => { ready?; server.drain(); }
+//!         _ = tokio::time::sleep(timeout) => { /* ... */ }
+//!     }
+//! }
+//! ```
+//!
+//! どちらも待っている途中で捨ててよい（読んだイベントは次の呼び出しまで持ち越す）
+
+use crate::input::frames::{Frame, FrameSplitter};
+use crate::poll::wait_readable;
+use evdev::{Device, InputEvent};
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+
+/// evdevのデバイスをSYN_REPORTごとのフレームで読む
+pub struct InputStream {
+    device: AsyncFd<Device>,
+    splitter: FrameSplitter,
+    frames: VecDeque<Frame>,
+}
+
+impl InputStream {
+    /// デバイスをノンブロッキングにしてループに登録する
+    pub fn new(device: Device) -> io::Result<Self> {
+        set_nonblocking(device.as_raw_fd())?;
+        Ok(Self {
+            device: AsyncFd::new(device)?,
+            splitter: FrameSplitter::new(),
+            frames: VecDeque::new(),
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        self.device.get_ref()
+    }
+
+    pub fn device_mut(&mut self) -> &mut Device {
+        self.device.get_mut()
+    }
+
+    /// 次のフレームを待つ
+    ///
+    /// エラー（切断など）の後はこのデバイスを読めない
+    pub async fn next_frame(&mut self) -> io::Result<Frame> {
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                return Ok(frame);
+            }
+            let mut guard = self.device.readable().await?;
+            let events = match guard.try_io(|device| read_raw(device.as_raw_fd())) {
+                Ok(events) => events?,
+                // 読み切った（readinessはクリア済み）
+                Err(_would_block) => continue,
+            };
+            self.frames.extend(self.splitter.extend(events));
+        }
+    }
+}
+
+/// 読み込み可能になったことだけを通知するfd（読むのは呼び出し側）
+///
+/// fdはこれより長く開いている必要があり、ノンブロッキングで読み切れること
+pub struct ReadableFd {
+    fd: AsyncFd<RawFd>,
+}
+
+impl ReadableFd {
+    pub fn new(fd: RawFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// 読み込み可能になるまで待つ
+    ///
+    /// 読み残しがあれば次の呼び出しもすぐに返る
+    pub async fn readable(&self) -> io::Result<()> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            // epollは変化しか通知しないので、前回読み切ったかはpollで確かめる
+            if wait_readable(&[*self.fd.get_ref()], Some(Duration::ZERO))?[0] {
+                return Ok(());
+            }
+            guard.clear_ready();
+        }
+    }
+}
+
+/// `Option` の読み込み元を待つ（なければ待ち続ける。`select!` の分岐用）
+pub async fn maybe<T, F>(source: Option<T>, wait: impl FnOnce(T) -> F) -> F::Output
+where
+    F: std::future::Future,
+{
+    match source {
+        Some(source) => wait(source).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// カーネルのイベントをそのまま読む
+///
+/// `Device::fetch_events` はSYN_DROPPEDを隠して自前の差分で補うので、
+/// 取りこぼしを `Frame::Dropped` として扱えるよう生のイベントを使う
+fn read_raw(fd: RawFd) -> io::Result<Vec<InputEvent>> {
+    let mut buf = [unsafe { std::mem::zeroed::<libc::input_event>() }; 64];
+    let n = unsafe {
+        libc::read(
+            fd,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            std::mem::size_of_val(&buf),
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if n == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let count = n as usize / std::mem::size_of::<libc::input_event>();
+    Ok(buf[..count]
+        .iter()
+        .map(|&ev| InputEvent::from(ev))
+        .collect())
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// デーモン用のランタイム（シングルスレッド + I/O + タイマー）
+pub fn runtime() -> io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    fn stream_pair() -> (UnixStream, UnixStream) {
+        let (writer, reader) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        (writer, reader)
+    }
+
+    #[test]
+    fn readable_fds_are_multiplexed() {
+        runtime().unwrap().block_on(async {
+            let (mut a_writer, mut a) = stream_pair();
+            let (mut b_writer, mut b) = stream_pair();
+            let a_fd = ReadableFd::new(a.as_raw_fd()).unwrap();
+            let b_fd = ReadableFd::new(b.as_raw_fd()).unwrap();
+
+            b_writer.write_all(b"x").unwrap();
+            let ready = tokio::select! {
+                _ = a_fd.readable() => "a",
+                _ = b_fd.readable() => "b",
+            };
+            assert_eq!(ready, "b");
+            b.read_exact(&mut [0u8; 1]).unwrap();
+
+            a_writer.write_all(b"yz").unwrap();
+            let ready = tokio::select! {
+                _ = a_fd.readable() => "a",
+                _ = b_fd.readable() => "b",
+            };
+            assert_eq!(ready, "a");
+
+            // 読み残しがあれば再び通知される
+            a.read_exact(&mut [0u8; 1]).unwrap();
+            let next = tokio::time::timeout(Duration::from_secs(1), a_fd.readable()).await;
+            assert!(matches!(next, Ok(Ok(()))));
+
+            // 読み切ったら次に書かれるまで待つ
+            a.read_exact(&mut [0u8; 1]).unwrap();
+            let next = tokio::time::timeout(Duration::from_millis(50), a_fd.readable()).await;
+            assert!(next.is_err());
+        });
+    }
+
+    #[test]
+    fn timers_and_missing_sources_can_be_combined() {
+        runtime().unwrap().block_on(async {
+            let (_writer, reader) = stream_pair();
+            let fd = ReadableFd::new(reader.as_raw_fd()).unwrap();
+            let missing: Option<&ReadableFd> = None;
+
+            let timed_out = tokio::select! {
+                _ = fd.readable() => false,
+                _ = maybe(missing, ReadableFd::readable) => false,
+                _ = tokio::time::sleep(Duration::from_millis(20)) => true,
+            };
+            assert!(timed_out);
+        });
+    }
+
+    #[test]
+    fn raw_reads_keep_syn_dropped() {
+        use crate::input::frames::FrameSplitter;
+        use evdev::{EventType, Synchronization};
+
+        let syn = |code: Synchronization| {
+            let mut ev = unsafe { std::mem::zeroed::<libc::input_event>() };
+            ev.type_ = EventType::SYNCHRONIZATION.0;
+            ev.code = code.0;
+            ev
+        };
+        let events = [
+            syn(Synchronization::SYN_DROPPED),
+            syn(Synchronization::SYN_REPORT),
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(events.as_ptr() as *const u8, std::mem::size_of_val(&events))
+        };
+        let (mut writer, reader) = stream_pair();
+        writer.write_all(bytes).unwrap();
+
+        let read = read_raw(reader.as_raw_fd()).unwrap();
+        assert_eq!(read.len(), 2);
+        let frames = FrameSplitter::new().extend(read);
+        assert!(matches!(frames[..], [Frame::Dropped]));
+
+        // 読むものがなければWouldBlock
+        let err = read_raw(reader.as_raw_fd()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+}
@@ -0,0 +1,349 @@
+//! 状態変化時のシェルコマンド実行
+//!
+//! コマンドは別スレッドで実行し、イベントループを止めない
+
+use crate::config::HooksConfig;
+use crate::engine::TouchEvent;
+use crate::input::TouchpadDimensions;
+use log::warn;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 終了待ちのポーリング間隔
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// フックに渡す状況
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    /// タッチパッドのデバイス名
+    pub device: String,
+    pub dimensions: TouchpadDimensions,
+}
+
+/// フックの実行結果
+#[derive(Debug)]
+pub enum HookOutcome {
+    Exited(ExitStatus),
+    TimedOut,
+}
+
+/// フックコマンドの実行管理
+pub struct HookRunner {
+    config: HooksConfig,
+    context: HookContext,
+    running: Arc<AtomicUsize>,
+}
+
+impl HookRunner {
+    pub fn new(config: HooksConfig, context: HookContext) -> Self {
+        Self {
+            config,
+            context,
+            running: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// 設定とデバイスの情報を差し替える
+    ///
+    /// 実行中のコマンドは数え続ける（再読み込みを繰り返しても同時実行数の上限を超えない）
+    pub fn reconfigure(&mut self, config: HooksConfig, context: HookContext) {
+        self.config = config;
+        self.context = context;
+    }
+
+    /// 実行中のコマンド数
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// 状態変化に対応するフックを非同期に実行
+    ///
+    /// 同時実行数の上限に達している場合は実行せず false を返す
+    pub fn fire(&self, event: &TouchEvent) -> bool {
+        let Some(command) = self.command_for(event) else {
+            return true;
+        };
+        self.spawn(command, self.env_for(event)).is_some()
+    }
+
+    fn command_for(&self, event: &TouchEvent) -> Option<&str> {
+        match event {
+            TouchEvent::Activated { .. } => self.config.on_activate.as_deref(),
+            TouchEvent::Deactivated => self.config.on_deactivate.as_deref(),
+            // 手のひらも除外と同じく on_excluded（WAY_THUMBSENSE_EVENT で区別できる）
+            TouchEvent::Excluded { .. } | TouchEvent::PalmRejected { .. } => {
+                self.config.on_excluded.as_deref()
+            }
+        }
+    }
+
+    /// フックに渡す環境変数
+    fn env_for(&self, event: &TouchEvent) -> Vec<(&'static str, String)> {
+        let (name, x, y, zone) = match *event {
+            TouchEvent::Activated { x, y } => ("activate", x, y, None),
+            TouchEvent::Deactivated => ("deactivate", None, None, None),
+            TouchEvent::Excluded { x, y, edge } => ("excluded", Some(x), Some(y), Some(edge)),
+            TouchEvent::PalmRejected { x, y } => ("palm", x, y, None),
+        };
+        let dims = &self.context.dimensions;
+
+        let mut env = vec![
+            ("WAY_THUMBSENSE_EVENT", name.to_string()),
+            ("WAY_THUMBSENSE_DEVICE", self.context.device.clone()),
+            (
+                "WAY_THUMBSENSE_ZONE",
+                zone.map_or("none", |e| e.as_str()).to_string(),
+            ),
+        ];
+        if let Some(x) = x {
+            env.push(("WAY_THUMBSENSE_X", x.to_string()));
+            env.push((
+                "WAY_THUMBSENSE_X_PERCENT",
+                percent(x, dims.min_x, dims.width()),
+            ));
+        }
+        if let Some(y) = y {
+            env.push(("WAY_THUMBSENSE_Y", y.to_string()));
+            env.push((
+                "WAY_THUMBSENSE_Y_PERCENT",
+                percent(y, dims.min_y, dims.height()),
+            ));
+        }
+        env
+    }
+
+    fn spawn(
+        &self,
+        command: &str,
+        env: Vec<(&'static str, String)>,
+    ) -> Option<JoinHandle<io::Result<HookOutcome>>> {
+        let limit = self.config.max_concurrent;
+        let acquired = self
+            .running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < limit).then_some(n + 1)
+            })
+            .is_ok();
+        if !acquired {
+            warn!("Hook skipped (too many running): {}", command);
+            return None;
+        }
+
+        let command = command.to_string();
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let running = Arc::clone(&self.running);
+
+        Some(thread::spawn(move || {
+            let result = run_with_timeout(&command, &env, timeout);
+            match &result {
+                Ok(HookOutcome::TimedOut) => warn!("Hook timed out: {}", command),
+                Ok(HookOutcome::Exited(status)) if !status.success() => {
+                    warn!("Hook failed ({}): {}", status, command)
+                }
+                Err(e) => warn!("Hook error: {}: {}", command, e),
+                _ => {}
+            }
+            running.fetch_sub(1, Ordering::SeqCst);
+            result
+        }))
+    }
+}
+
+/// `sh -c` でコマンドを実行し、タイムアウトしたら強制終了
+///
+/// フックは専用のプロセスグループで動かし、`sh` が終わってもグループ内の子孫
+/// （`sleep 5; notify-send ...` のバックグラウンド実行など）が残っている間は実行中とみなす。
+/// タイムアウトしたらグループごと終了させる
+fn run_with_timeout(
+    command: &str,
+    env: &[(&'static str, String)],
+    timeout: Duration,
+) -> io::Result<HookOutcome> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    let group = child.id() as libc::pid_t;
+
+    let deadline = Instant::now() + timeout;
+    let mut status = None;
+    loop {
+        if status.is_none() {
+            status = child.try_wait()?;
+        }
+        if let Some(status) = status.filter(|_| !group_alive(group)) {
+            return Ok(HookOutcome::Exited(status));
+        }
+        if Instant::now() >= deadline {
+            unsafe { libc::killpg(group, libc::SIGKILL) };
+            if status.is_none() {
+                child.wait()?;
+            }
+            return Ok(HookOutcome::TimedOut);
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+/// プロセスグループにまだプロセスが残っているか
+fn group_alive(group: libc::pid_t) -> bool {
+    unsafe { libc::kill(-group, 0) == 0 }
+}
+
+/// 座標を範囲内の割合（0-100）で表す
+fn percent(value: i32, min: i32, range: i32) -> String {
+    if range == 0 {
+        return "0".to_string();
+    }
+    format!("{:.1}", (value - min) as f32 * 100.0 / range as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::Edge;
+
+    fn runner(config: HooksConfig) -> HookRunner {
+        HookRunner::new(
+            config,
+            HookContext {
+                device: "Test Touchpad".to_string(),
+                dimensions: TouchpadDimensions {
+                    min_x: 0,
+                    max_x: 1000,
+                    min_y: 0,
+                    max_y: 500,
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn env_describes_excluded_touch() {
+        let runner = runner(HooksConfig::default());
+        let env = runner.env_for(&TouchEvent::Excluded {
+            x: 250,
+            y: 450,
+            edge: Edge::Bottom,
+        });
+        let get = |key: &str| env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("WAY_THUMBSENSE_EVENT"), Some("excluded"));
+        assert_eq!(get("WAY_THUMBSENSE_DEVICE"), Some("Test Touchpad"));
+        assert_eq!(get("WAY_THUMBSENSE_ZONE"), Some("bottom"));
+        assert_eq!(get("WAY_THUMBSENSE_X"), Some("250"));
+        assert_eq!(get("WAY_THUMBSENSE_X_PERCENT"), Some("25.0"));
+        assert_eq!(get("WAY_THUMBSENSE_Y_PERCENT"), Some("90.0"));
+    }
+
+    #[test]
+    fn hook_receives_environment() {
+        let out = std::env::temp_dir().join(format!("way-thumbsense-hook-{}", std::process::id()));
+        let runner = runner(HooksConfig::default());
+
+        let handle = runner
+            .spawn(
+                &format!("echo \"$WAY_THUMBSENSE_EVENT\" > {}", out.display()),
+                runner.env_for(&TouchEvent::Deactivated),
+            )
+            .unwrap();
+        let outcome = handle.join().unwrap().unwrap();
+
+        assert!(matches!(outcome, HookOutcome::Exited(s) if s.success()));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "deactivate\n");
+        assert_eq!(runner.running(), 0);
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn slow_hook_is_killed_after_timeout() {
+        let runner = runner(HooksConfig {
+            timeout_ms: 50,
+            ..HooksConfig::default()
+        });
+
+        let started = Instant::now();
+        let handle = runner.spawn("sleep 5", Vec::new()).unwrap();
+        let outcome = handle.join().unwrap().unwrap();
+
+        assert!(matches!(outcome, HookOutcome::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn background_children_are_killed_with_the_hook() {
+        let runner = runner(HooksConfig {
+            timeout_ms: 100,
+            ..HooksConfig::default()
+        });
+        let pid_file =
+            std::env::temp_dir().join(format!("way-thumbsense-hook-pid-{}", std::process::id()));
+
+        // shはすぐに終わるが、バックグラウンドのsleepが残る
+        let handle = runner
+            .spawn(
+                &format!("sleep 5 & echo $! > {}", pid_file.display()),
+                Vec::new(),
+            )
+            .unwrap();
+        assert_eq!(runner.running(), 1);
+        let outcome = handle.join().unwrap().unwrap();
+        assert!(matches!(outcome, HookOutcome::TimedOut));
+        assert_eq!(runner.running(), 0);
+
+        // 親のいなくなったsleepは回収されずにゾンビとして残ることがある
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()));
+        assert!(stat.map_or(true, |stat| stat.contains(") Z ")));
+        std::fs::remove_file(pid_file).unwrap();
+    }
+
+    #[test]
+    fn concurrency_limit_skips_extra_hooks() {
+        let runner = runner(HooksConfig {
+            timeout_ms: 1000,
+            max_concurrent: 1,
+            ..HooksConfig::default()
+        });
+
+        let first = runner.spawn("sleep 0.2", Vec::new()).unwrap();
+        assert!(runner.spawn("true", Vec::new()).is_none());
+
+        first.join().unwrap().unwrap();
+        assert!(runner.spawn("true", Vec::new()).is_some());
+    }
+
+    #[test]
+    fn reconfigure_keeps_counting_running_hooks() {
+        let mut runner = runner(HooksConfig {
+            timeout_ms: 1000,
+            max_concurrent: 1,
+            ..HooksConfig::default()
+        });
+        let first = runner.spawn("sleep 0.2", Vec::new()).unwrap();
+
+        let context = runner.context.clone();
+        runner.reconfigure(
+            HooksConfig {
+                timeout_ms: 1000,
+                max_concurrent: 1,
+                on_activate: Some("true".to_string()),
+                ..HooksConfig::default()
+            },
+            context,
+        );
+        assert_eq!(runner.running(), 1);
+        assert!(runner.spawn("true", Vec::new()).is_none());
+
+        first.join().unwrap().unwrap();
+        assert!(runner.spawn("true", Vec::new()).is_some());
+    }
+}
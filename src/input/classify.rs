@@ -0,0 +1,299 @@
+//! 入力デバイスの種類の判定
+//!
+//! udevの input_id と同じく、入力プロパティ・BTN_TOOL_*・MT軸から
+//! タッチパッドとタッチスクリーン・ペンタブレット・ゲームパッドを区別する
+
+use evdev::{AbsoluteAxisType, Device, Key, PropType, RelativeAxisType};
+use serde::Serialize;
+
+/// タッチパッドを持つゲームパッドのベンダー（Sony, Valve）
+///
+/// DualShock 4 / DualSense のタッチパッドは独立したイベントノードで、
+/// 能力だけではクリックパッドと区別できない
+const GAMEPAD_VENDORS: &[u16] = &[0x054c, 0x28de];
+
+/// デバイスの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Touchpad,
+    /// パッド自体が押し込めるタッチパッド（INPUT_PROP_BUTTONPAD）
+    Clickpad,
+    Touchscreen,
+    /// ペンタブレット
+    Tablet,
+    Keyboard,
+    Mouse,
+    /// ゲームパッド・ジョイスティック（付属のタッチパッドを含む）
+    Joystick,
+    Other,
+}
+
+impl DeviceKind {
+    /// タッチパッドの自動検出の対象か
+    pub fn is_touchpad(&self) -> bool {
+        matches!(self, DeviceKind::Touchpad | DeviceKind::Clickpad)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceKind::Touchpad => "touchpad",
+            DeviceKind::Clickpad => "clickpad",
+            DeviceKind::Touchscreen => "touchscreen",
+            DeviceKind::Tablet => "tablet",
+            DeviceKind::Keyboard => "keyboard",
+            DeviceKind::Mouse => "mouse",
+            DeviceKind::Joystick => "joystick",
+            DeviceKind::Other => "other",
+        }
+    }
+}
+
+/// 判定に使うデバイスの能力
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub keys: Vec<Key>,
+    pub abs: Vec<AbsoluteAxisType>,
+    pub rel: Vec<RelativeAxisType>,
+    pub props: Vec<PropType>,
+    pub vendor: u16,
+}
+
+impl Capabilities {
+    pub fn from_device(device: &Device) -> Self {
+        Self {
+            keys: device
+                .supported_keys()
+                .map(|keys| keys.iter().collect())
+                .unwrap_or_default(),
+            abs: device
+                .supported_absolute_axes()
+                .map(|axes| axes.iter().collect())
+                .unwrap_or_default(),
+            rel: device
+                .supported_relative_axes()
+                .map(|axes| axes.iter().collect())
+                .unwrap_or_default(),
+            props: device.properties().iter().collect(),
+            vendor: device.input_id().vendor(),
+        }
+    }
+
+    fn has_key(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+
+    fn has_abs(&self, axis: AbsoluteAxisType) -> bool {
+        self.abs.contains(&axis)
+    }
+
+    fn has_prop(&self, prop: PropType) -> bool {
+        self.props.contains(&prop)
+    }
+}
+
+/// 能力からデバイスの種類を決める
+///
+/// `find_touchpad` / `find_keyboard` / list-devices はすべてこれを使う（udevの情報があればそちらが優先）
+#[derive(Debug, Clone)]
+pub struct DeviceClassifier {
+    caps: Capabilities,
+}
+
+impl DeviceClassifier {
+    pub fn new(caps: Capabilities) -> Self {
+        Self { caps }
+    }
+
+    pub fn from_device(device: &Device) -> Self {
+        Self::new(Capabilities::from_device(device))
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.caps
+    }
+
+    pub fn kind(&self) -> DeviceKind {
+        if self.is_tablet() {
+            DeviceKind::Tablet
+        } else if self.is_joystick() {
+            DeviceKind::Joystick
+        } else if self.is_touch_surface() {
+            if self.caps.has_prop(PropType::DIRECT) || !self.is_indirect_touch() {
+                DeviceKind::Touchscreen
+            } else if self.caps.has_prop(PropType::BUTTONPAD) {
+                DeviceKind::Clickpad
+            } else {
+                DeviceKind::Touchpad
+            }
+        } else if self.has_keyboard_keys() {
+            DeviceKind::Keyboard
+        } else if self.is_mouse() {
+            DeviceKind::Mouse
+        } else {
+            DeviceKind::Other
+        }
+    }
+
+    /// タッチパッドの自動検出の対象か
+    pub fn is_touchpad(&self) -> bool {
+        self.kind().is_touchpad()
+    }
+
+    /// キーボードの自動検出の対象か
+    pub fn is_keyboard(&self) -> bool {
+        self.kind() == DeviceKind::Keyboard
+    }
+
+    /// KEY_JとKEY_Aを持つ
+    fn has_keyboard_keys(&self) -> bool {
+        self.caps.has_key(Key::KEY_J) && self.caps.has_key(Key::KEY_A)
+    }
+
+    /// BTN_TOUCHと座標軸（単一またはMT）を持つ
+    fn is_touch_surface(&self) -> bool {
+        let single = self.caps.has_abs(AbsoluteAxisType::ABS_X)
+            && self.caps.has_abs(AbsoluteAxisType::ABS_Y);
+        let multi = self.caps.has_abs(AbsoluteAxisType::ABS_MT_POSITION_X)
+            && self.caps.has_abs(AbsoluteAxisType::ABS_MT_POSITION_Y);
+        self.caps.has_key(Key::BTN_TOUCH) && (single || multi)
+    }
+
+    /// カーソルを動かす面か（プロパティがない古いドライバはBTN_TOOL_FINGERで判断）
+    fn is_indirect_touch(&self) -> bool {
+        self.caps.has_prop(PropType::POINTER) || self.caps.has_key(Key::BTN_TOOL_FINGER)
+    }
+
+    fn is_tablet(&self) -> bool {
+        [Key::BTN_TOOL_PEN, Key::BTN_TOOL_RUBBER, Key::BTN_STYLUS]
+            .iter()
+            .any(|&k| self.caps.has_key(k))
+    }
+
+    fn is_joystick(&self) -> bool {
+        // BTN_JOYSTICK (0x120) から BTN_THUMBR (0x13e) まで
+        let buttons = self
+            .caps
+            .keys
+            .iter()
+            .any(|k| (Key::BTN_TRIGGER.code()..=Key::BTN_THUMBR.code()).contains(&k.code()));
+        buttons || (self.is_touch_surface() && GAMEPAD_VENDORS.contains(&self.caps.vendor))
+    }
+
+    fn is_mouse(&self) -> bool {
+        self.caps.has_key(Key::BTN_LEFT)
+            && self.caps.rel.contains(&RelativeAxisType::REL_X)
+            && self.caps.rel.contains(&RelativeAxisType::REL_Y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::evemu::EvemuDevice;
+    use AbsoluteAxisType as Abs;
+
+    const MT_AXES: &[Abs] = &[
+        Abs::ABS_X,
+        Abs::ABS_Y,
+        Abs::ABS_MT_SLOT,
+        Abs::ABS_MT_POSITION_X,
+        Abs::ABS_MT_POSITION_Y,
+        Abs::ABS_MT_TRACKING_ID,
+    ];
+
+    fn kind(keys: &[Key], abs: &[Abs], props: &[PropType], vendor: u16) -> DeviceKind {
+        DeviceClassifier::new(Capabilities {
+            keys: keys.to_vec(),
+            abs: abs.to_vec(),
+            rel: Vec::new(),
+            props: props.to_vec(),
+            vendor,
+        })
+        .kind()
+    }
+
+    #[test]
+    fn recorded_clickpad() {
+        let device =
+            EvemuDevice::parse(include_str!("../../tests/fixtures/touchpad.evemu")).unwrap();
+        assert_eq!(
+            DeviceClassifier::new(device.capabilities()).kind(),
+            DeviceKind::Clickpad
+        );
+    }
+
+    #[test]
+    fn touch_surfaces() {
+        let finger = [Key::BTN_TOUCH, Key::BTN_TOOL_FINGER, Key::BTN_LEFT];
+
+        // プロパティのない古いPS/2タッチパッド
+        assert_eq!(
+            kind(&finger, &[Abs::ABS_X, Abs::ABS_Y], &[], 0x0002),
+            DeviceKind::Touchpad
+        );
+        assert_eq!(
+            kind(&finger, MT_AXES, &[PropType::POINTER], 0x05ac),
+            DeviceKind::Touchpad
+        );
+        // タッチスクリーン（INPUT_PROP_DIRECT、またはBTN_TOOL_FINGERなし）
+        assert_eq!(
+            kind(&[Key::BTN_TOUCH], MT_AXES, &[PropType::DIRECT], 0x04f3),
+            DeviceKind::Touchscreen
+        );
+        assert_eq!(
+            kind(&[Key::BTN_TOUCH], &[Abs::ABS_X, Abs::ABS_Y], &[], 0x0eef),
+            DeviceKind::Touchscreen
+        );
+        // DualShock 4 のタッチパッドはクリックパッドと同じ能力を持つ
+        assert_eq!(
+            kind(
+                &finger,
+                MT_AXES,
+                &[PropType::POINTER, PropType::BUTTONPAD],
+                0x054c
+            ),
+            DeviceKind::Joystick
+        );
+    }
+
+    #[test]
+    fn tablets_and_gamepads() {
+        let pen = [
+            Key::BTN_TOUCH,
+            Key::BTN_TOOL_PEN,
+            Key::BTN_TOOL_RUBBER,
+            Key::BTN_STYLUS,
+        ];
+        let pen_axes = [Abs::ABS_X, Abs::ABS_Y, Abs::ABS_PRESSURE];
+        assert_eq!(kind(&pen, &pen_axes, &[], 0x056a), DeviceKind::Tablet);
+        assert_eq!(
+            kind(&pen, &pen_axes, &[PropType::DIRECT], 0x056a),
+            DeviceKind::Tablet
+        );
+
+        let gamepad = [Key::BTN_SOUTH, Key::BTN_EAST, Key::BTN_START];
+        assert_eq!(
+            kind(&gamepad, &[Abs::ABS_X, Abs::ABS_Y], &[], 0x045e),
+            DeviceKind::Joystick
+        );
+    }
+
+    #[test]
+    fn keyboards_and_mice() {
+        assert_eq!(
+            kind(&[Key::KEY_A, Key::KEY_J], &[], &[], 0x0001),
+            DeviceKind::Keyboard
+        );
+        // 自分の仮想キーボードはF24しか持たない
+        assert_eq!(kind(&[Key::KEY_F24], &[], &[], 0), DeviceKind::Other);
+
+        let mouse = DeviceClassifier::new(Capabilities {
+            keys: vec![Key::BTN_LEFT, Key::BTN_RIGHT],
+            rel: vec![RelativeAxisType::REL_X, RelativeAxisType::REL_Y],
+            ..Default::default()
+        });
+        assert_eq!(mouse.kind(), DeviceKind::Mouse);
+        assert!(!mouse.is_touchpad());
+    }
+}
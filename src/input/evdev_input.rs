@@ -1,16 +1,15 @@
 //! evdevデバイスの検出と読み取り
 
-use evdev::{AbsoluteAxisType, Device, EventType, Key};
+use super::inventory::{self, Role};
+use super::udev::{DeviceSelector, SysRoot};
+use evdev::{AbsoluteAxisType, Device, EventType};
+use serde::{Deserialize, Serialize};
 use std::fs;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum DeviceType {
-    Touchpad,
-    Keyboard,
-}
+use std::path::Path;
 
 /// タッチパッドの寸法情報
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TouchpadDimensions {
     pub min_x: i32,
     pub max_x: i32,
@@ -45,97 +44,114 @@ pub fn get_touchpad_dimensions(device: &Device) -> Option<TouchpadDimensions> {
     })
 }
 
-/// デバイス名の一部を指定してデバイスを検索
-pub fn find_device(name_contains: &str) -> Result<Device, FindDeviceError> {
-    for entry in fs::read_dir("/dev/input").map_err(|_| FindDeviceError::CannotReadInputDir)? {
-        let entry = entry.map_err(|_| FindDeviceError::CannotReadInputDir)?;
-        let path = entry.path();
+/// keydが作る仮想キーボードの名前（find_keyboardで優先する）
+pub const KEYD_KEYBOARD: &str = "keyd virtual keyboard";
 
-        if !path.to_string_lossy().contains("event") {
-            continue;
-        }
+/// 自分自身が作成した仮想デバイスか（タッチパッドの自動検出から除外）
+pub fn is_own_device(name: &str) -> bool {
+    name.contains("way-thumbsense")
+}
 
-        if let Ok(device) = Device::open(&path) {
-            if let Some(name) = device.name() {
-                if name.contains(name_contains) {
-                    if device.supported_events().contains(EventType::KEY) {
-                        return Ok(device);
-                    }
-                }
-            }
-        }
-    }
+/// デバイスを検索（名前の一部、またはudevのプロパティ。`DeviceSelector` を参照）
+pub fn find_device(selector: &str) -> Result<Device, FindDeviceError> {
+    find_device_in(
+        Path::new("/dev/input"),
+        &SysRoot::default(),
+        &DeviceSelector::parse(selector),
+    )
+}
 
-    Err(FindDeviceError::NotFound(name_contains.to_string()))
+/// `find_device` と同じだが、`exclude` がtrueを返すイベントノードは選ばない
+///
+/// 自分の仮想デバイスを（名前ではなくノードで）読まないように使う
+pub fn find_device_except(
+    selector: &str,
+    exclude: impl Fn(&Path) -> bool,
+) -> Result<Device, FindDeviceError> {
+    find_matching(
+        Path::new("/dev/input"),
+        &SysRoot::default(),
+        &DeviceSelector::parse(selector),
+        exclude,
+    )
 }
 
-/// BTN_TOUCH対応のタッチパッドを自動検出
-pub fn find_touchpad() -> Result<Device, FindDeviceError> {
-    for entry in fs::read_dir("/dev/input").map_err(|_| FindDeviceError::CannotReadInputDir)? {
+/// `dir` のイベントノードから、指定に一致するキーを持つデバイスを検索
+pub fn find_device_in(
+    dir: &Path,
+    root: &SysRoot,
+    selector: &DeviceSelector,
+) -> Result<Device, FindDeviceError> {
+    find_matching(dir, root, selector, |_| false)
+}
+
+fn find_matching(
+    dir: &Path,
+    root: &SysRoot,
+    selector: &DeviceSelector,
+    exclude: impl Fn(&Path) -> bool,
+) -> Result<Device, FindDeviceError> {
+    for entry in fs::read_dir(dir).map_err(|_| FindDeviceError::CannotReadInputDir)? {
         let entry = entry.map_err(|_| FindDeviceError::CannotReadInputDir)?;
         let path = entry.path();
 
-        if !path.to_string_lossy().contains("event") {
+        if !path.to_string_lossy().contains("event") || exclude(&path) {
             continue;
         }
 
         if let Ok(device) = Device::open(&path) {
-            // 自分自身の仮想デバイスを除外
-            if let Some(name) = device.name() {
-                if name.contains("way-thumbsense") {
-                    continue;
-                }
+            if !device.supported_events().contains(EventType::KEY) {
+                continue;
             }
-
-            // BTN_TOUCHとABS_X/ABS_Yを持つデバイスのみ（実際のタッチパッド）
-            let has_btn_touch = device
-                .supported_keys()
-                .map(|keys| keys.contains(Key::BTN_TOUCH))
-                .unwrap_or(false);
-
-            let has_abs_axes = device
-                .supported_absolute_axes()
-                .map(|axes| {
-                    axes.contains(AbsoluteAxisType::ABS_X)
-                        && axes.contains(AbsoluteAxisType::ABS_Y)
-                })
-                .unwrap_or(false);
-
-            if has_btn_touch && has_abs_axes {
+            let metadata = if selector.needs_metadata() {
+                root.metadata(&path)
+            } else {
+                None
+            };
+            if selector.matches(device.name(), metadata.as_ref()) {
                 return Ok(device);
             }
         }
     }
 
-    Err(FindDeviceError::NotFound("touchpad with BTN_TOUCH".to_string()))
+    Err(FindDeviceError::NotFound(selector.to_string()))
 }
 
-/// キーボードを自動検出（KEY_J対応デバイス）
-pub fn find_keyboard() -> Result<Device, FindDeviceError> {
-    // 優先順位: keyd virtual keyboard > 物理キーボード
-    if let Ok(device) = find_device("keyd virtual keyboard") {
-        return Ok(device);
-    }
+/// タッチパッドを自動検出（タッチスクリーン・ペンタブレット・ゲームパッドは除く）
+pub fn find_touchpad() -> Result<Device, FindDeviceError> {
+    find_touchpad_in(Path::new("/dev/input"), &SysRoot::default())
+}
 
-    // keydがない場合はKEY_J対応のキーボードを探す
-    for entry in fs::read_dir("/dev/input").map_err(|_| FindDeviceError::CannotReadInputDir)? {
-        let entry = entry.map_err(|_| FindDeviceError::CannotReadInputDir)?;
-        let path = entry.path();
+/// `dir` のイベントノードからタッチパッドを自動検出
+///
+/// udevの `ID_INPUT_TOUCHPAD` があればそれに従う（選び方は `inventory::select` と同じ）
+pub fn find_touchpad_in(dir: &Path, root: &SysRoot) -> Result<Device, FindDeviceError> {
+    find_selected(dir, root, Role::Touchpad)?
+        .ok_or_else(|| FindDeviceError::NotFound("touchpad".to_string()))
+}
 
-        if !path.to_string_lossy().contains("event") {
-            continue;
-        }
+/// キーボードを自動検出（keydの仮想キーボード、なければKEY_JとKEY_Aを持つデバイス）
+pub fn find_keyboard() -> Result<Device, FindDeviceError> {
+    find_keyboard_in(Path::new("/dev/input"), &SysRoot::default())
+}
 
-        if let Ok(device) = Device::open(&path) {
-            if let Some(keys) = device.supported_keys() {
-                if keys.contains(Key::KEY_J) && keys.contains(Key::KEY_A) {
-                    return Ok(device);
-                }
-            }
-        }
-    }
+/// `dir` のイベントノードからキーボードを自動検出
+///
+/// udevの `ID_INPUT_KEYBOARD` があればそれに従う（選び方は `inventory::select` と同じ）
+pub fn find_keyboard_in(dir: &Path, root: &SysRoot) -> Result<Device, FindDeviceError> {
+    find_selected(dir, root, Role::Keyboard)?
+        .ok_or_else(|| FindDeviceError::NotFound("keyboard with KEY_J".to_string()))
+}
 
-    Err(FindDeviceError::NotFound("keyboard with KEY_J".to_string()))
+/// list-devices と同じ一覧から `role` に選ばれたデバイスを開く
+fn find_selected(
+    dir: &Path,
+    root: &SysRoot,
+    role: Role,
+) -> Result<Option<Device>, FindDeviceError> {
+    let reports =
+        inventory::scan(dir, root, None).map_err(|_| FindDeviceError::CannotReadInputDir)?;
+    Ok(inventory::selected(&reports, role).and_then(|report| Device::open(&report.path).ok()))
 }
 
 #[derive(Debug)]
@@ -148,7 +164,7 @@ impl std::fmt::Display for FindDeviceError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FindDeviceError::CannotReadInputDir => write!(f, "Cannot read /dev/input directory"),
-            FindDeviceError::NotFound(name) => write!(f, "Device '{}' not found", name),
+            FindDeviceError::NotFound(what) => write!(f, "Device not found ({})", what),
         }
     }
 }
@@ -159,6 +175,20 @@ impl std::error::Error for FindDeviceError {}
 mod tests {
     use super::*;
 
+    #[test]
+    fn missing_input_dir_is_reported() {
+        let dir = Path::new("/nonexistent/way-thumbsense/input");
+        let root = SysRoot::under(Path::new("/nonexistent"));
+        assert!(matches!(
+            find_touchpad_in(dir, &root),
+            Err(FindDeviceError::CannotReadInputDir)
+        ));
+        assert!(matches!(
+            find_keyboard_in(dir, &root),
+            Err(FindDeviceError::CannotReadInputDir)
+        ));
+    }
+
     // 注: これらのテストは実際のデバイスが必要なので、
     // CI環境では #[ignore] を付けて実行をスキップ
 
@@ -0,0 +1,462 @@
+//! evemu-record 形式のデバイス記述とイベントの読み込み
+//!
+//! libinputなどのバグ報告に添付される記録をテストや再現に使う。
+//! 対応する行は `N:`（名前）・`I:`（ID）・`P:`（プロパティ）・`B:`（ビットマスク）・
+//! `A:`（AbsInfo）・`E:`（イベント）で、それ以外とコメントは無視する
+
+use super::{Capabilities, DeviceClassifier, TouchpadDimensions};
+use crate::recording::{DeviceInfo, RecordedEvent, Recording, Source};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputId, Key,
+    MiscType, PropType, RelativeAxisType, Synchronization, UinputAbsSetup,
+};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// `A:` 行の軸情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbsAxis {
+    pub min: i32,
+    pub max: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+/// `E:` 行のイベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvemuEvent {
+    /// 記録中の時刻（マイクロ秒）
+    pub time_us: u64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl EvemuEvent {
+    pub fn to_input_event(&self) -> InputEvent {
+        InputEvent::new(EventType(self.event_type), self.code, self.value)
+    }
+}
+
+/// evemu-record の内容
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvemuDevice {
+    pub name: String,
+    /// バス・ベンダー・プロダクト・バージョン
+    pub id: Option<(u16, u16, u16, u16)>,
+    /// 入力プロパティ（INPUT_PROP_*）
+    pub properties: Vec<u16>,
+    /// イベント種別ごとの対応コード
+    pub codes: BTreeMap<u16, Vec<u16>>,
+    pub axes: BTreeMap<u16, AbsAxis>,
+    pub events: Vec<EvemuEvent>,
+}
+
+impl EvemuDevice {
+    /// evemu-record の出力を読み込む
+    pub fn parse(text: &str) -> Result<Self, EvemuError> {
+        let mut device = Self::default();
+        // B:/P: は複数行に分かれるので種別ごとのビット位置を覚えておく
+        let mut offsets: BTreeMap<u16, u16> = BTreeMap::new();
+        let mut property_offset = 0u16;
+
+        for (index, raw) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = raw.split('#').next().unwrap_or("").trim();
+            let Some((tag, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            let err = |msg: &str| EvemuError::Parse(line_no, format!("{}: {}", msg, raw.trim()));
+
+            match tag {
+                // 名前に '#' が含まれる場合に備えて元の行から取る
+                "N" => device.name = raw.trim_start()[2..].trim().to_string(),
+                "I" => {
+                    let ids = fields
+                        .iter()
+                        .map(|f| u16::from_str_radix(f, 16))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| err("invalid id"))?;
+                    match ids[..] {
+                        [bus, vendor, product, version] => {
+                            device.id = Some((bus, vendor, product, version))
+                        }
+                        _ => return Err(err("invalid id")),
+                    }
+                }
+                "P" => {
+                    let bytes =
+                        parse_hex_bytes(&fields).ok_or_else(|| err("invalid properties"))?;
+                    device
+                        .properties
+                        .extend(bits(&bytes).map(|bit| property_offset + bit));
+                    property_offset += bytes.len() as u16 * 8;
+                }
+                "B" => {
+                    let (event_type, bytes) = fields
+                        .split_first()
+                        .and_then(|(t, bytes)| {
+                            Some((u16::from_str_radix(t, 16).ok()?, parse_hex_bytes(bytes)?))
+                        })
+                        .ok_or_else(|| err("invalid bitmask"))?;
+                    let offset = offsets.entry(event_type).or_default();
+                    let codes = device.codes.entry(event_type).or_default();
+                    codes.extend(bits(&bytes).map(|bit| *offset + bit));
+                    *offset += bytes.len() as u16 * 8;
+                }
+                "A" => {
+                    let code = fields
+                        .first()
+                        .and_then(|c| u16::from_str_radix(c, 16).ok())
+                        .ok_or_else(|| err("invalid axis"))?;
+                    let values = fields[1..]
+                        .iter()
+                        .map(|f| f.parse::<i32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| err("invalid axis"))?;
+                    if values.len() < 4 {
+                        return Err(err("invalid axis"));
+                    }
+                    device.axes.insert(
+                        code,
+                        AbsAxis {
+                            min: values[0],
+                            max: values[1],
+                            fuzz: values[2],
+                            flat: values[3],
+                            resolution: values.get(4).copied().unwrap_or(0),
+                        },
+                    );
+                }
+                "E" => device
+                    .events
+                    .push(parse_event(&fields).ok_or_else(|| err("invalid event"))?),
+                _ => {}
+            }
+        }
+
+        if device.name.is_empty() && device.codes.is_empty() {
+            return Err(EvemuError::Parse(0, "not an evemu recording".to_string()));
+        }
+        Ok(device)
+    }
+
+    /// 種別とコードに対応しているか
+    pub fn supports(&self, event_type: EventType, code: u16) -> bool {
+        self.codes
+            .get(&event_type.0)
+            .is_some_and(|codes| codes.contains(&code))
+    }
+
+    /// 対応しているキー（ボタン）
+    pub fn keys(&self) -> Vec<Key> {
+        self.codes_of(EventType::KEY).map(Key::new).collect()
+    }
+
+    fn codes_of(&self, event_type: EventType) -> impl Iterator<Item = u16> + '_ {
+        self.codes.get(&event_type.0).into_iter().flatten().copied()
+    }
+
+    /// 記録したデバイスの能力
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            keys: self.keys(),
+            abs: self
+                .codes_of(EventType::ABSOLUTE)
+                .map(AbsoluteAxisType)
+                .collect(),
+            rel: self
+                .codes_of(EventType::RELATIVE)
+                .map(RelativeAxisType)
+                .collect(),
+            props: self.properties.iter().map(|&p| PropType(p)).collect(),
+            vendor: self.id.map_or(0, |(_, vendor, _, _)| vendor),
+        }
+    }
+
+    /// タッチパッドか（`find_touchpad` と同じ条件）
+    pub fn is_touchpad(&self) -> bool {
+        DeviceClassifier::new(self.capabilities()).is_touchpad()
+    }
+
+    /// ABS_X/ABS_Yの範囲（`get_touchpad_dimensions` と同じ値）
+    pub fn dimensions(&self) -> Option<TouchpadDimensions> {
+        let x = self.axes.get(&AbsoluteAxisType::ABS_X.0)?;
+        let y = self.axes.get(&AbsoluteAxisType::ABS_Y.0)?;
+        Some(TouchpadDimensions {
+            min_x: x.min,
+            max_x: x.max,
+            min_y: y.min,
+            max_y: y.max,
+        })
+    }
+
+    /// `recording::replay` でエンジンに流せる記録に変換
+    ///
+    /// 時刻は最初のイベントからの経過時間にする
+    pub fn to_recording(&self) -> Recording {
+        let start = self.events.first().map_or(0, |e| e.time_us);
+        Recording {
+            devices: vec![DeviceInfo {
+                source: Source::Touchpad,
+                name: self.name.clone(),
+                dimensions: self.dimensions(),
+            }],
+            events: self
+                .events
+                .iter()
+                .map(|e| RecordedEvent {
+                    time_us: e.time_us.saturating_sub(start),
+                    source: Source::Touchpad,
+                    event_type: e.event_type,
+                    code: e.code,
+                    value: e.value,
+                })
+                .collect(),
+        }
+    }
+
+    /// 同じ名前・ID・キー・軸・プロパティの仮想デバイスをuinputで作成
+    pub fn create_device(&self) -> io::Result<VirtualDevice> {
+        let keys: AttributeSet<Key> = self.keys().into_iter().collect();
+        let properties: AttributeSet<PropType> =
+            self.properties.iter().map(|&p| PropType(p)).collect();
+        let misc: AttributeSet<MiscType> = self.codes_of(EventType::MISC).map(MiscType).collect();
+        let (bus, vendor, product, version) = self.id.unwrap_or((BusType::BUS_VIRTUAL.0, 0, 0, 0));
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name(&self.name)
+            .input_id(InputId::new(BusType(bus), vendor, product, version))
+            .with_keys(&keys)?
+            .with_properties(&properties)?;
+        if misc.iter().next().is_some() {
+            builder = builder.with_msc(&misc)?;
+        }
+        for code in self.codes_of(EventType::ABSOLUTE) {
+            let axis = self.axes.get(&code).copied().unwrap_or(AbsAxis {
+                min: 0,
+                max: 0,
+                fuzz: 0,
+                flat: 0,
+                resolution: 0,
+            });
+            let info = AbsInfo::new(0, axis.min, axis.max, axis.fuzz, axis.flat, axis.resolution);
+            builder =
+                builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType(code), info))?;
+        }
+        builder.build()
+    }
+
+    /// 記録時の間隔でイベントを仮想デバイスに送る
+    pub fn play(&self, device: &mut VirtualDevice) -> io::Result<()> {
+        let first = self.events.first().map_or(0, |e| e.time_us);
+        let start = Instant::now();
+        let mut frame = Vec::new();
+
+        for event in &self.events {
+            let is_report = event.event_type == EventType::SYNCHRONIZATION.0
+                && event.code == Synchronization::SYN_REPORT.0;
+            if !is_report {
+                frame.push(event.to_input_event());
+                continue;
+            }
+
+            let at = start + Duration::from_micros(event.time_us.saturating_sub(first));
+            std::thread::sleep(at.saturating_duration_since(Instant::now()));
+            // emit がSYN_REPORTを付ける
+            device.emit(&frame)?;
+            frame.clear();
+        }
+        Ok(())
+    }
+}
+
+/// 記録がevemu形式か（先頭のコメントかN:行で判断）
+pub fn is_evemu(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| line.starts_with("# EVEMU") || line.starts_with("N:"))
+}
+
+fn parse_hex_bytes(fields: &[&str]) -> Option<Vec<u8>> {
+    fields
+        .iter()
+        .map(|f| u8::from_str_radix(f, 16).ok())
+        .collect()
+}
+
+/// ビットマスクの立っているビット位置
+fn bits(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    bytes.iter().enumerate().flat_map(|(i, &byte)| {
+        (0..8)
+            .filter(move |bit| byte & (1 << bit) != 0)
+            .map(move |bit| i as u16 * 8 + bit)
+    })
+}
+
+/// `E: <秒>.<マイクロ秒> <種別hex> <コードhex> <値>`
+fn parse_event(fields: &[&str]) -> Option<EvemuEvent> {
+    let [time, event_type, code, value, ..] = fields else {
+        return None;
+    };
+    let (sec, usec) = time.split_once('.')?;
+    Some(EvemuEvent {
+        time_us: sec.parse::<u64>().ok()? * 1_000_000 + usec.parse::<u64>().ok()?,
+        event_type: u16::from_str_radix(event_type, 16).ok()?,
+        code: u16::from_str_radix(code, 16).ok()?,
+        value: value.parse().ok()?,
+    })
+}
+
+#[derive(Debug)]
+pub enum EvemuError {
+    Parse(usize, String),
+}
+
+impl std::fmt::Display for EvemuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvemuError::Parse(0, msg) => write!(f, "Invalid evemu recording: {}", msg),
+            EvemuError::Parse(line, msg) => {
+                write!(f, "Invalid evemu recording at line {}: {}", line, msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvemuError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Engine, TouchEvent};
+    use crate::recording::{replay, ReplayAction};
+    use crate::tracker::{Edge, ExclusionZones, TouchTracker};
+
+    /// タッチパッドの記録（下端に手のひら → 中央をタップ）
+    const TOUCHPAD: &str = "\
+# EVEMU 1.3
+# Kernel: 6.6.7-arch1-1
+# Input device name: \"SYNA2B52:00 06CB:CE78 Touchpad\"
+N: SYNA2B52:00 06CB:CE78 Touchpad
+I: 0018 06cb ce78 0100
+P: 05 00 00 00 00 00 00 00
+B: 00 1b 00 00 00 00 00 00 00
+B: 01 00 00 00 00 00 00 00 00
+B: 01 00 00 00 00 00 00 00 00
+B: 01 00 00 00 00 00 00 00 00
+B: 01 00 00 00 00 00 00 00 00
+B: 01 00 00 01 00 00 00 00 00
+B: 01 20 e5 00 00 00 00 00 00
+B: 03 03 00 00 00 00 80 e0 02
+B: 04 20 00 00 00 00 00 00 00
+A: 00 0 1224 0 0 12
+A: 01 0 804 0 0 12
+A: 2f 0 4 0 0 0
+A: 35 0 1224 0 0 12
+A: 36 0 804 0 0 12
+A: 37 0 2 0 0 0
+A: 39 0 65535 0 0 0
+################################
+#      Waiting for events      #
+################################
+E: 0.000001 0003 0039 0000	# EV_ABS / ABS_MT_TRACKING_ID   0
+E: 0.000001 0003 0035 0612	# EV_ABS / ABS_MT_POSITION_X    612
+E: 0.000001 0003 0036 0790	# EV_ABS / ABS_MT_POSITION_Y    790
+E: 0.000001 0001 014a 0001	# EV_KEY / BTN_TOUCH            1
+E: 0.000001 0001 0145 0001	# EV_KEY / BTN_TOOL_FINGER      1
+E: 0.000001 0003 0000 0612	# EV_ABS / ABS_X                612
+E: 0.000001 0003 0001 0790	# EV_ABS / ABS_Y                790
+E: 0.000001 0004 0005 0000	# EV_MSC / MSC_TIMESTAMP        0
+E: 0.000001 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +0ms
+E: 0.052310 0003 0039 -001	# EV_ABS / ABS_MT_TRACKING_ID   -1
+E: 0.052310 0001 014a 0000	# EV_KEY / BTN_TOUCH            0
+E: 0.052310 0001 0145 0000	# EV_KEY / BTN_TOOL_FINGER      0
+E: 0.052310 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +52ms
+E: 1.204050 0003 0039 0001	# EV_ABS / ABS_MT_TRACKING_ID   1
+E: 1.204050 0003 0035 0600	# EV_ABS / ABS_MT_POSITION_X    600
+E: 1.204050 0003 0036 0400	# EV_ABS / ABS_MT_POSITION_Y    400
+E: 1.204050 0001 014a 0001	# EV_KEY / BTN_TOUCH            1
+E: 1.204050 0003 0000 0600	# EV_ABS / ABS_X                600
+E: 1.204050 0003 0001 0400	# EV_ABS / ABS_Y                400
+E: 1.204050 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +1152ms
+E: 1.300000 0003 0039 -001	# EV_ABS / ABS_MT_TRACKING_ID   -1
+E: 1.300000 0001 014a 0000	# EV_KEY / BTN_TOUCH            0
+E: 1.300000 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +96ms
+";
+
+    #[test]
+    fn parse_device_description() {
+        let device = EvemuDevice::parse(TOUCHPAD).unwrap();
+
+        assert_eq!(device.name, "SYNA2B52:00 06CB:CE78 Touchpad");
+        assert_eq!(device.id, Some((0x18, 0x06cb, 0xce78, 0x0100)));
+        // INPUT_PROP_POINTER, INPUT_PROP_BUTTONPAD
+        assert_eq!(device.properties, vec![0, 2]);
+        assert!(device.is_touchpad());
+        assert!(device.supports(EventType::KEY, Key::BTN_LEFT.code()));
+        assert!(device.supports(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TRACKING_ID.0));
+        assert!(!device.supports(EventType::KEY, Key::KEY_J.code()));
+        assert_eq!(
+            device.dimensions(),
+            Some(TouchpadDimensions {
+                min_x: 0,
+                max_x: 1224,
+                min_y: 0,
+                max_y: 804
+            })
+        );
+        assert_eq!(device.axes[&0x35].resolution, 12);
+        assert_eq!(device.events.len(), 23);
+        assert_eq!(device.events[9].value, -1);
+        assert_eq!(device.events[13].time_us, 1_204_050);
+    }
+
+    #[test]
+    fn palm_in_bottom_zone_does_not_activate() {
+        let device = EvemuDevice::parse(TOUCHPAD).unwrap();
+        let recording = device.to_recording();
+        let mut engine = Engine::new(TouchTracker::new(
+            device.dimensions().unwrap(),
+            ExclusionZones::new(0.0, 15.0, 0.0, 0.0),
+        ));
+
+        let actions: Vec<_> = replay(&recording, &mut engine)
+            .into_iter()
+            .map(|o| (o.time_us, o.action))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (
+                    0,
+                    ReplayAction::Touch(TouchEvent::Excluded {
+                        x: 612,
+                        y: 790,
+                        edge: Edge::Bottom
+                    })
+                ),
+                (
+                    1_204_049,
+                    ReplayAction::Touch(TouchEvent::Activated {
+                        x: Some(600),
+                        y: Some(400)
+                    })
+                ),
+                (1_299_999, ReplayAction::Touch(TouchEvent::Deactivated)),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_format() {
+        assert!(is_evemu(TOUCHPAD));
+        assert!(!is_evemu("{\"version\":1,\"devices\":[]}"));
+        assert!(EvemuDevice::parse("hello\nworld").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! SYN_REPORTごとのフレーム分割
+//!
+//! カーネルはSYN_REPORTまでを1つの状態として送るので、座標とBTN_TOUCHは同じフレームの中で
+//! 組み合わせる。SYN_DROPPEDが届いたら次のSYN_REPORTまでを捨て、呼び出し側が
+//! デバイスの現在の状態（`TouchSnapshot`）から作り直す
+
+use evdev::{InputEvent, InputEventKind, Synchronization};
+
+/// 分割したフレーム
+#[derive(Debug, Clone)]
+pub enum Frame {
+    /// SYN_REPORTまでのイベント（SYN_REPORTを含む）
+    Events(Vec<InputEvent>),
+    /// SYN_DROPPEDで取りこぼした（デバイスの状態から再同期する）
+    Dropped,
+}
+
+/// 読んだイベントをSYN_REPORTごとのフレームにまとめる
+///
+/// 1回の読み込みをまたぐフレームは次の呼び出しまで持ち越す
+#[derive(Debug, Default)]
+pub struct FrameSplitter {
+    pending: Vec<InputEvent>,
+    /// SYN_DROPPEDの後、次のSYN_REPORTを待っている
+    dropping: bool,
+}
+
+impl FrameSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// イベントを1つ渡し、フレームが揃ったら返す
+    pub fn push(&mut self, event: InputEvent) -> Option<Frame> {
+        match event.kind() {
+            InputEventKind::Synchronization(Synchronization::SYN_DROPPED) => {
+                self.pending.clear();
+                self.dropping = true;
+                None
+            }
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                if std::mem::take(&mut self.dropping) {
+                    self.pending.clear();
+                    return Some(Frame::Dropped);
+                }
+                self.pending.push(event);
+                Some(Frame::Events(std::mem::take(&mut self.pending)))
+            }
+            _ if self.dropping => None,
+            _ => {
+                self.pending.push(event);
+                None
+            }
+        }
+    }
+
+    /// 複数のイベントを渡し、揃ったフレームを返す
+    pub fn extend(&mut self, events: impl IntoIterator<Item = InputEvent>) -> Vec<Frame> {
+        events.into_iter().filter_map(|ev| self.push(ev)).collect()
+    }
+}
+
+/// イベント列をフレームに分ける
+///
+/// SYN_REPORTで終わらない残りも最後のフレームとして返す
+pub fn split(events: &[InputEvent]) -> Vec<Frame> {
+    let mut splitter = FrameSplitter::new();
+    let mut frames = splitter.extend(events.iter().copied());
+    if !splitter.pending.is_empty() {
+        frames.push(Frame::Events(splitter.pending));
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::{AbsoluteAxisType, EventType};
+
+    fn syn(code: Synchronization) -> InputEvent {
+        InputEvent::new(EventType::SYNCHRONIZATION, code.0, 0)
+    }
+
+    /// 比較用に (type, code, value) へ。取りこぼしは None
+    fn values(frames: Vec<Frame>) -> Vec<Option<Vec<(u16, u16, i32)>>> {
+        frames
+            .into_iter()
+            .map(|frame| match frame {
+                Frame::Events(events) => Some(
+                    events
+                        .iter()
+                        .map(|ev| (ev.event_type().0, ev.code(), ev.value()))
+                        .collect(),
+                ),
+                Frame::Dropped => None,
+            })
+            .collect()
+    }
+
+    fn abs_x(value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, value)
+    }
+
+    const X: fn(i32) -> (u16, u16, i32) = |value| (EventType::ABSOLUTE.0, 0, value);
+    const REPORT: (u16, u16, i32) = (0, 0, 0);
+
+    #[test]
+    fn splits_at_syn_report() {
+        let report = syn(Synchronization::SYN_REPORT);
+        let frames = split(&[abs_x(1), report, abs_x(2), abs_x(3), report]);
+        assert_eq!(
+            values(frames),
+            vec![Some(vec![X(1), REPORT]), Some(vec![X(2), X(3), REPORT])]
+        );
+    }
+
+    #[test]
+    fn carries_partial_frame_over() {
+        let report = syn(Synchronization::SYN_REPORT);
+        let mut splitter = FrameSplitter::new();
+        assert!(splitter.extend([abs_x(1)]).is_empty());
+        assert_eq!(
+            values(splitter.extend([abs_x(2), report])),
+            vec![Some(vec![X(1), X(2), REPORT])]
+        );
+
+        // 一括で分けるときは残りも返す
+        assert_eq!(values(split(&[abs_x(4)])), vec![Some(vec![X(4)])]);
+    }
+
+    #[test]
+    fn syn_dropped_discards_until_next_report() {
+        let report = syn(Synchronization::SYN_REPORT);
+        let frames = split(&[
+            abs_x(1),
+            syn(Synchronization::SYN_DROPPED),
+            abs_x(2),
+            report,
+            abs_x(3),
+            report,
+        ]);
+        assert_eq!(values(frames), vec![None, Some(vec![X(3), REPORT])]);
+    }
+}
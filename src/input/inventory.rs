@@ -0,0 +1,496 @@
+//! `/dev/input/event*` の一覧と、自動検出でどれが選ばれるか（list-devices 用）
+
+use super::classify::{Capabilities, DeviceClassifier, DeviceKind};
+use super::evdev_input::{is_own_device, KEYD_KEYBOARD};
+use super::udev::{DeviceMetadata, DeviceSelector, SysRoot};
+use evdev::{AbsoluteAxisType, Device, Key};
+use serde::{Serialize, Serializer};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// デバイスを開けたか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    Ok,
+    /// 権限がない（inputグループに入っていないなど）
+    PermissionDenied,
+    Error,
+}
+
+/// 自動検出での役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Touchpad,
+    Keyboard,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Touchpad => "touchpad",
+            Role::Keyboard => "keyboard",
+        }
+    }
+}
+
+/// 自動検出で選ばれる/選ばれない理由
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Selection {
+    pub role: Role,
+    pub selected: bool,
+    pub reason: String,
+}
+
+/// バス・ベンダー・プロダクト・バージョン
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceIds {
+    pub bus: String,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+/// 絶対軸の範囲
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AxisRange {
+    #[serde(serialize_with = "debug_name")]
+    pub axis: AbsoluteAxisType,
+    pub min: i32,
+    pub max: i32,
+    pub resolution: i32,
+}
+
+/// 1デバイス分の情報
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceReport {
+    pub path: PathBuf,
+    pub access: Access,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<DeviceKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<DeviceIds>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phys: Option<String>,
+    /// sysfsとudevの情報
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udev: Option<DeviceMetadata>,
+    #[serde(serialize_with = "debug_names")]
+    pub keys: Vec<Key>,
+    pub axes: Vec<AxisRange>,
+    pub selection: Vec<Selection>,
+}
+
+impl DeviceReport {
+    /// 開けなかったデバイス
+    pub fn unreadable(path: &Path, error: &io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            access: if error.kind() == io::ErrorKind::PermissionDenied {
+                Access::PermissionDenied
+            } else {
+                Access::Error
+            },
+            error: Some(error.to_string()),
+            class: None,
+            name: None,
+            ids: None,
+            phys: None,
+            udev: None,
+            keys: Vec::new(),
+            axes: Vec::new(),
+            selection: Vec::new(),
+        }
+    }
+
+    fn from_device(path: &Path, device: &Device) -> Self {
+        let classifier = DeviceClassifier::from_device(device);
+        let abs_state = device.get_abs_state().ok();
+        let id = device.input_id();
+        let Capabilities { keys, abs, .. } = classifier.capabilities().clone();
+
+        Self {
+            path: path.to_path_buf(),
+            access: Access::Ok,
+            error: None,
+            class: Some(classifier.kind()),
+            name: device.name().map(str::to_string),
+            ids: Some(DeviceIds {
+                bus: format!("{:?}", id.bus_type()),
+                vendor: id.vendor(),
+                product: id.product(),
+                version: id.version(),
+            }),
+            phys: device.physical_path().map(str::to_string),
+            udev: None,
+            axes: abs
+                .iter()
+                .map(|&axis| {
+                    let info = abs_state.as_ref().and_then(|s| s.get(axis.0 as usize));
+                    AxisRange {
+                        axis,
+                        min: info.map_or(0, |i| i.minimum),
+                        max: info.map_or(0, |i| i.maximum),
+                        resolution: info.map_or(0, |i| i.resolution),
+                    }
+                })
+                .collect(),
+            keys,
+            selection: Vec::new(),
+        }
+    }
+
+    fn is_readable(&self) -> bool {
+        self.access == Access::Ok
+    }
+
+    fn matches(&self, selector: &DeviceSelector) -> bool {
+        // find_device と同じくキーを持つデバイスだけ
+        selector.matches(self.name.as_deref(), self.udev.as_ref()) && !self.keys.is_empty()
+    }
+}
+
+/// `dir` のイベントノードをすべて調べる
+///
+/// `touchpad` は設定の `[device] touchpad`（指定があればそれで選ぶ）。
+/// 結果はイベント番号順
+pub fn scan(dir: &Path, root: &SysRoot, touchpad: Option<&str>) -> io::Result<Vec<DeviceReport>> {
+    // 選択はfind_*と同じくread_dirの順で行う
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.to_string_lossy().contains("event") {
+            continue;
+        }
+        let mut report = match Device::open(&path) {
+            Ok(device) => DeviceReport::from_device(&path, &device),
+            Err(e) => DeviceReport::unreadable(&path, &e),
+        };
+        report.udev = root.metadata(&path);
+        reports.push(report);
+    }
+
+    select(&mut reports, touchpad);
+    reports.sort_by_key(|r| event_number(&r.path));
+    Ok(reports)
+}
+
+fn event_number(path: &Path) -> (u32, PathBuf) {
+    let number = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_prefix("event"))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(u32::MAX);
+    (number, path.to_path_buf())
+}
+
+/// 自動検出での候補としての呼び名（候補でなければNone）
+///
+/// udevのプロパティが読めればそれ（`ID_INPUT_TOUCHPAD` / `ID_INPUT_KEYBOARD`）に従い、
+/// udevが動いていなければ能力からの判定を使う
+fn candidate(report: &DeviceReport, role: Role) -> Option<&'static str> {
+    let udev = report.udev.as_ref().filter(|m| !m.properties.is_empty());
+    match (role, udev) {
+        // ゲームパッドのタッチパッドはudevでもタッチパッドになるので除く
+        (Role::Touchpad, Some(udev)) => (udev.is_touchpad()
+            && report.class != Some(DeviceKind::Joystick))
+        .then_some("touchpad (ID_INPUT_TOUCHPAD=1)"),
+        (Role::Touchpad, None) => report
+            .class
+            .filter(DeviceKind::is_touchpad)
+            .map(|kind| kind.as_str()),
+        (Role::Keyboard, Some(udev)) => (udev.is_keyboard() && report.keys.contains(&Key::KEY_J))
+            .then_some("keyboard (ID_INPUT_KEYBOARD=1)"),
+        (Role::Keyboard, None) => {
+            (report.class == Some(DeviceKind::Keyboard)).then_some("keyboard with KEY_J and KEY_A")
+        }
+    }
+}
+
+/// find_touchpad / find_device / find_keyboard と同じ規則で選択理由を付ける
+///
+/// `reports` は /dev/input を列挙した順であること
+pub fn select(reports: &mut [DeviceReport], touchpad: Option<&str>) {
+    // タッチパッド
+    match touchpad.map(DeviceSelector::parse) {
+        Some(selector) => {
+            if let Some(report) = reports
+                .iter_mut()
+                .find(|r| r.is_readable() && r.matches(&selector))
+            {
+                report.selection.push(Selection {
+                    role: Role::Touchpad,
+                    selected: true,
+                    reason: format!("{} ([device] touchpad)", selector),
+                });
+            }
+        }
+        None => {
+            let mut first: Option<PathBuf> = None;
+            for report in reports.iter_mut().filter(|r| r.is_readable()) {
+                let Some(kind) = candidate(report, Role::Touchpad) else {
+                    continue;
+                };
+                let (selected, reason) = if report.name.as_deref().is_some_and(is_own_device) {
+                    (false, "way-thumbsense's own virtual device".to_string())
+                } else if let Some(first) = &first {
+                    (
+                        false,
+                        format!("is a {}, but {} comes first", kind, first.display()),
+                    )
+                } else {
+                    first = Some(report.path.clone());
+                    (true, format!("first {}", kind))
+                };
+                report.selection.push(Selection {
+                    role: Role::Touchpad,
+                    selected,
+                    reason,
+                });
+            }
+        }
+    }
+
+    // キーボード（keydの仮想キーボードを優先）
+    if let Some(report) = reports.iter_mut().find(|r| {
+        r.is_readable() && r.matches(&DeviceSelector::NameContains(KEYD_KEYBOARD.to_string()))
+    }) {
+        report.selection.push(Selection {
+            role: Role::Keyboard,
+            selected: true,
+            reason: "keyd virtual keyboard is preferred".to_string(),
+        });
+        return;
+    }
+    let mut first: Option<PathBuf> = None;
+    for report in reports.iter_mut().filter(|r| r.is_readable()) {
+        let Some(kind) = candidate(report, Role::Keyboard) else {
+            continue;
+        };
+        let (selected, reason) = match &first {
+            Some(first) => (
+                false,
+                format!("is a keyboard, but {} comes first", first.display()),
+            ),
+            None => {
+                first = Some(report.path.clone());
+                (true, format!("first {}", kind))
+            }
+        };
+        report.selection.push(Selection {
+            role: Role::Keyboard,
+            selected,
+            reason,
+        });
+    }
+}
+
+/// `role` に選ばれたデバイス
+pub fn selected(reports: &[DeviceReport], role: Role) -> Option<&DeviceReport> {
+    reports
+        .iter()
+        .find(|r| r.selection.iter().any(|s| s.role == role && s.selected))
+}
+
+/// 権限不足で開けなかったデバイスがあるか
+pub fn any_permission_denied(reports: &[DeviceReport]) -> bool {
+    reports.iter().any(|r| r.access == Access::PermissionDenied)
+}
+
+fn debug_name<T: std::fmt::Debug, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{:?}", value))
+}
+
+fn debug_names<T: std::fmt::Debug, S: Serializer>(values: &[T], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(values.iter().map(|v| format!("{:?}", v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(event: u32, name: &str, keys: &[Key], axes: &[AbsoluteAxisType]) -> DeviceReport {
+        DeviceReport {
+            path: PathBuf::from(format!("/dev/input/event{}", event)),
+            access: Access::Ok,
+            error: None,
+            class: Some(
+                DeviceClassifier::new(Capabilities {
+                    keys: keys.to_vec(),
+                    abs: axes.to_vec(),
+                    ..Default::default()
+                })
+                .kind(),
+            ),
+            name: Some(name.to_string()),
+            ids: None,
+            phys: None,
+            udev: None,
+            keys: keys.to_vec(),
+            axes: axes
+                .iter()
+                .map(|&axis| AxisRange {
+                    axis,
+                    min: 0,
+                    max: 1000,
+                    resolution: 0,
+                })
+                .collect(),
+            selection: Vec::new(),
+        }
+    }
+
+    fn touchpad(event: u32, name: &str) -> DeviceReport {
+        report(
+            event,
+            name,
+            &[Key::BTN_LEFT, Key::BTN_TOUCH, Key::BTN_TOOL_FINGER],
+            &[AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y],
+        )
+    }
+
+    /// BTN_TOOL_FINGERのないタッチ面
+    fn touchscreen(event: u32, name: &str) -> DeviceReport {
+        report(
+            event,
+            name,
+            &[Key::BTN_TOUCH],
+            &[AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y],
+        )
+    }
+
+    fn keyboard(event: u32, name: &str) -> DeviceReport {
+        report(event, name, &[Key::KEY_A, Key::KEY_J], &[])
+    }
+
+    fn selected_events(reports: &[DeviceReport], role: Role) -> Vec<u32> {
+        reports
+            .iter()
+            .filter(|r| r.selection.iter().any(|s| s.role == role && s.selected))
+            .map(|r| event_number(&r.path).0)
+            .collect()
+    }
+
+    #[test]
+    fn selects_first_candidates_like_discovery() {
+        let mut reports = vec![
+            touchpad(7, "way-thumbsense virtual mouse"),
+            touchscreen(4, "ELAN Touchscreen"),
+            keyboard(3, "AT Translated Set 2 keyboard"),
+            touchpad(5, "SYNA Touchpad"),
+            touchpad(9, "Magic Trackpad"),
+            DeviceReport::unreadable(
+                Path::new("/dev/input/event2"),
+                &io::Error::from(io::ErrorKind::PermissionDenied),
+            ),
+        ];
+        select(&mut reports, None);
+
+        assert_eq!(selected_events(&reports, Role::Touchpad), vec![5]);
+        assert_eq!(selected_events(&reports, Role::Keyboard), vec![3]);
+        assert_eq!(
+            reports[0].selection[0].reason,
+            "way-thumbsense's own virtual device"
+        );
+        assert_eq!(reports[1].class, Some(DeviceKind::Touchscreen));
+        assert!(reports[1].selection.is_empty());
+        assert_eq!(
+            reports[4].selection[0].reason,
+            "is a touchpad, but /dev/input/event5 comes first"
+        );
+        assert!(any_permission_denied(&reports));
+    }
+
+    #[test]
+    fn configured_name_and_keyd_keyboard_win() {
+        let mut reports = vec![
+            touchpad(5, "SYNA Touchpad"),
+            touchpad(9, "Magic Trackpad"),
+            keyboard(3, "AT Translated Set 2 keyboard"),
+            report(12, "keyd virtual keyboard", &[Key::KEY_A], &[]),
+        ];
+        select(&mut reports, Some("Magic"));
+
+        assert_eq!(selected_events(&reports, Role::Touchpad), vec![9]);
+        assert_eq!(selected_events(&reports, Role::Keyboard), vec![12]);
+        assert!(reports[2].selection.is_empty());
+    }
+
+    #[test]
+    fn select_touchpad_by_udev_property() {
+        let root =
+            SysRoot::under(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysroot"));
+        let mut reports = vec![touchpad(4, "Other Touchpad"), touchpad(5, "SYNA Touchpad")];
+        for report in &mut reports {
+            report.udev = root.metadata(&report.path);
+        }
+        select(&mut reports, Some("ID_INPUT_TOUCHPAD=1"));
+
+        assert_eq!(selected_events(&reports, Role::Touchpad), vec![5]);
+        assert_eq!(
+            reports[1].selection[0].reason,
+            "ID_INPUT_TOUCHPAD=1 ([device] touchpad)"
+        );
+    }
+
+    #[test]
+    fn udev_properties_override_capabilities() {
+        let root =
+            SysRoot::under(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysroot"));
+        // event5 は ID_INPUT_TOUCHPAD=1、event3 は ID_INPUT_KEYBOARD=1
+        let mut reports = vec![
+            touchpad(2, "Touchpad without udev data"),
+            keyboard(4, "Keyboard without udev data"),
+            report(
+                5,
+                "SYNA2B52:00 06CB:CE78 Touchpad",
+                &[Key::BTN_TOUCH],
+                &[AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y],
+            ),
+            keyboard(3, "AT Translated Set 2 keyboard"),
+        ];
+        for report in &mut reports {
+            report.udev = root.metadata(&report.path);
+        }
+        // udevが動いていない環境のデバイスは能力で判定する
+        assert!(reports[0].udev.is_none());
+        select(&mut reports, None);
+
+        assert_eq!(selected_events(&reports, Role::Touchpad), vec![2]);
+        assert_eq!(selected_events(&reports, Role::Keyboard), vec![4]);
+        assert_eq!(
+            reports[2].selection[0].reason,
+            "is a touchpad (ID_INPUT_TOUCHPAD=1), but /dev/input/event2 comes first"
+        );
+
+        let mut reports = reports.split_off(2);
+        for report in &mut reports {
+            report.selection.clear();
+        }
+        select(&mut reports, None);
+        assert_eq!(
+            selected(&reports, Role::Touchpad).unwrap().path,
+            Path::new("/dev/input/event5")
+        );
+        assert_eq!(
+            reports[1].selection[0].reason,
+            "first keyboard (ID_INPUT_KEYBOARD=1)"
+        );
+    }
+
+    #[test]
+    fn json_uses_names() {
+        let json = serde_json::to_value(touchpad(5, "pad")).unwrap();
+        assert_eq!(json["class"], "touchpad");
+        assert_eq!(json["keys"][0], "BTN_LEFT");
+        assert_eq!(json["axes"][1]["axis"], "ABS_Y");
+        assert!(json.get("error").is_none());
+    }
+}
@@ -1,6 +1,16 @@
+pub mod classify;
 pub mod evdev_input;
+pub mod evemu;
+pub mod frames;
+pub mod inventory;
+pub mod snapshot;
+pub mod udev;
 
+pub use classify::{Capabilities, DeviceClassifier, DeviceKind};
 pub use evdev_input::{
-    find_device, find_keyboard, find_touchpad, get_touchpad_dimensions, DeviceType,
-    TouchpadDimensions,
+    find_device, find_device_except, find_device_in, find_keyboard, find_keyboard_in,
+    find_touchpad, find_touchpad_in, get_touchpad_dimensions, TouchpadDimensions,
 };
+pub use frames::{Frame, FrameSplitter};
+pub use snapshot::{Contact, TouchSnapshot};
+pub use udev::{DeviceMetadata, DeviceSelector, SysRoot};
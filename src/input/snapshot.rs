@@ -0,0 +1,283 @@
+//! デバイスの現在のタッチ状態
+//!
+//! 指を置いたまま開いたり、SYN_DROPPEDで取りこぼしたりするとBTN_TOUCH=1の変化は届かない。
+//! キー・軸・MTスロットの状態をioctlで読み、エンジンに1フレームとして渡して追いつく
+
+use evdev::{AbsoluteAxisType, Device, EventType, InputEvent, Key, Synchronization};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// 指の本数を表すキー（1本から5本）
+const TOOL_KEYS: [Key; 5] = [
+    Key::BTN_TOOL_FINGER,
+    Key::BTN_TOOL_DOUBLETAP,
+    Key::BTN_TOOL_TRIPLETAP,
+    Key::BTN_TOOL_QUADTAP,
+    Key::BTN_TOOL_QUINTTAP,
+];
+
+/// MTスロットの接触
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub slot: usize,
+    pub tracking_id: i32,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// ABS_MT_TOOL_TYPE（対応していなければNone）
+    pub tool_type: Option<i32>,
+}
+
+/// ある時点のタッチ状態
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TouchSnapshot {
+    /// BTN_TOUCH
+    pub touching: bool,
+    /// 押されているBTN_TOOL_*
+    pub tools: Vec<Key>,
+    /// ABS_X / ABS_Y
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// 現在のスロットのABS_MT_TOOL_TYPE
+    pub tool_type: Option<i32>,
+    /// 現在のスロット（ABS_MT_SLOT。MTに対応していなければNone）
+    pub slot: Option<usize>,
+    /// tracking IDが割り当てられているスロット（スロット順）
+    pub contacts: Vec<Contact>,
+}
+
+impl TouchSnapshot {
+    /// デバイスから読む（EVIOCGKEY / EVIOCGABS / EVIOCGMTSLOTS）
+    pub fn read(device: &Device) -> io::Result<Self> {
+        let keys = device.get_key_state()?;
+        let abs = device.get_abs_state()?;
+        let supports = |axis: AbsoluteAxisType| {
+            device
+                .supported_absolute_axes()
+                .is_some_and(|axes| axes.contains(axis))
+        };
+        let value = |axis: AbsoluteAxisType| supports(axis).then(|| abs[axis.0 as usize].value);
+
+        let mut contacts = Vec::new();
+        if supports(AbsoluteAxisType::ABS_MT_SLOT) && supports(AbsoluteAxisType::ABS_MT_TRACKING_ID)
+        {
+            let slots = (abs[AbsoluteAxisType::ABS_MT_SLOT.0 as usize].maximum + 1).max(0) as usize;
+            let per_slot = |axis: AbsoluteAxisType| -> io::Result<Option<Vec<i32>>> {
+                if supports(axis) {
+                    mt_slot_values(device, axis, slots).map(Some)
+                } else {
+                    Ok(None)
+                }
+            };
+            let tracking_ids = mt_slot_values(device, AbsoluteAxisType::ABS_MT_TRACKING_ID, slots)?;
+            let xs = per_slot(AbsoluteAxisType::ABS_MT_POSITION_X)?;
+            let ys = per_slot(AbsoluteAxisType::ABS_MT_POSITION_Y)?;
+            let tools = per_slot(AbsoluteAxisType::ABS_MT_TOOL_TYPE)?;
+            let at = |values: &Option<Vec<i32>>, slot: usize| {
+                values.as_ref().and_then(|v| v.get(slot).copied())
+            };
+            contacts = tracking_ids
+                .iter()
+                .enumerate()
+                .filter(|(_, &id)| id >= 0)
+                .map(|(slot, &tracking_id)| Contact {
+                    slot,
+                    tracking_id,
+                    x: at(&xs, slot),
+                    y: at(&ys, slot),
+                    tool_type: at(&tools, slot),
+                })
+                .collect();
+        }
+
+        Ok(Self {
+            touching: keys.contains(Key::BTN_TOUCH),
+            tools: TOOL_KEYS
+                .into_iter()
+                .filter(|&key| keys.contains(key))
+                .collect(),
+            x: value(AbsoluteAxisType::ABS_X),
+            y: value(AbsoluteAxisType::ABS_Y),
+            tool_type: value(AbsoluteAxisType::ABS_MT_TOOL_TYPE),
+            slot: value(AbsoluteAxisType::ABS_MT_SLOT).map(|slot| slot.max(0) as usize),
+            contacts,
+        })
+    }
+
+    /// 指の本数（BTN_TOOL_*から。なければ接触の数）
+    pub fn finger_count(&self) -> usize {
+        TOOL_KEYS
+            .iter()
+            .rposition(|key| self.tools.contains(key))
+            .map_or(self.contacts.len(), |i| i + 1)
+    }
+
+    /// エンジンに渡すフレーム
+    ///
+    /// 触れている間は接触ごとのスロット・tracking ID・ツールの種類と、
+    /// 最初の接触（なければABS_X/ABS_Y）の座標を含む。触れていなくても現在のスロットへ戻す。
+    /// タイムスタンプは0なので遅延の計測には使わない
+    pub fn to_frame(&self) -> Vec<InputEvent> {
+        let abs =
+            |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+        let mut frame = Vec::new();
+        let contacts = if self.touching {
+            &self.contacts[..]
+        } else {
+            &[]
+        };
+        for contact in contacts {
+            frame.push(abs(AbsoluteAxisType::ABS_MT_SLOT, contact.slot as i32));
+            frame.push(abs(
+                AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                contact.tracking_id,
+            ));
+            frame.extend(
+                contact
+                    .tool_type
+                    .map(|tool| abs(AbsoluteAxisType::ABS_MT_TOOL_TYPE, tool)),
+            );
+        }
+        // 以降のイベントはカーネルの現在のスロットを前提に届く
+        if let Some(slot) = self.slot {
+            frame.push(abs(AbsoluteAxisType::ABS_MT_SLOT, slot as i32));
+        }
+        if self.touching {
+            let (x, y) = match self.contacts.first() {
+                Some(contact) => (contact.x, contact.y),
+                None => (self.x, self.y),
+            };
+            if self.contacts.is_empty() {
+                frame.extend(
+                    self.tool_type
+                        .map(|tool| abs(AbsoluteAxisType::ABS_MT_TOOL_TYPE, tool)),
+                );
+            }
+            for (axis, value) in [(AbsoluteAxisType::ABS_X, x), (AbsoluteAxisType::ABS_Y, y)] {
+                frame.extend(value.map(|value| abs(axis, value)));
+            }
+        }
+        frame.push(InputEvent::new(
+            EventType::KEY,
+            Key::BTN_TOUCH.code(),
+            self.touching as i32,
+        ));
+        frame.push(InputEvent::new(
+            EventType::SYNCHRONIZATION,
+            Synchronization::SYN_REPORT.0,
+            0,
+        ));
+        frame
+    }
+}
+
+/// スロットごとの値を読む（EVIOCGMTSLOTS）
+fn mt_slot_values(device: &Device, axis: AbsoluteAxisType, slots: usize) -> io::Result<Vec<i32>> {
+    // 先頭に軸のコードを入れて渡すと、続きにスロット順の値が入る
+    let mut buf = vec![0i32; slots + 1];
+    buf[0] = axis.0 as i32;
+    let len = std::mem::size_of_val(buf.as_slice()) as libc::c_ulong;
+    // _IOC(_IOC_READ, 'E', 0x0a, len)
+    let request = (2 << 30) | (len << 16) | ((b'E' as libc::c_ulong) << 8) | 0x0a;
+    let ret = unsafe { libc::ioctl(device.as_raw_fd(), request as _, buf.as_mut_ptr()) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.remove(0);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(frame: &[InputEvent]) -> Vec<(u16, u16, i32)> {
+        frame
+            .iter()
+            .map(|ev| (ev.event_type().0, ev.code(), ev.value()))
+            .collect()
+    }
+
+    fn contact(slot: usize, x: i32, y: i32) -> Contact {
+        Contact {
+            slot,
+            tracking_id: slot as i32 + 10,
+            x: Some(x),
+            y: Some(y),
+            tool_type: Some(0),
+        }
+    }
+
+    #[test]
+    fn frame_restores_slots_and_uses_first_contact() {
+        let snapshot = TouchSnapshot {
+            touching: true,
+            tools: vec![Key::BTN_TOOL_DOUBLETAP],
+            x: Some(1),
+            y: Some(2),
+            tool_type: None,
+            slot: Some(3),
+            contacts: vec![contact(1, 300, 400), contact(3, 500, 600)],
+        };
+        assert_eq!(snapshot.finger_count(), 2);
+        let abs = |axis: AbsoluteAxisType, value| (EventType::ABSOLUTE.0, axis.0, value);
+        assert_eq!(
+            values(&snapshot.to_frame()),
+            vec![
+                abs(AbsoluteAxisType::ABS_MT_SLOT, 1),
+                abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 11),
+                abs(AbsoluteAxisType::ABS_MT_TOOL_TYPE, 0),
+                abs(AbsoluteAxisType::ABS_MT_SLOT, 3),
+                abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 13),
+                abs(AbsoluteAxisType::ABS_MT_TOOL_TYPE, 0),
+                abs(AbsoluteAxisType::ABS_MT_SLOT, 3),
+                abs(AbsoluteAxisType::ABS_X, 300),
+                abs(AbsoluteAxisType::ABS_Y, 400),
+                (EventType::KEY.0, Key::BTN_TOUCH.code(), 1),
+                (EventType::SYNCHRONIZATION.0, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_falls_back_to_single_touch_axes() {
+        let snapshot = TouchSnapshot {
+            touching: true,
+            x: Some(10),
+            y: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.finger_count(), 0);
+        assert_eq!(
+            values(&snapshot.to_frame())[..2],
+            [
+                (EventType::ABSOLUTE.0, AbsoluteAxisType::ABS_X.0, 10),
+                (EventType::ABSOLUTE.0, AbsoluteAxisType::ABS_Y.0, 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn untouched_frame_has_no_position() {
+        let snapshot = TouchSnapshot {
+            x: Some(10),
+            y: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            values(&snapshot.to_frame()),
+            vec![
+                (EventType::KEY.0, Key::BTN_TOUCH.code(), 0),
+                (EventType::SYNCHRONIZATION.0, 0, 0),
+            ]
+        );
+        // 現在のスロットは触れていなくても戻す
+        let snapshot = TouchSnapshot {
+            slot: Some(2),
+            ..snapshot
+        };
+        assert_eq!(
+            values(&snapshot.to_frame())[0],
+            (EventType::ABSOLUTE.0, AbsoluteAxisType::ABS_MT_SLOT.0, 2)
+        );
+    }
+}
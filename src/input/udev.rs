@@ -0,0 +1,256 @@
+//! sysfsとudevデータベースから読むデバイスの情報と、それを使ったデバイスの指定
+//!
+//! `/sys/class/input/eventN` からデバイス番号と名前・IDを、
+//! `/run/udev/data/c<major>:<minor>` から `ID_INPUT_TOUCHPAD` などのプロパティを読む
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// sysfsとudevデータベースの場所（テストではフィクスチャのディレクトリを使う）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysRoot {
+    pub sysfs: PathBuf,
+    pub udev_data: PathBuf,
+}
+
+impl Default for SysRoot {
+    fn default() -> Self {
+        Self::under(Path::new("/"))
+    }
+}
+
+impl SysRoot {
+    /// `root` 以下の `sys` と `run/udev/data`
+    pub fn under(root: &Path) -> Self {
+        Self {
+            sysfs: root.join("sys"),
+            udev_data: root.join("run/udev/data"),
+        }
+    }
+
+    /// イベントノード（`/dev/input/event5` など）の情報
+    ///
+    /// sysfsにノードがなければNone。udevが動いていない環境ではプロパティは空になる
+    pub fn metadata(&self, devnode: &Path) -> Option<DeviceMetadata> {
+        let sysname = devnode.file_name()?.to_str()?;
+        let class_dir = self.sysfs.join("class/input").join(sysname);
+        let devnum = parse_devnum(&fs::read_to_string(class_dir.join("dev")).ok()?)?;
+        let device_dir = class_dir.join("device");
+
+        let properties =
+            read_udev_data(&self.udev_data.join(format!("c{}:{}", devnum.0, devnum.1)))
+                .unwrap_or_default();
+
+        Some(DeviceMetadata {
+            sysname: sysname.to_string(),
+            devnum,
+            name: read_attribute(&device_dir.join("name")),
+            phys: read_attribute(&device_dir.join("phys")),
+            vendor: read_attribute(&device_dir.join("id/vendor")),
+            product: read_attribute(&device_dir.join("id/product")),
+            properties,
+        })
+    }
+}
+
+/// sysfsとudevから読んだ1デバイスの情報
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceMetadata {
+    /// `event5` など
+    pub sysname: String,
+    /// デバイス番号（major, minor）
+    pub devnum: (u32, u32),
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phys: Option<String>,
+    /// sysfsの `id/vendor`（16進数の文字列）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    /// udevのプロパティ（`E:` 行）
+    pub properties: BTreeMap<String, String>,
+}
+
+impl DeviceMetadata {
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// udevがタッチパッドと判定したか（ID_INPUT_TOUCHPAD）
+    pub fn is_touchpad(&self) -> bool {
+        self.property("ID_INPUT_TOUCHPAD") == Some("1")
+    }
+
+    /// udevがキーボードと判定したか（ID_INPUT_KEYBOARD）
+    pub fn is_keyboard(&self) -> bool {
+        self.property("ID_INPUT_KEYBOARD") == Some("1")
+    }
+
+    /// ID_VENDOR_ID（なければsysfsの値）
+    pub fn vendor_id(&self) -> Option<&str> {
+        self.property("ID_VENDOR_ID").or(self.vendor.as_deref())
+    }
+
+    /// ID_PATH（接続位置。同じ型番のデバイスを区別できる）
+    pub fn id_path(&self) -> Option<&str> {
+        self.property("ID_PATH")
+    }
+
+    /// ID_SEAT（省略時は seat0）
+    pub fn seat(&self) -> &str {
+        self.property("ID_SEAT").unwrap_or("seat0")
+    }
+}
+
+/// `[device] touchpad` などのデバイスの指定
+///
+/// `ID_PATH=platform-i8042-serio-1` のように大文字のキーと `=` を含む場合はudevのプロパティ、
+/// それ以外は名前の一部として扱う
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    NameContains(String),
+    Property { key: String, value: String },
+}
+
+impl DeviceSelector {
+    pub fn parse(text: &str) -> Self {
+        match text.split_once('=') {
+            Some((key, value)) if is_property_key(key) => DeviceSelector::Property {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+            _ => DeviceSelector::NameContains(text.to_string()),
+        }
+    }
+
+    /// udevの情報が必要か（名前だけなら読まずに済む）
+    pub fn needs_metadata(&self) -> bool {
+        matches!(self, DeviceSelector::Property { .. })
+    }
+
+    pub fn matches(&self, name: Option<&str>, metadata: Option<&DeviceMetadata>) -> bool {
+        match self {
+            DeviceSelector::NameContains(pattern) => name.is_some_and(|n| n.contains(pattern)),
+            DeviceSelector::Property { key, value } => metadata.is_some_and(|m| {
+                // ID_SEATは省略時の値も一致させる
+                let actual = match key.as_str() {
+                    "ID_SEAT" => Some(m.seat()),
+                    "ID_VENDOR_ID" => m.vendor_id(),
+                    _ => m.property(key),
+                };
+                actual == Some(value.as_str())
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelector::NameContains(pattern) => write!(f, "name contains \"{}\"", pattern),
+            DeviceSelector::Property { key, value } => write!(f, "{}={}", key, value),
+        }
+    }
+}
+
+fn is_property_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// `dev` ファイルの `13:69`
+fn parse_devnum(text: &str) -> Option<(u32, u32)> {
+    let (major, minor) = text.trim().split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    let value = value.trim_end_matches('\n');
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// udevデータベースのファイルから `E:KEY=VALUE` を読む
+fn read_udev_data(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| line.strip_prefix("E:"))
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_root() -> SysRoot {
+        SysRoot::under(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysroot"))
+    }
+
+    #[test]
+    fn read_sysfs_and_udev_data() {
+        let root = fixture_root();
+
+        let pad = root.metadata(Path::new("/dev/input/event5")).unwrap();
+        assert_eq!(pad.devnum, (13, 69));
+        assert_eq!(pad.name.as_deref(), Some("SYNA2B52:00 06CB:CE78 Touchpad"));
+        assert!(pad.is_touchpad());
+        assert!(!pad.is_keyboard());
+        assert_eq!(pad.vendor_id(), Some("06cb"));
+        assert_eq!(
+            pad.id_path(),
+            Some("pci-0000:00:15.1-platform-i2c_designware.1")
+        );
+        assert_eq!(pad.seat(), "seat0");
+
+        let keyboard = root.metadata(Path::new("/dev/input/event3")).unwrap();
+        assert!(keyboard.is_keyboard());
+        assert_eq!(keyboard.seat(), "seat1");
+
+        assert_eq!(root.metadata(Path::new("/dev/input/event9")), None);
+    }
+
+    #[test]
+    fn missing_udev_database() {
+        // udevのない環境（コンテナなど）でもsysfsの情報は読める
+        let mut root = fixture_root();
+        root.udev_data = root.udev_data.join("missing");
+        let pad = root.metadata(Path::new("/dev/input/event5")).unwrap();
+        assert!(pad.properties.is_empty());
+        assert_eq!(pad.vendor_id(), Some("06cb"));
+    }
+
+    #[test]
+    fn parse_and_match_selectors() {
+        let root = fixture_root();
+        let pad = root.metadata(Path::new("/dev/input/event5")).unwrap();
+        let keyboard = root.metadata(Path::new("/dev/input/event3")).unwrap();
+
+        let selector = DeviceSelector::parse("ID_PATH=pci-0000:00:15.1-platform-i2c_designware.1");
+        assert!(selector.needs_metadata());
+        assert!(selector.matches(None, Some(&pad)));
+        assert!(!selector.matches(None, Some(&keyboard)));
+        assert!(!selector.matches(None, None));
+
+        let seat0 = DeviceSelector::parse("ID_SEAT=seat0");
+        assert!(seat0.matches(None, Some(&pad)));
+        assert!(!seat0.matches(None, Some(&keyboard)));
+        assert!(DeviceSelector::parse("ID_VENDOR_ID=0001").matches(None, Some(&keyboard)));
+
+        // 小文字や空白を含むものは名前として扱う
+        let name = DeviceSelector::parse("Touchpad mode=abs");
+        assert_eq!(
+            name,
+            DeviceSelector::NameContains("Touchpad mode=abs".to_string())
+        );
+        assert!(DeviceSelector::parse("SYNA").matches(pad.name.as_deref(), None));
+    }
+}
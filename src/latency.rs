@@ -0,0 +1,143 @@
+//! カーネルのタイムスタンプから出力までの遅延の計測
+//!
+//! evdevのイベントはカーネルが付けた時刻（CLOCK_REALTIME）を持つので、
+//! 出力を書き込んだ直後の時刻との差をそのイベントの処理にかかった時間とみなす
+
+use evdev::{InputEvent, InputEventKind, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// デーモンで保持する直近のサンプル数
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// カーネルのタイムスタンプから現在までの時間（時計が戻った場合は0）
+pub fn since(timestamp: SystemTime) -> Duration {
+    SystemTime::now()
+        .duration_since(timestamp)
+        .unwrap_or_default()
+}
+
+/// イベント列の最後のBTN_TOUCHのタイムスタンプ
+pub fn touch_timestamp(events: &[InputEvent]) -> Option<SystemTime> {
+    events
+        .iter()
+        .rev()
+        .find(|ev| ev.kind() == InputEventKind::Key(Key::BTN_TOUCH))
+        .map(InputEvent::timestamp)
+}
+
+/// 直近のサンプル（古いものから捨てる）
+#[derive(Debug, Clone)]
+pub struct LatencySamples {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl Default for LatencySamples {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl LatencySamples {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let micros = |q: f64| percentile(&sorted, q).map_or(0, |d| d.as_micros() as u64);
+        LatencySummary {
+            count: sorted.len(),
+            p50_us: micros(0.50),
+            p90_us: micros(0.90),
+            p99_us: micros(0.99),
+            max_us: sorted.last().map_or(0, |d| d.as_micros() as u64),
+        }
+    }
+}
+
+/// 最近接順位法（`sorted` は昇順）
+fn percentile(sorted: &[Duration], q: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// パーセンタイル（マイクロ秒）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl LatencySummary {
+    /// Prometheusのsummaryの `quantile` ラベルと値
+    pub fn quantiles(&self) -> [(&'static str, u64); 3] {
+        [
+            ("0.5", self.p50_us),
+            ("0.9", self.p90_us),
+            ("0.99", self.p99_us),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut samples = LatencySamples::default();
+        assert_eq!(samples.summary(), LatencySummary::default());
+
+        // 1..=100µs を逆順に入れても順位で決まる
+        for us in (1..=100).rev() {
+            samples.record(Duration::from_micros(us));
+        }
+        assert_eq!(
+            samples.summary(),
+            LatencySummary {
+                count: 100,
+                p50_us: 50,
+                p90_us: 90,
+                p99_us: 99,
+                max_us: 100,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_recent_samples() {
+        let mut samples = LatencySamples::with_capacity(3);
+        for us in [900, 1, 2, 3] {
+            samples.record(Duration::from_micros(us));
+        }
+        let summary = samples.summary();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.max_us, 3);
+    }
+
+    #[test]
+    fn clock_going_backwards() {
+        let future = SystemTime::now() + Duration::from_secs(60);
+        assert_eq!(since(future), Duration::ZERO);
+    }
+}
@@ -1,4 +1,20 @@
+pub mod calibrate;
+pub mod config;
+pub mod control;
 pub mod core;
+pub mod doctor;
+pub mod engine;
+pub mod event_loop;
+pub mod hooks;
 pub mod input;
+pub mod latency;
+pub mod logging;
+pub mod metrics;
+pub mod monitor;
 pub mod output;
+pub mod poll;
+pub mod recording;
+pub mod signals;
+pub mod snippet;
 pub mod tracker;
+pub mod watch;
@@ -0,0 +1,382 @@
+//! レベル付きのログ出力（logクレートのバックエンド）
+//!
+//! - モジュールごとのレベル: `info,way_thumbsense::engine=trace`
+//! - 出力先は標準エラー（通常の形式かjournald形式）またはファイル
+//! - キーボードのキーコードは `log_keys` を有効にしない限り伏せる（[`key`] を参照）
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// キーコードをログに出すか
+static LOG_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// ログの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `INFO way_thumbsense::engine: ...`
+    Plain,
+    /// 行頭に `<6>` のような優先度を付ける（systemdのサービスとして動かす場合）
+    Journald,
+}
+
+impl LogFormat {
+    /// systemdから起動されていればjournald形式
+    pub fn detect() -> Self {
+        if std::env::var_os("JOURNAL_STREAM").is_some() {
+            LogFormat::Journald
+        } else {
+            LogFormat::Plain
+        }
+    }
+}
+
+/// モジュールごとのレベル
+///
+/// `warn` のような全体のレベルと、`way_thumbsense::hooks=debug` のような
+/// モジュール（とその子）のレベルをカンマで区切って並べる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> Result<Self, LoggingError> {
+        let mut filter = LogFilter {
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => filter
+                    .modules
+                    .push((module.trim().to_string(), parse_level(level.trim())?)),
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        // 長い（より具体的な）指定を優先する
+        filter
+            .modules
+            .sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(filter)
+    }
+
+    /// `target`（モジュールパス）に適用されるレベル
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    /// いずれかのモジュールで出力されうる最も詳しいレベル
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+fn parse_level(text: &str) -> Result<LevelFilter, LoggingError> {
+    text.parse()
+        .map_err(|_| LoggingError::InvalidLevel(text.to_string()))
+}
+
+/// ロガーの設定
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub filter: LogFilter,
+    pub format: LogFormat,
+    /// 指定があれば標準エラーの代わりに追記する
+    pub file: Option<PathBuf>,
+    /// キーボードのキーコードをそのまま出す
+    pub log_keys: bool,
+}
+
+/// グローバルなロガーを設定（プロセスで1回だけ）
+pub fn init(options: LogOptions) -> Result<(), LoggingError> {
+    let file = match &options.file {
+        Some(path) => Some(Mutex::new(open_log_file(path)?)),
+        None => None,
+    };
+    LOG_KEYS.store(options.log_keys, Ordering::Relaxed);
+    log::set_max_level(options.filter.max_level());
+    // プロセスの終了まで使うのでリークさせて 'static にする
+    let logger = Box::leak(Box::new(Logger {
+        filter: options.filter,
+        format: options.format,
+        file,
+    }));
+    log::set_logger(logger).map_err(|_| LoggingError::AlreadyInitialized)
+}
+
+/// 新しく作るときは本人だけが読めるようにする（`log_keys` で入力内容が残りうる）
+fn open_log_file(path: &Path) -> Result<File, LoggingError> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| LoggingError::File(path.to_path_buf(), e))
+}
+
+struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        match &self.file {
+            Some(file) => {
+                // ファイルには時刻を付ける（journaldは自分で付ける）
+                let line = format_line(
+                    self.format,
+                    record.level(),
+                    record.target(),
+                    &message,
+                    Some(SystemTime::now()),
+                );
+                if let Ok(mut file) = file.lock() {
+                    let _ = file.write_all(line.as_bytes());
+                }
+            }
+            None => {
+                let line =
+                    format_line(self.format, record.level(), record.target(), &message, None);
+                let _ = io::stderr().lock().write_all(line.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(Ok(mut file)) = self.file.as_ref().map(Mutex::lock) {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// 1行分の出力（改行付き）
+pub fn format_line(
+    format: LogFormat,
+    level: Level,
+    target: &str,
+    message: &str,
+    time: Option<SystemTime>,
+) -> String {
+    let mut line = String::new();
+    match format {
+        LogFormat::Plain => {
+            if let Some(time) = time {
+                line.push_str(&format_timestamp(time));
+                line.push(' ');
+            }
+            line.push_str(&format!("{:<5} {}: {}", level, target, message));
+        }
+        LogFormat::Journald => {
+            line.push_str(&format!(
+                "<{}>{}: {}",
+                syslog_priority(level),
+                target,
+                message
+            ));
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// sd-daemon(3) の優先度
+fn syslog_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// UTCのRFC 3339形式（ミリ秒まで）
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // 1970-01-01からの日数を年月日に変換（Howard Hinnantのcivil_from_days）
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// キーボードから読んだキーコードをログに出すときに使う
+///
+/// `log_keys` を有効にしていなければ `[key]` と表示し、
+/// ログファイルが入力内容の記録にならないようにする
+pub fn key(code: u16) -> KeyLog {
+    KeyLog {
+        code,
+        visible: LOG_KEYS.load(Ordering::Relaxed),
+    }
+}
+
+/// [`key`] の表示
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyLog {
+    code: u16,
+    /// 作った時点の `log_keys`
+    visible: bool,
+}
+
+impl std::fmt::Display for KeyLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.visible {
+            write!(f, "{:?}", evdev::Key::new(self.code))
+        } else {
+            f.write_str("[key]")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoggingError {
+    InvalidLevel(String),
+    File(PathBuf, io::Error),
+    AlreadyInitialized,
+}
+
+impl std::fmt::Display for LoggingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoggingError::InvalidLevel(level) => write!(
+                f,
+                "Invalid log level '{}' (expected off, error, warn, info, debug or trace)",
+                level
+            ),
+            LoggingError::File(path, e) => {
+                write!(f, "Cannot open log file {}: {}", path.display(), e)
+            }
+            LoggingError::AlreadyInitialized => write!(f, "Logger is already initialized"),
+        }
+    }
+}
+
+impl std::error::Error for LoggingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_levels() {
+        let filter =
+            LogFilter::parse("warn, way_thumbsense::engine=trace,way_thumbsense=info").unwrap();
+        assert_eq!(
+            filter.level_for("way_thumbsense::engine"),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            filter.level_for("way_thumbsense::engine::inner"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.level_for("way_thumbsense::hooks"), LevelFilter::Info);
+        // 名前の途中では一致しない
+        assert_eq!(filter.level_for("way_thumbsense_extra"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("evdev"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        assert!(matches!(
+            LogFilter::parse("verbose"),
+            Err(LoggingError::InvalidLevel(_))
+        ));
+        assert_eq!(LogFilter::parse("").unwrap().max_level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn line_formats() {
+        let time = UNIX_EPOCH + std::time::Duration::from_millis(1_792_325_045_123);
+        assert_eq!(
+            format_line(
+                LogFormat::Plain,
+                Level::Info,
+                "way_thumbsense",
+                "started",
+                Some(time)
+            ),
+            "2026-10-18T12:04:05.123Z INFO  way_thumbsense: started\n"
+        );
+        assert_eq!(
+            format_line(LogFormat::Plain, Level::Debug, "x", "m", None),
+            "DEBUG x: m\n"
+        );
+        assert_eq!(
+            format_line(LogFormat::Journald, Level::Warn, "x", "m", Some(time)),
+            "<4>x: m\n"
+        );
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn keys_are_redacted_by_default() {
+        // 他のテストと並行して動くのでグローバルな設定は変えない
+        assert_eq!(key(36).to_string(), "[key]");
+        let visible = KeyLog {
+            code: 36,
+            visible: true,
+        };
+        assert_eq!(visible.to_string(), "KEY_J");
+    }
+
+    #[test]
+    fn log_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("way-thumbsense-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        drop(open_log_file(&path).unwrap());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -3,163 +3,167 @@
 //! タッチパッドに触れている間、仮想キー(F24)を押し続ける
 //! keydでF24をmousenavレイヤーのトリガーにすることでThumbSenseを実現
 
-use clap::Parser;
-use evdev::{AbsoluteAxisType, InputEventKind, Key};
-use way_thumbsense::input::{find_touchpad, get_touchpad_dimensions};
-use way_thumbsense::output::VirtualDevice;
-use way_thumbsense::tracker::{ExclusionZones, TouchTracker};
+mod cli;
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use way_thumbsense::config::BackendKind;
 
 /// ThumbSense implementation for Linux/Wayland
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
-    /// 上端の除外割合 (0.0 - 100.0)
-    #[arg(long, default_value_t = 0.0)]
-    exclude_top: f32,
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// 設定ファイル（省略時は $XDG_CONFIG_HOME/way-thumbsense/config.toml）
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// 制御ソケット（省略時は $XDG_RUNTIME_DIR/way-thumbsense.sock）
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// レイヤー切り替えの出力方式（設定ファイルより優先）
+    #[arg(long, global = true, value_enum)]
+    backend: Option<BackendKind>,
 
-    /// 下端の除外割合 (0.0 - 100.0)
-    #[arg(long, default_value_t = 0.0)]
-    exclude_bottom: f32,
+    /// 上端の除外割合 (0.0 - 100.0、設定ファイルより優先)
+    #[arg(long, global = true)]
+    exclude_top: Option<f32>,
 
-    /// 左端の除外割合 (0.0 - 100.0)
-    #[arg(long, default_value_t = 0.0)]
-    exclude_left: f32,
+    /// 下端の除外割合 (0.0 - 100.0、設定ファイルより優先)
+    #[arg(long, global = true)]
+    exclude_bottom: Option<f32>,
 
-    /// 右端の除外割合 (0.0 - 100.0)
-    #[arg(long, default_value_t = 0.0)]
-    exclude_right: f32,
+    /// 左端の除外割合 (0.0 - 100.0、設定ファイルより優先)
+    #[arg(long, global = true)]
+    exclude_left: Option<f32>,
 
-    /// デバッグ情報を表示
-    #[arg(long, default_value_t = false)]
+    /// 右端の除外割合 (0.0 - 100.0、設定ファイルより優先)
+    #[arg(long, global = true)]
+    exclude_right: Option<f32>,
+
+    /// デバッグ情報を表示（`--log-level debug` と同じ）
+    #[arg(long, global = true, default_value_t = false)]
     debug: bool,
+
+    /// ログのレベル（例: `debug`、`info,way_thumbsense::engine=trace`）
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    /// ログの形式（省略時はsystemdから起動されていればjournald）
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<way_thumbsense::logging::LogFormat>,
+
+    /// 標準エラーの代わりにログを追記するファイル
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// キーボードのキーコードをログに出す（入力内容が残るので注意）
+    #[arg(long, global = true)]
+    log_keys: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 入力デバイスの一覧と、タッチパッド/キーボードとして選ばれるかを表示
+    ListDevices {
+        /// JSONで出力
+        #[arg(long)]
+        json: bool,
+    },
+    /// 権限・uinput・デバイス・keydの設定などセットアップを確認
+    Doctor,
+    /// トリガーキーとマッピングからkeyd/kanataの設定を生成して表示
+    GenConfig {
+        #[arg(long, value_enum)]
+        target: way_thumbsense::snippet::Target,
+    },
+    /// タッチパッドの範囲と除外領域を測って設定ファイルに書き込む
+    Calibrate {
+        /// 除外領域に足す余白 (%)
+        #[arg(long, default_value_t = 5.0)]
+        margin: f32,
+
+        /// 書き込まずに結果を表示するだけ
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// タッチパッド・除外領域・接触点とレイヤーの判定を端末に表示
+    Monitor,
+    /// タッチパッドとキーボードのイベントをファイルに記録（Ctrl+Cで終了）
+    Record {
+        /// 記録先のファイル
+        output: PathBuf,
+
+        /// マッピング以外のキーも記録する（入力内容が残るので注意）
+        #[arg(long)]
+        all_keys: bool,
+    },
+    /// 記録したイベントをエンジンに流して出力を表示
+    Replay {
+        /// recordまたはevemu-recordで記録したファイル
+        input: PathBuf,
+
+        /// 表示だけでなく、記録時の間隔でレイヤーを実際に切り替える
+        #[arg(long)]
+        emit: bool,
+
+        /// evemu-recordの記録から仮想タッチパッドを作ってイベントを送る
+        #[arg(long, conflicts_with = "emit")]
+        recreate: bool,
+    },
+    /// uinputの仮想タッチパッド・キーボードで入力から出力までの遅延を測る
+    BenchLatency {
+        /// タッチの回数
+        #[arg(long, default_value_t = 200)]
+        iterations: usize,
+
+        /// タッチの間隔 (ms)
+        #[arg(long, default_value_t = 20)]
+        interval_ms: u64,
+    },
+    /// 実行中のデーモンを操作
+    Ctl {
+        #[command(subcommand)]
+        action: cli::ctl::CtlAction,
+    },
+    /// ステータスバー向けに状態をJSONで出力
+    Status {
+        /// 状態が変わるたびに1行ずつ出力し続ける
+        #[arg(long)]
+        follow: bool,
+
+        #[arg(long, value_enum, default_value_t = cli::status::Format::Waybar)]
+        format: cli::status::Format,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-
-    println!("way-thumbsense starting...");
-
-    // タッチパッドを検出
-    let mut touchpad = find_touchpad()?;
-    println!("Touchpad: {}", touchpad.name().unwrap_or("unknown"));
-
-    // タッチパッドの寸法を取得
-    let dimensions = get_touchpad_dimensions(&touchpad)
-        .ok_or_else(|| anyhow::anyhow!("Failed to get touchpad dimensions"))?;
-    println!(
-        "Touchpad dimensions: X({} to {}), Y({} to {})",
-        dimensions.min_x, dimensions.max_x, dimensions.min_y, dimensions.max_y
-    );
-
-    // 除外領域の設定
-    let exclusion_zones = ExclusionZones::new(
-        args.exclude_top,
-        args.exclude_bottom,
-        args.exclude_left,
-        args.exclude_right,
-    );
-
-    if exclusion_zones.top > 0.0
-        || exclusion_zones.bottom > 0.0
-        || exclusion_zones.left > 0.0
-        || exclusion_zones.right > 0.0
-    {
-        println!(
-            "Exclusion zones: top={}%, bottom={}%, left={}%, right={}%",
-            exclusion_zones.top, exclusion_zones.bottom, exclusion_zones.left, exclusion_zones.right
-        );
-    } else {
-        println!("Exclusion zones: disabled");
-    }
-
-    // タッチトラッカーを初期化
-    let mut tracker = TouchTracker::new(dimensions, exclusion_zones);
-
-    // 仮想キーボードを作成
-    let mut vdev = VirtualDevice::new()?;
-    println!("Virtual keyboard created");
-
-    println!("\nRunning... (Ctrl+C to exit)");
-    println!("Touch trackpad to activate F24 (mousenav layer)\n");
-
-    let mut is_touching = false;
-    let mut f24_active = false; // F24が実際に押されているか
-
-    loop {
-        match touchpad.fetch_events() {
-            Ok(events) => {
-                // イベントをVecに集める
-                let events_vec: Vec<_> = events.collect();
-
-                // 1st pass: 座標を更新
-                for ev in &events_vec {
-                    match ev.kind() {
-                        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X)
-                        | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
-                            tracker.update_x(ev.value());
-                        }
-                        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y)
-                        | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
-                            tracker.update_y(ev.value());
-                        }
-                        _ => {}
-                    }
-                }
-
-                // 2nd pass: BTN_TOUCHを処理
-                for ev in &events_vec {
-                    if let InputEventKind::Key(key) = ev.kind() {
-                        if key == Key::BTN_TOUCH {
-                            let now_touching = ev.value() == 1;
-
-                            if now_touching != is_touching {
-                                is_touching = now_touching;
-
-                                if is_touching {
-                                    // タッチ開始
-                                    let in_exclusion = tracker.is_in_exclusion_zone();
-
-                                    if args.debug {
-                                        println!(
-                                            "[Touch] {} -> excluded: {}",
-                                            tracker.debug_info(),
-                                            in_exclusion
-                                        );
-                                    }
-
-                                    if !in_exclusion {
-                                        // 除外領域外でのタッチ → F24を押す
-                                        if args.debug {
-                                            println!("[Touch] -> F24 press");
-                                        }
-                                        vdev.forward_key(Key::KEY_F24, 1)?;
-                                        f24_active = true;
-                                    } else if args.debug {
-                                        println!("[Touch] -> F24 not pressed (in exclusion zone)");
-                                    }
-                                } else {
-                                    // タッチ終了
-                                    if f24_active {
-                                        // F24を離す
-                                        if args.debug {
-                                            println!("[Touch] -> F24 release");
-                                        }
-                                        vdev.forward_key(Key::KEY_F24, 0)?;
-                                        f24_active = false;
-                                    }
-                                    tracker.reset();
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Touchpad error: {}", e);
-                break;
-            }
+    cli::init_logging(&args)?;
+
+    match &args.command {
+        Some(Command::ListDevices { json }) => cli::list_devices::run(&args, *json),
+        Some(Command::Doctor) => cli::doctor::run(&args),
+        Some(Command::GenConfig { target }) => cli::gen_config::run(&args, *target),
+        Some(Command::Calibrate { margin, dry_run }) => {
+            cli::calibrate::run(&args, *margin, *dry_run)
         }
+        Some(Command::Monitor) => cli::monitor::run(&args),
+        Some(Command::Record { output, all_keys }) => cli::record::run(&args, output, *all_keys),
+        Some(Command::Replay {
+            input,
+            emit,
+            recreate,
+        }) => cli::replay::run(&args, input, *emit, *recreate),
+        Some(Command::BenchLatency {
+            iterations,
+            interval_ms,
+        }) => cli::bench_latency::run(&args, *iterations, *interval_ms),
+        Some(Command::Ctl { action }) => cli::ctl::run(&args, action),
+        Some(Command::Status { follow, format }) => cli::status::run(&args, *follow, *format),
+        None => cli::daemon::run(&args),
     }
-
-    Ok(())
 }
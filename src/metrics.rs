@@ -0,0 +1,430 @@
+//! デーモンの動作の集計（除外領域の大きさを調整するため）
+//!
+//! `ctl metrics` で問い合わせるほか、`[metrics] socket` を指定すると
+//! Prometheusのテキスト形式を返すUnixソケットを開く:
+//!
+//! ```text
+//! curl --unix-socket $XDG_RUNTIME_DIR/way-thumbsense-metrics.sock http://localhost/metrics
+//! ```
+
+use crate::engine::TouchEvent;
+use crate::latency::{LatencySamples, LatencySummary};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// トリガーを押していた時間のヒストグラムの上限（秒）
+pub const HOLD_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// HTTPのリクエストを待つ時間（来なければテキストだけ返す）
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// 集計値
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// レイヤーを有効化したタッチ
+    pub activations: u64,
+    /// 除外領域で無視したタッチ（辺ごと）
+    pub exclusions: BTreeMap<String, u64>,
+    /// 手のひらとして無視したタッチ
+    pub palm_rejections: u64,
+    /// 切断されたタッチパッドを開き直した回数
+    pub reconnects: u64,
+    /// トリガーを押していた時間
+    pub hold_seconds: Histogram,
+    /// BTN_TOUCHのカーネルのタイムスタンプから出力の書き込みまで（直近のタッチ）
+    #[serde(default)]
+    pub touch_latency: LatencySummary,
+    /// マッピングしたキーのカーネルのタイムスタンプからクリックの書き込みまで（`[device] keyboard`）
+    #[serde(default)]
+    pub key_latency: LatencySummary,
+}
+
+impl Default for MetricsSnapshot {
+    fn default() -> Self {
+        Self {
+            activations: 0,
+            exclusions: BTreeMap::new(),
+            palm_rejections: 0,
+            reconnects: 0,
+            hold_seconds: Histogram::new(HOLD_BUCKETS),
+            touch_latency: LatencySummary::default(),
+            key_latency: LatencySummary::default(),
+        }
+    }
+}
+
+/// 累積のヒストグラム（Prometheusと同じく `counts[i]` は `bounds[i]` 以下の数）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub bounds: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(&mut self.counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// エンジンの状態変化から集計する
+#[derive(Debug, Default)]
+pub struct Metrics {
+    snapshot: MetricsSnapshot,
+    /// 有効化した時刻（押していた時間の計測用）
+    active_since: Option<Instant>,
+    touch_latency: LatencySamples,
+    key_latency: LatencySamples,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, event: &TouchEvent, now: Instant) {
+        let snapshot = &mut self.snapshot;
+        match event {
+            TouchEvent::Activated { .. } => {
+                snapshot.activations += 1;
+                self.active_since = Some(now);
+            }
+            TouchEvent::Excluded { edge, .. } => {
+                *snapshot
+                    .exclusions
+                    .entry(edge.as_str().to_string())
+                    .or_default() += 1;
+            }
+            TouchEvent::PalmRejected { .. } => snapshot.palm_rejections += 1,
+            TouchEvent::Deactivated => {
+                if let Some(since) = self.active_since.take() {
+                    snapshot
+                        .hold_seconds
+                        .observe(now.saturating_duration_since(since).as_secs_f64());
+                }
+            }
+        }
+    }
+
+    /// 切断されたタッチパッドを開き直した（設定の再読み込みは数えない）
+    pub fn record_reconnect(&mut self) {
+        self.snapshot.reconnects += 1;
+    }
+
+    /// タッチの状態変化を出力に書き込むまでの時間
+    pub fn record_touch_latency(&mut self, latency: Duration) {
+        self.touch_latency.record(latency);
+    }
+
+    /// マッピングしたキーをクリックとして書き込むまでの時間
+    pub fn record_key_latency(&mut self, latency: Duration) {
+        self.key_latency.record(latency);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            touch_latency: self.touch_latency.summary(),
+            key_latency: self.key_latency.summary(),
+            ..self.snapshot.clone()
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Prometheusのテキスト形式（version 0.0.4）
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "activations_total",
+            "Touches that activated the layer.",
+        );
+        writeln!(out, "way_thumbsense_activations_total {}", self.activations).unwrap();
+
+        counter(
+            &mut out,
+            "exclusions_total",
+            "Touches ignored because they started in an exclusion zone.",
+        );
+        for edge in ["top", "bottom", "left", "right"] {
+            writeln!(
+                out,
+                "way_thumbsense_exclusions_total{{edge=\"{}\"}} {}",
+                edge,
+                self.exclusions.get(edge).copied().unwrap_or(0)
+            )
+            .unwrap();
+        }
+
+        counter(
+            &mut out,
+            "palm_rejections_total",
+            "Touches ignored because the kernel reported a palm.",
+        );
+        writeln!(
+            out,
+            "way_thumbsense_palm_rejections_total {}",
+            self.palm_rejections
+        )
+        .unwrap();
+
+        counter(
+            &mut out,
+            "reconnects_total",
+            "Times the touchpad was reopened after being disconnected.",
+        );
+        writeln!(out, "way_thumbsense_reconnects_total {}", self.reconnects).unwrap();
+
+        let hold = &self.hold_seconds;
+        out.push_str(
+            "# HELP way_thumbsense_hold_duration_seconds How long the trigger was held.\n\
+             # TYPE way_thumbsense_hold_duration_seconds histogram\n",
+        );
+        for (bound, count) in hold.bounds.iter().zip(&hold.counts) {
+            writeln!(
+                out,
+                "way_thumbsense_hold_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "way_thumbsense_hold_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            hold.count
+        )
+        .unwrap();
+        writeln!(out, "way_thumbsense_hold_duration_seconds_sum {}", hold.sum).unwrap();
+        writeln!(
+            out,
+            "way_thumbsense_hold_duration_seconds_count {}",
+            hold.count
+        )
+        .unwrap();
+
+        latency(
+            &mut out,
+            "touch_latency_seconds",
+            "Recent latency from the kernel touch timestamp to the output write.",
+            &self.touch_latency,
+        );
+        latency(
+            &mut out,
+            "key_latency_seconds",
+            "Recent latency from the kernel timestamp of a mapped key to the click write.",
+            &self.key_latency,
+        );
+        out
+    }
+}
+
+/// 直近のサンプルの分位数なので単調増加しない（gauge）
+fn latency(out: &mut String, name: &str, help: &str, summary: &LatencySummary) {
+    writeln!(out, "# HELP way_thumbsense_{} {}", name, help).unwrap();
+    writeln!(out, "# TYPE way_thumbsense_{} gauge", name).unwrap();
+    for (quantile, micros) in summary.quantiles() {
+        writeln!(
+            out,
+            "way_thumbsense_{}{{quantile=\"{}\"}} {}",
+            name,
+            quantile,
+            micros as f64 / 1e6
+        )
+        .unwrap();
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str) {
+    writeln!(out, "# HELP way_thumbsense_{} {}", name, help).unwrap();
+    writeln!(out, "# TYPE way_thumbsense_{} counter", name).unwrap();
+}
+
+/// Prometheusのテキスト形式を返すUnixソケット
+///
+/// HTTPのリクエスト（`curl --unix-socket`）にはHTTPの応答を、
+/// 何も送らない接続（`socat`）には本文だけを返す
+pub struct MetricsServer {
+    path: PathBuf,
+}
+
+impl MetricsServer {
+    pub fn bind(path: &Path, metrics: Arc<Mutex<Metrics>>) -> io::Result<Self> {
+        // 前回の残骸を削除（動作中のデーモンがいればエラー）
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let metrics = Arc::clone(&metrics);
+                thread::spawn(move || {
+                    let _ = serve_connection(stream, &metrics);
+                });
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn serve_connection(stream: UnixStream, metrics: &Mutex<Metrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    // タイムアウトは本文だけを返す
+    let _ = reader.read_line(&mut request_line);
+    let is_http = request_line.contains(" HTTP/");
+    if is_http {
+        // ヘッダーを読み捨てる
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+            line.clear();
+        }
+    }
+
+    let body = metrics.lock().unwrap().snapshot().to_prometheus();
+    if is_http {
+        write!(
+            writer,
+            "HTTP/1.0 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            body.len()
+        )?;
+    }
+    writer.write_all(body.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::Edge;
+    use std::io::Read;
+
+    fn sample() -> Metrics {
+        let start = Instant::now();
+        let mut metrics = Metrics::new();
+        metrics.observe(&TouchEvent::Activated { x: None, y: None }, start);
+        metrics.observe(&TouchEvent::Deactivated, start + Duration::from_millis(300));
+        metrics.observe(
+            &TouchEvent::Excluded {
+                x: 0,
+                y: 0,
+                edge: Edge::Bottom,
+            },
+            start,
+        );
+        metrics.observe(&TouchEvent::PalmRejected { x: None, y: None }, start);
+        // 有効化していなければ時間は数えない
+        metrics.observe(&TouchEvent::Deactivated, start);
+        metrics.record_reconnect();
+        metrics.record_touch_latency(Duration::from_micros(250));
+        metrics.record_key_latency(Duration::from_micros(1500));
+        metrics
+    }
+
+    #[test]
+    fn counts_events_and_hold_durations() {
+        let snapshot = sample().snapshot();
+        assert_eq!(snapshot.activations, 1);
+        assert_eq!(snapshot.exclusions.get("bottom"), Some(&1));
+        assert_eq!(snapshot.palm_rejections, 1);
+        assert_eq!(snapshot.reconnects, 1);
+
+        let hold = &snapshot.hold_seconds;
+        assert_eq!(hold.count, 1);
+        assert!((hold.sum - 0.3).abs() < 1e-9);
+        // 0.25秒以下には入らず、0.5秒以下から累積
+        assert_eq!(hold.counts[..5], [0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn prometheus_text_format() {
+        let text = sample().snapshot().to_prometheus();
+        assert!(text.contains("# TYPE way_thumbsense_activations_total counter\n"));
+        assert!(text.contains("way_thumbsense_activations_total 1\n"));
+        assert!(text.contains("way_thumbsense_exclusions_total{edge=\"bottom\"} 1\n"));
+        assert!(text.contains("way_thumbsense_exclusions_total{edge=\"top\"} 0\n"));
+        assert!(text.contains("way_thumbsense_hold_duration_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("way_thumbsense_hold_duration_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("way_thumbsense_hold_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("way_thumbsense_hold_duration_seconds_count 1\n"));
+        assert!(text.contains("way_thumbsense_touch_latency_seconds{quantile=\"0.99\"} 0.00025\n"));
+        assert!(text.contains("# TYPE way_thumbsense_key_latency_seconds gauge\n"));
+        assert!(text.contains("way_thumbsense_key_latency_seconds{quantile=\"0.5\"} 0.0015\n"));
+    }
+
+    #[test]
+    fn serves_http_and_plain_text() {
+        let path = std::env::temp_dir().join(format!(
+            "way-thumbsense-metrics-{}.sock",
+            std::process::id()
+        ));
+        let metrics = Arc::new(Mutex::new(sample()));
+        let server = MetricsServer::bind(&path, Arc::clone(&metrics)).unwrap();
+
+        let mut stream = UnixStream::connect(server.path()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.contains("\r\n\r\n# HELP way_thumbsense_activations_total"));
+
+        // リクエストなしで接続した場合は本文だけ
+        metrics.lock().unwrap().record_reconnect();
+        let mut plain = String::new();
+        UnixStream::connect(server.path())
+            .unwrap()
+            .read_to_string(&mut plain)
+            .unwrap();
+        assert!(plain.starts_with("# HELP"));
+        assert!(plain.contains("way_thumbsense_reconnects_total 2\n"));
+    }
+}
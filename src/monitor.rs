@@ -0,0 +1,395 @@
+//! monitor サブコマンドの表示内容（端末への出力とは独立）
+//!
+//! タッチパッドを縮小した枠に除外領域と接触点を描き、
+//! タッチごとにレイヤーを有効化した/しなかった理由を記録する
+
+use crate::engine::{Engine, TouchEvent};
+use crate::input::TouchpadDimensions;
+use crate::tracker::{ExclusionZones, TouchTracker};
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key};
+use std::collections::{BTreeMap, VecDeque};
+
+/// 保持する判定履歴の件数
+const LOG_LEN: usize = 8;
+
+/// 枠と履歴以外に使う行数（最後の1行は空けておく）
+const STATUS_LINES: usize = 5;
+
+/// スロットごとの接触点
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Contact {
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+
+/// マルチタッチのスロットを追跡
+///
+/// MTプロトコルBに対応し、スロットのないデバイスはBTN_TOUCHとABS_X/Yで
+/// スロット0として扱う
+#[derive(Debug, Default)]
+pub struct Contacts {
+    slots: BTreeMap<i32, Contact>,
+    current_slot: i32,
+    multitouch: bool,
+}
+
+impl Contacts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// fetch_eventsで得たイベント列を反映
+    pub fn process(&mut self, events: &[InputEvent]) {
+        for ev in events {
+            match ev.kind() {
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => {
+                    self.current_slot = ev.value();
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                    self.multitouch = true;
+                    if ev.value() < 0 {
+                        self.slots.remove(&self.current_slot);
+                    } else {
+                        self.slots.entry(self.current_slot).or_default();
+                    }
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                    self.slots.entry(self.current_slot).or_default().x = Some(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                    self.slots.entry(self.current_slot).or_default().y = Some(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) if !self.multitouch => {
+                    self.slots.entry(0).or_default().x = Some(ev.value());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) if !self.multitouch => {
+                    self.slots.entry(0).or_default().y = Some(ev.value());
+                }
+                InputEventKind::Key(Key::BTN_TOUCH) if !self.multitouch && ev.value() == 0 => {
+                    self.slots.clear();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 現在触れているスロットと座標
+    pub fn iter(&self) -> impl Iterator<Item = (i32, Contact)> + '_ {
+        self.slots.iter().map(|(&slot, &contact)| (slot, contact))
+    }
+}
+
+/// monitor の表示状態
+#[derive(Debug)]
+pub struct Monitor {
+    device: String,
+    engine: Engine,
+    contacts: Contacts,
+    log: VecDeque<String>,
+    touches: u64,
+}
+
+impl Monitor {
+    pub fn new(device: &str, dimensions: TouchpadDimensions, zones: ExclusionZones) -> Self {
+        Self {
+            device: device.to_string(),
+            engine: Engine::new(TouchTracker::new(dimensions, zones)),
+            contacts: Contacts::new(),
+            log: VecDeque::new(),
+            touches: 0,
+        }
+    }
+
+    /// 除外領域を差し替え（設定ファイルの変更時）
+    pub fn set_exclusion_zones(&mut self, zones: ExclusionZones) {
+        self.engine.set_exclusion_zones(zones);
+        self.push_log(format!(
+            "zones changed: top={}%, bottom={}%, left={}%, right={}%",
+            zones.top, zones.bottom, zones.left, zones.right
+        ));
+    }
+
+    /// イベント列を処理して判定を記録
+    pub fn process(&mut self, events: &[InputEvent]) {
+        self.contacts.process(events);
+        for event in self.engine.process(events) {
+            let line = self.describe(event);
+            self.push_log(line);
+        }
+    }
+
+    fn describe(&mut self, event: TouchEvent) -> String {
+        match event {
+            TouchEvent::Activated { x, y } => {
+                self.touches += 1;
+                format!(
+                    "#{} touch at {} -> activated (outside exclusion zones)",
+                    self.touches,
+                    self.format_position(x, y)
+                )
+            }
+            TouchEvent::Excluded { x, y, edge } => {
+                self.touches += 1;
+                format!(
+                    "#{} touch at {} -> not activated: in {} zone",
+                    self.touches,
+                    self.format_position(Some(x), Some(y)),
+                    edge.as_str()
+                )
+            }
+            TouchEvent::PalmRejected { x, y } => {
+                self.touches += 1;
+                format!(
+                    "#{} touch at {} -> not activated: palm",
+                    self.touches,
+                    self.format_position(x, y)
+                )
+            }
+            TouchEvent::Deactivated => format!("#{} released -> deactivated", self.touches),
+        }
+    }
+
+    fn format_position(&self, x: Option<i32>, y: Option<i32>) -> String {
+        let dims = self.engine.tracker().dimensions();
+        match (x, y) {
+            (Some(x), Some(y)) => format!(
+                "({:.0}%, {:.0}%)",
+                percent(x, dims.min_x, dims.width()),
+                percent(y, dims.min_y, dims.height())
+            ),
+            _ => "unknown position".to_string(),
+        }
+    }
+
+    fn push_log(&mut self, line: String) {
+        if self.log.len() == LOG_LEN {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    /// 端末の大きさに合わせて1画面分の行を描画
+    pub fn render(&self, cols: usize, rows: usize) -> Vec<String> {
+        let dims = *self.engine.tracker().dimensions();
+        let zones = *self.engine.tracker().exclusion_zones();
+        let (width, height) = pad_size(&dims, cols, rows.saturating_sub(STATUS_LINES + LOG_LEN));
+
+        let mut lines = vec![format!(
+            "way-thumbsense monitor - {}  (q to quit)",
+            self.device
+        )];
+
+        if width > 0 && height > 0 {
+            let grid = self.draw_pad(&dims, zones, width, height);
+            lines.push(format!("┌{}┐", "─".repeat(width)));
+            for row in grid {
+                lines.push(format!("│{}│", row.into_iter().collect::<String>()));
+            }
+            lines.push(format!("└{}┘", "─".repeat(width)));
+        }
+
+        lines.push(format!(
+            "Range: X({} to {}), Y({} to {})  Zones: top={}%, bottom={}%, left={}%, right={}%",
+            dims.min_x,
+            dims.max_x,
+            dims.min_y,
+            dims.max_y,
+            zones.top,
+            zones.bottom,
+            zones.left,
+            zones.right
+        ));
+        lines.push(format!(
+            "Layer: {}  Touching: {}  Contacts: {}",
+            if self.engine.is_active() {
+                "ACTIVE"
+            } else {
+                "inactive"
+            },
+            if self.engine.is_touching() {
+                "yes"
+            } else {
+                "no"
+            },
+            self.contacts.iter().count()
+        ));
+        lines.push(String::new());
+        lines.extend(self.log.iter().cloned());
+        lines
+    }
+
+    /// 枠内のセル: 除外領域は '░'、接触点はスロット番号
+    fn draw_pad(
+        &self,
+        dims: &TouchpadDimensions,
+        zones: ExclusionZones,
+        width: usize,
+        height: usize,
+    ) -> Vec<Vec<char>> {
+        // 判定はデーモンと同じTouchTrackerで行う
+        let mut probe = TouchTracker::new(*dims, zones);
+        let mut grid: Vec<Vec<char>> = (0..height)
+            .map(|row| {
+                (0..width)
+                    .map(|col| {
+                        probe.update_x(cell_center(col, width, dims.min_x, dims.width()));
+                        probe.update_y(cell_center(row, height, dims.min_y, dims.height()));
+                        if probe.is_in_exclusion_zone() {
+                            '░'
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (slot, contact) in self.contacts.iter() {
+            if let (Some(x), Some(y)) = (contact.x, contact.y) {
+                let col = cell_index(x, dims.min_x, dims.width(), width);
+                let row = cell_index(y, dims.min_y, dims.height(), height);
+                grid[row][col] = char::from_digit(slot.rem_euclid(10) as u32, 10).unwrap_or('*');
+            }
+        }
+
+        grid
+    }
+}
+
+fn percent(value: i32, min: i32, range: i32) -> f32 {
+    (value - min) as f32 / range.max(1) as f32 * 100.0
+}
+
+/// セルの中心に当たる座標
+fn cell_center(index: usize, cells: usize, min: i32, range: i32) -> i32 {
+    min + ((index as f64 + 0.5) / cells as f64 * range as f64) as i32
+}
+
+/// 座標が入るセル（範囲外は端のセル）
+fn cell_index(value: i32, min: i32, range: i32, cells: usize) -> usize {
+    let ratio = (value - min) as f64 / range.max(1) as f64;
+    ((ratio * cells as f64) as isize).clamp(0, cells as isize - 1) as usize
+}
+
+/// 縦横比を保って枠の内側の大きさを決める（端末のセルは縦長なので高さを半分に）
+fn pad_size(dims: &TouchpadDimensions, cols: usize, rows: usize) -> (usize, usize) {
+    let max_width = cols.saturating_sub(2);
+    let max_height = rows.saturating_sub(2);
+    if max_width == 0 || max_height == 0 || dims.width() <= 0 || dims.height() <= 0 {
+        return (0, 0);
+    }
+
+    let aspect = dims.height() as f64 / dims.width() as f64 / 2.0;
+    let width = max_width.min((max_height as f64 / aspect) as usize).max(1);
+    let height = ((width as f64 * aspect).round() as usize).clamp(1, max_height);
+    (width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::EventType;
+
+    fn dims() -> TouchpadDimensions {
+        TouchpadDimensions {
+            min_x: 0,
+            max_x: 1000,
+            min_y: 0,
+            max_y: 500,
+        }
+    }
+
+    fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+    }
+
+    fn touch(value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), value)
+    }
+
+    #[test]
+    fn tracks_multitouch_slots() {
+        let mut contacts = Contacts::new();
+        contacts.process(&[
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 10),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, 100),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 200),
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 1),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 11),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, 700),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, 300),
+            abs(AbsoluteAxisType::ABS_X, 100),
+        ]);
+        assert_eq!(contacts.iter().count(), 2);
+
+        contacts.process(&[
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+        ]);
+        let remaining: Vec<_> = contacts.iter().collect();
+        assert_eq!(
+            remaining,
+            vec![(
+                1,
+                Contact {
+                    x: Some(700),
+                    y: Some(300)
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn logs_why_touch_was_not_activated() {
+        let zones = ExclusionZones::new(0.0, 20.0, 0.0, 0.0);
+        let mut monitor = Monitor::new("pad", dims(), zones);
+
+        monitor.process(&[
+            abs(AbsoluteAxisType::ABS_X, 500),
+            abs(AbsoluteAxisType::ABS_Y, 475),
+            touch(1),
+        ]);
+        monitor.process(&[touch(0)]);
+        monitor.process(&[
+            abs(AbsoluteAxisType::ABS_X, 500),
+            abs(AbsoluteAxisType::ABS_Y, 100),
+            touch(1),
+        ]);
+
+        let log: Vec<_> = monitor.log.iter().cloned().collect();
+        assert_eq!(
+            log,
+            vec![
+                "#1 touch at (50%, 95%) -> not activated: in bottom zone",
+                "#2 touch at (50%, 20%) -> activated (outside exclusion zones)",
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_zones_and_contacts() {
+        let zones = ExclusionZones::new(0.0, 25.0, 0.0, 0.0);
+        let mut monitor = Monitor::new("pad", dims(), zones);
+        monitor.process(&[
+            abs(AbsoluteAxisType::ABS_X, 10),
+            abs(AbsoluteAxisType::ABS_Y, 10),
+            touch(1),
+        ]);
+
+        // 幅20、高さ5（縦横比 1/2 をさらに半分）
+        let lines = monitor.render(22, 7 + STATUS_LINES + LOG_LEN);
+        assert_eq!(lines[1], format!("┌{}┐", "─".repeat(20)));
+        assert_eq!(lines[2], format!("│0{}│", " ".repeat(19)));
+        assert_eq!(lines[6], format!("│{}│", "░".repeat(20)));
+        assert_eq!(lines[7], format!("└{}┘", "─".repeat(20)));
+        assert!(lines[9].starts_with("Layer: ACTIVE"));
+    }
+
+    #[test]
+    fn pad_size_keeps_aspect_ratio() {
+        assert_eq!(pad_size(&dims(), 82, 100), (80, 20));
+        assert_eq!(pad_size(&dims(), 200, 12), (40, 10));
+        assert_eq!(pad_size(&dims(), 1, 10), (0, 0));
+    }
+}
@@ -0,0 +1,338 @@
+//! kanata TCPサーバー経由のレイヤー切り替え
+
+use super::LayerBackend;
+use crate::config::KanataConfig;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// 接続と書き込みのタイムアウト
+///
+/// デーモンはイベントループ内で書き込むので、待っている間の入力が止まらないよう短くする
+const IO_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// kanataとの接続
+struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// 読みかけの行（ノンブロッキングで読むと途中で止まることがある）
+    partial: String,
+}
+
+impl Connection {
+    fn open(address: &str) -> io::Result<Self> {
+        let addr = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "kanata address did not resolve",
+            )
+        })?;
+        let writer = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+        writer.set_nodelay(true)?;
+        writer.set_write_timeout(Some(IO_TIMEOUT))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self {
+            writer,
+            reader,
+            partial: String::new(),
+        })
+    }
+
+    /// 相手が接続を閉じたか（未読の通知が残っていれば生きている）
+    fn is_closed(&self) -> bool {
+        if !self.reader.buffer().is_empty() {
+            return false;
+        }
+        if self.writer.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let closed = match self.writer.peek(&mut [0u8; 1]) {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+        };
+        closed || self.writer.set_nonblocking(false).is_err()
+    }
+
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())
+    }
+
+    /// 届いている行をブロックせずに読み、JSONとして解釈する
+    fn drain(&mut self) -> io::Result<Vec<Value>> {
+        self.writer.set_nonblocking(true)?;
+        let mut messages = Vec::new();
+        let result = loop {
+            match self.reader.read_line(&mut self.partial) {
+                Ok(0) => break Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => {
+                    let line = std::mem::take(&mut self.partial);
+                    // 解釈できない行は読み飛ばす
+                    if let Ok(message) = serde_json::from_str(&line) {
+                        messages.push(message);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(messages),
+                Err(e) => break Err(e),
+            }
+        };
+        self.writer.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// kanata TCPバックエンド
+///
+/// タッチ開始で設定レイヤーへ切り替え、終了時に直前のレイヤーへ戻す。
+/// 直前のレイヤーは応答を待たずに、kanataが送ってくる通知から追う
+pub struct KanataBackend {
+    address: String,
+    layer: String,
+    default_layer: String,
+    connection: Option<Connection>,
+    /// LayerChange / CurrentLayerName で知った現在のレイヤー
+    current_layer: Option<String>,
+    previous_layer: Option<String>,
+}
+
+impl KanataBackend {
+    pub fn new(config: &KanataConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            layer: config.layer.clone(),
+            default_layer: config.default_layer.clone(),
+            connection: None,
+            current_layer: None,
+            previous_layer: None,
+        }
+    }
+
+    /// kanataに接続できるか確認
+    pub fn check(&mut self) -> io::Result<()> {
+        self.connection().map(|_| ())
+    }
+
+    /// 接続を返す（切れていれば再接続）
+    ///
+    /// 接続したら現在のレイヤー名を問い合わせておく（応答は通知と一緒に読む）
+    fn connection(&mut self) -> io::Result<&mut Connection> {
+        if self.connection.as_ref().is_some_and(Connection::is_closed) {
+            self.connection = None;
+        }
+        if self.connection.is_none() {
+            let mut connection = Connection::open(&self.address)?;
+            connection.send(&json!({ "RequestCurrentLayerName": {} }))?;
+            self.current_layer = None;
+            self.connection = Some(connection);
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// メッセージを送信（失敗時は1度だけ再接続して再送）
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        if self.connection()?.send(message).is_ok() {
+            return Ok(());
+        }
+        self.connection = None;
+        self.connection()?.send(message)
+    }
+
+    /// 届いている通知から現在のレイヤーを更新
+    fn poll_layer(&mut self) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+        match connection.drain() {
+            Ok(messages) => {
+                for message in messages {
+                    let name = message["LayerChange"]["new"]
+                        .as_str()
+                        .or_else(|| message["CurrentLayerName"]["name"].as_str());
+                    if let Some(name) = name {
+                        self.current_layer = Some(name.to_string());
+                    }
+                }
+            }
+            Err(_) => self.connection = None,
+        }
+    }
+
+    fn change_layer(&mut self, layer: &str) -> io::Result<()> {
+        self.send(&json!({ "ChangeLayer": { "new": layer } }))?;
+        self.current_layer = Some(layer.to_string());
+        Ok(())
+    }
+}
+
+impl LayerBackend for KanataBackend {
+    fn activate(&mut self) -> io::Result<()> {
+        // 分からなければ（古いkanataは問い合わせに応答しない） default_layer に戻す
+        self.poll_layer();
+        self.previous_layer = self
+            .current_layer
+            .take()
+            .filter(|layer| *layer != self.layer);
+
+        let layer = self.layer.clone();
+        self.change_layer(&layer)
+    }
+
+    fn deactivate(&mut self) -> io::Result<()> {
+        self.poll_layer();
+        let layer = self
+            .previous_layer
+            .take()
+            .unwrap_or_else(|| self.default_layer.clone());
+        self.change_layer(&layer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::wait_readable;
+    use std::net::TcpListener;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Instant;
+
+    /// 受信した行を記録するkanataの代役
+    ///
+    /// `current` が Some なら RequestCurrentLayerName に応答し、`notify` は接続直後に送る。
+    /// `drop_after` 行受信するごとに接続を切る
+    fn spawn_stand_in(
+        current: Option<&str>,
+        notify: Option<Value>,
+        drop_after: Option<usize>,
+    ) -> (String, mpsc::Receiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let current = current.map(str::to_string);
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                if let Some(notify) = &notify {
+                    writeln!(stream, "{}", notify).unwrap();
+                }
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut count = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    let message: Value = serde_json::from_str(&line).unwrap();
+                    line.clear();
+                    if message.get("RequestCurrentLayerName").is_some() {
+                        if let Some(name) = &current {
+                            let reply = json!({ "CurrentLayerName": { "name": name } });
+                            writeln!(stream, "{}", reply).unwrap();
+                        }
+                    }
+                    tx.send(message).unwrap();
+                    count += 1;
+                    if drop_after == Some(count) {
+                        break;
+                    }
+                }
+            }
+        });
+
+        (address, rx)
+    }
+
+    fn backend(address: String) -> KanataBackend {
+        KanataBackend::new(&KanataConfig {
+            address,
+            layer: "mousenav".to_string(),
+            default_layer: "base".to_string(),
+        })
+    }
+
+    /// 接続して問い合わせが届き、応答が返ってくるまで待つ
+    fn connect(backend: &mut KanataBackend, rx: &mpsc::Receiver<Value>) {
+        backend.check().unwrap();
+        assert_eq!(rx.recv().unwrap(), json!({ "RequestCurrentLayerName": {} }));
+        wait_for_stand_in(backend);
+    }
+
+    /// 代役が送った応答・通知・切断がバックエンド側で読めるようになるまで待つ
+    ///
+    /// 応答と通知は代役が `rx` に送る前に書き込まれるので、受け取った後なら取りこぼさない
+    fn wait_for_stand_in(backend: &KanataBackend) {
+        let fd = backend.connection.as_ref().unwrap().writer.as_raw_fd();
+        let ready = wait_readable(&[fd], Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(ready, vec![true]);
+    }
+
+    #[test]
+    fn restores_previous_layer_on_release() {
+        let (address, rx) = spawn_stand_in(Some("qwerty"), None, None);
+        let mut backend = backend(address);
+        connect(&mut backend, &rx);
+
+        backend.activate().unwrap();
+        backend.deactivate().unwrap();
+
+        assert_eq!(
+            rx.recv().unwrap(),
+            json!({ "ChangeLayer": { "new": "mousenav" } })
+        );
+        assert_eq!(
+            rx.recv().unwrap(),
+            json!({ "ChangeLayer": { "new": "qwerty" } })
+        );
+    }
+
+    #[test]
+    fn follows_layer_change_notifications() {
+        let notify = json!({ "LayerChange": { "new": "gaming" } });
+        let (address, rx) = spawn_stand_in(None, Some(notify), None);
+        let mut backend = backend(address);
+        connect(&mut backend, &rx);
+
+        backend.activate().unwrap();
+        backend.deactivate().unwrap();
+
+        let messages: Vec<Value> = rx.iter().take(2).collect();
+        assert_eq!(messages[1], json!({ "ChangeLayer": { "new": "gaming" } }));
+    }
+
+    #[test]
+    fn falls_back_to_default_layer_without_reply() {
+        let (address, rx) = spawn_stand_in(None, None, None);
+        let mut backend = backend(address);
+
+        // 応答を待たずにすぐ切り替える
+        let started = Instant::now();
+        backend.activate().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+        backend.deactivate().unwrap();
+
+        let messages: Vec<Value> = rx.iter().take(3).collect();
+        assert_eq!(messages[1], json!({ "ChangeLayer": { "new": "mousenav" } }));
+        assert_eq!(messages[2], json!({ "ChangeLayer": { "new": "base" } }));
+    }
+
+    #[test]
+    fn reconnects_after_server_drops_connection() {
+        // 2行（問い合わせ + ChangeLayer）ごとに切断される
+        let (address, rx) = spawn_stand_in(None, None, Some(2));
+        let mut backend = backend(address);
+
+        backend.activate().unwrap();
+        let first: Vec<Value> = rx.iter().take(2).collect();
+        assert_eq!(first[1], json!({ "ChangeLayer": { "new": "mousenav" } }));
+
+        // 切断が届くのを待つ
+        wait_for_stand_in(&backend);
+
+        backend.deactivate().unwrap();
+        let second: Vec<Value> = rx.iter().take(2).collect();
+        assert_eq!(second[0], json!({ "RequestCurrentLayerName": {} }));
+        assert_eq!(second[1], json!({ "ChangeLayer": { "new": "base" } }));
+    }
+}
@@ -0,0 +1,249 @@
+//! keyd制御ソケット経由のレイヤー切り替え
+
+use super::LayerBackend;
+use crate::config::KeydConfig;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// keyd.h の MAX_IPC_MESSAGE_SIZE
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// struct ipc_message { type; timeout; data[MAX_IPC_MESSAGE_SIZE]; sz; } のサイズ
+const MESSAGE_LEN: usize = 4 + 4 + MAX_MESSAGE_SIZE + 8;
+
+/// ソケットの読み書きタイムアウト
+///
+/// デーモンはイベントループ内で書き込むので、待っている間の入力が止まらないよう短くする
+const IO_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// keyd.h の enum ipc_msg_type_e
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MessageType {
+    Success = 0,
+    Fail = 1,
+    Bind = 2,
+    Input = 3,
+    Macro = 4,
+    Reload = 5,
+}
+
+impl MessageType {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Success),
+            1 => Some(Self::Fail),
+            2 => Some(Self::Bind),
+            3 => Some(Self::Input),
+            4 => Some(Self::Macro),
+            5 => Some(Self::Reload),
+            _ => None,
+        }
+    }
+}
+
+/// keydとやりとりする1メッセージ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageType,
+    pub data: String,
+}
+
+impl Message {
+    /// ipc_message構造体のバイト列に変換
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let data = self.data.as_bytes();
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "keyd message too long",
+            ));
+        }
+
+        let mut buf = vec![0u8; MESSAGE_LEN];
+        buf[0..4].copy_from_slice(&(self.kind as u32).to_ne_bytes());
+        buf[8..8 + data.len()].copy_from_slice(data);
+        buf[8 + MAX_MESSAGE_SIZE..].copy_from_slice(&(data.len() as u64).to_ne_bytes());
+        Ok(buf)
+    }
+
+    /// ipc_message構造体のバイト列から復元
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        if buf.len() != MESSAGE_LEN {
+            return Err(invalid("unexpected keyd message length"));
+        }
+
+        let kind = u32::from_ne_bytes(buf[0..4].try_into().unwrap());
+        let kind =
+            MessageType::from_u32(kind).ok_or_else(|| invalid("unknown keyd message type"))?;
+
+        let size = u64::from_ne_bytes(buf[8 + MAX_MESSAGE_SIZE..].try_into().unwrap()) as usize;
+        let data = buf[8..8 + size.min(MAX_MESSAGE_SIZE)].to_vec();
+
+        Ok(Self {
+            kind,
+            data: String::from_utf8_lossy(&data).into_owned(),
+        })
+    }
+}
+
+/// keydのソケットにメッセージを送り、応答を待つ
+pub fn send_message(socket: &Path, message: &Message) -> io::Result<Message> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    stream.write_all(&message.encode()?)?;
+
+    let mut buf = vec![0u8; MESSAGE_LEN];
+    stream.read_exact(&mut buf)?;
+    Message::decode(&buf)
+}
+
+/// keyd IPCバックエンド
+///
+/// タッチ開始/終了でkeydに式を実行させ、レイヤーを直接切り替える
+pub struct KeydBackend {
+    socket: PathBuf,
+    activate: String,
+    deactivate: String,
+}
+
+impl KeydBackend {
+    pub fn new(config: &KeydConfig) -> Self {
+        Self {
+            socket: config.socket.clone(),
+            activate: config.activate_expr(),
+            deactivate: config.deactivate_expr(),
+        }
+    }
+
+    /// keydが応答するか確認
+    pub fn check(&self) -> io::Result<()> {
+        UnixStream::connect(&self.socket).map(|_| ())
+    }
+
+    fn run(&self, expr: &str) -> io::Result<()> {
+        let reply = send_message(
+            &self.socket,
+            &Message {
+                kind: MessageType::Macro,
+                data: expr.to_string(),
+            },
+        )?;
+
+        match reply.kind {
+            MessageType::Success => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "keyd rejected '{}': {}",
+                expr, reply.data
+            ))),
+        }
+    }
+}
+
+impl LayerBackend for KeydBackend {
+    fn activate(&mut self) -> io::Result<()> {
+        self.run(&self.activate)
+    }
+
+    fn deactivate(&mut self) -> io::Result<()> {
+        self.run(&self.deactivate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// 受信したメッセージを記録するkeydの代役
+    fn spawn_stand_in(name: &str, reply: MessageType) -> (PathBuf, Arc<Mutex<Vec<Message>>>) {
+        let path = std::env::temp_dir().join(format!(
+            "way-thumbsense-keyd-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = Arc::clone(&received);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = vec![0u8; MESSAGE_LEN];
+                stream.read_exact(&mut buf).unwrap();
+                log.lock().unwrap().push(Message::decode(&buf).unwrap());
+
+                let answer = Message {
+                    kind: reply,
+                    data: "stand-in".to_string(),
+                };
+                stream.write_all(&answer.encode().unwrap()).unwrap();
+            }
+        });
+
+        (path, received)
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let message = Message {
+            kind: MessageType::Macro,
+            data: "toggle(nav)".to_string(),
+        };
+        let buf = message.encode().unwrap();
+
+        assert_eq!(buf.len(), MESSAGE_LEN);
+        assert_eq!(Message::decode(&buf).unwrap(), message);
+    }
+
+    #[test]
+    fn activate_and_deactivate_send_expressions() {
+        let (socket, received) = spawn_stand_in("ok", MessageType::Success);
+        let mut backend = KeydBackend::new(&KeydConfig {
+            socket: socket.clone(),
+            layer: "nav".to_string(),
+            activate: "toggle({layer})".to_string(),
+            deactivate: "clear({layer})".to_string(),
+        });
+
+        backend.activate().unwrap();
+        backend.deactivate().unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(
+            *received,
+            vec![
+                Message {
+                    kind: MessageType::Macro,
+                    data: "toggle(nav)".to_string(),
+                },
+                Message {
+                    kind: MessageType::Macro,
+                    data: "clear(nav)".to_string(),
+                },
+            ]
+        );
+        std::fs::remove_file(socket).unwrap();
+    }
+
+    #[test]
+    fn failure_reply_is_an_error() {
+        let (socket, _received) = spawn_stand_in("fail", MessageType::Fail);
+        let mut backend = KeydBackend::new(&KeydConfig {
+            socket: socket.clone(),
+            ..KeydConfig::default()
+        });
+
+        assert!(backend.activate().is_err());
+        std::fs::remove_file(socket).unwrap();
+    }
+}
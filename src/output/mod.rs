@@ -1,3 +1,38 @@
+pub mod kanata;
+pub mod keyd;
 pub mod uinput;
 
-pub use uinput::{VirtualDevice, VirtualMouse};
+pub use kanata::KanataBackend;
+pub use keyd::KeydBackend;
+pub use uinput::{
+    button_key, wait_until_ready, Capabilities, DeviceNode, VirtualDevice, VirtualMouse,
+};
+
+use crate::core::OutputAction;
+use evdev::Key;
+use std::io;
+use std::path::Path;
+
+/// タッチ開始/終了をレイヤー切り替えとして伝える出力先
+pub trait LayerBackend {
+    /// タッチ開始（レイヤー有効化）
+    fn activate(&mut self) -> io::Result<()>;
+
+    /// タッチ終了（レイヤー無効化）
+    fn deactivate(&mut self) -> io::Result<()>;
+
+    /// キーボードから読んだキーを変換結果に従って出力する（`[device] keyboard`）
+    ///
+    /// keyd/kanataはキーボードを自分で読むので対応しない
+    fn send_key(&mut self, _action: OutputAction, _key: Key, _value: i32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this backend does not forward keys",
+        ))
+    }
+
+    /// 自分で作成したイベントノードか（キーボードの検出で自分の仮想デバイスを読まないため）
+    fn owns_devnode(&self, _path: &Path) -> bool {
+        false
+    }
+}
@@ -1,73 +1,133 @@
 //! uinput仮想デバイス
 
+use super::LayerBackend;
+use crate::config::{Config, DeviceIdentity};
 use crate::core::{MouseButton, OutputAction};
-use evdev::{uinput::VirtualDeviceBuilder, AttributeSet, InputEvent, Key};
+use evdev::{uinput::VirtualDeviceBuilder, AttributeSet, InputEvent, InputId, Key};
+use std::fs::File;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// 準備完了チェックの間隔
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// 仮想デバイスが出力し得るキーの集合
+///
+/// 設定されたマッピングとトリガーから導出し、それ以外は広告しない。
+/// `[device] keyboard` を読むときはキーボードのキーをすべて転送する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub mouse_buttons: Vec<Key>,
+    pub keyboard_keys: Vec<Key>,
+}
+
+impl Capabilities {
+    /// 設定から必要最小限のキー集合を導出
+    pub fn from_config(config: &Config) -> Self {
+        let mut mouse_buttons: Vec<Key> = config
+            .mappings
+            .iter()
+            .map(|m| button_key(m.button))
+            .collect();
+        mouse_buttons.sort();
+        mouse_buttons.dedup();
+
+        let mut keyboard_keys = vec![config.trigger.key];
+        if config.device.keyboard.is_some() {
+            // BTN_MISC（BTN_0）より前がキーボードのキー
+            keyboard_keys.extend((1..Key::BTN_0.code()).map(Key::new));
+            keyboard_keys.sort();
+            keyboard_keys.dedup();
+        }
+
+        Self {
+            mouse_buttons,
+            keyboard_keys,
+        }
+    }
+}
+
+/// マウスボタンに対応するevdevキー
+pub fn button_key(button: MouseButton) -> Key {
+    match button {
+        MouseButton::Left => Key::BTN_LEFT,
+        MouseButton::Right => Key::BTN_RIGHT,
+        MouseButton::Middle => Key::BTN_MIDDLE,
+    }
+}
+
+/// 作成した仮想デバイスのsysfsパスとイベントノード
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceNode {
+    /// 例: /sys/devices/virtual/input/input42
+    pub syspath: PathBuf,
+    /// 例: /dev/input/event17
+    pub devnode: PathBuf,
+}
 
 /// 仮想マウス + キーボードデバイス
 pub struct VirtualDevice {
     mouse: evdev::uinput::VirtualDevice,
     keyboard: evdev::uinput::VirtualDevice,
+    mouse_node: DeviceNode,
+    keyboard_node: DeviceNode,
+    trigger_key: Key,
 }
 
 impl VirtualDevice {
-    /// 仮想デバイスを作成
-    pub fn new() -> io::Result<Self> {
-        // マウス
-        let mut mouse_keys = AttributeSet::<Key>::new();
-        mouse_keys.insert(Key::BTN_LEFT);
-        mouse_keys.insert(Key::BTN_RIGHT);
-        mouse_keys.insert(Key::BTN_MIDDLE);
-
-        let mouse = VirtualDeviceBuilder::new()?
-            .name("way-thumbsense mouse")
-            .with_keys(&mouse_keys)?
-            .build()?;
-
-        // キーボード（全キー対応）
-        let mut kb_keys = AttributeSet::<Key>::new();
-        for code in 1..=566u16 {
-            kb_keys.insert(Key::new(code));
-        }
+    /// 設定に従って仮想デバイスを作成
+    ///
+    /// イベントノードが作成されて開けるようになるまで待ってから返す
+    pub fn new(config: &Config) -> io::Result<Self> {
+        let caps = Capabilities::from_config(config);
+        let timeout = Duration::from_millis(config.virtual_device.ready_timeout_ms);
 
-        let keyboard = VirtualDeviceBuilder::new()?
-            .name("way-thumbsense keyboard")
-            .with_keys(&kb_keys)?
-            .build()?;
+        let mut mouse = build_device(&config.virtual_device.mouse, &caps.mouse_buttons)?;
+        let mut keyboard = build_device(&config.virtual_device.keyboard, &caps.keyboard_keys)?;
 
-        // デバイスがシステムに認識されるまで少し待つ
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        let deadline = Instant::now() + timeout;
+        let mouse_node = wait_until_ready(&mut mouse, deadline)?;
+        let keyboard_node = wait_until_ready(&mut keyboard, deadline)?;
 
-        Ok(Self { mouse, keyboard })
+        Ok(Self {
+            mouse,
+            keyboard,
+            mouse_node,
+            keyboard_node,
+            trigger_key: config.trigger.key,
+        })
+    }
+
+    /// 仮想マウスのノード
+    pub fn mouse_node(&self) -> &DeviceNode {
+        &self.mouse_node
+    }
+
+    /// 仮想キーボードのノード
+    pub fn keyboard_node(&self) -> &DeviceNode {
+        &self.keyboard_node
     }
 
     /// マウスクリック
     pub fn mouse_click(&mut self, button: MouseButton) -> io::Result<()> {
-        let key = match button {
-            MouseButton::Left => Key::BTN_LEFT,
-            MouseButton::Right => Key::BTN_RIGHT,
-        };
-
         self.mouse.emit(&[
-            InputEvent::new(evdev::EventType::KEY, key.code(), 1),
+            InputEvent::new(evdev::EventType::KEY, button_key(button).code(), 1),
             InputEvent::new(evdev::EventType::SYNCHRONIZATION, 0, 0),
         ])
     }
 
     /// マウスリリース
     pub fn mouse_release(&mut self, button: MouseButton) -> io::Result<()> {
-        let key = match button {
-            MouseButton::Left => Key::BTN_LEFT,
-            MouseButton::Right => Key::BTN_RIGHT,
-        };
-
         self.mouse.emit(&[
-            InputEvent::new(evdev::EventType::KEY, key.code(), 0),
+            InputEvent::new(evdev::EventType::KEY, button_key(button).code(), 0),
             InputEvent::new(evdev::EventType::SYNCHRONIZATION, 0, 0),
         ])
     }
 
     /// キーイベントをそのまま転送
+    ///
+    /// 仮想キーボードが広告していないキーはカーネルに破棄される
     pub fn forward_key(&mut self, key: Key, value: i32) -> io::Result<()> {
         self.keyboard.emit(&[
             InputEvent::new(evdev::EventType::KEY, key.code(), value),
@@ -85,5 +145,151 @@ impl VirtualDevice {
     }
 }
 
+fn build_device(
+    identity: &DeviceIdentity,
+    keys: &[Key],
+) -> io::Result<evdev::uinput::VirtualDevice> {
+    let mut key_set = AttributeSet::<Key>::new();
+    for key in keys {
+        key_set.insert(*key);
+    }
+
+    VirtualDeviceBuilder::new()?
+        .name(&identity.name)
+        .input_id(InputId::new(
+            identity.bus,
+            identity.vendor,
+            identity.product,
+            identity.version,
+        ))
+        .with_keys(&key_set)?
+        .build()
+}
+
+/// 仮想キーボードでトリガーキーを押し続ける
+impl LayerBackend for VirtualDevice {
+    fn activate(&mut self) -> io::Result<()> {
+        self.forward_key(self.trigger_key, 1)
+    }
+
+    fn deactivate(&mut self) -> io::Result<()> {
+        self.forward_key(self.trigger_key, 0)
+    }
+
+    fn send_key(&mut self, action: OutputAction, key: Key, value: i32) -> io::Result<()> {
+        match action {
+            OutputAction::PassThrough(_) => self.forward_key(key, value),
+            action => self.execute(action),
+        }
+    }
+
+    fn owns_devnode(&self, path: &Path) -> bool {
+        self.mouse_node.devnode == path || self.keyboard_node.devnode == path
+    }
+}
+
+/// イベントノードが存在し、開けるようになるまで待つ
+pub fn wait_until_ready(
+    device: &mut evdev::uinput::VirtualDevice,
+    deadline: Instant,
+) -> io::Result<DeviceNode> {
+    let syspath = device.get_syspath()?;
+
+    loop {
+        if let Some(devnode) = find_event_node(&syspath, Path::new("/dev/input")) {
+            if File::open(&devnode).is_ok() {
+                return Ok(DeviceNode { syspath, devnode });
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("event node for {} did not become ready", syspath.display()),
+            ));
+        }
+        std::thread::sleep(READY_POLL_INTERVAL);
+    }
+}
+
+/// sysfsの入力デバイスディレクトリから対応するイベントノードのパスを求める
+///
+/// udevがノードを作成する前は None
+fn find_event_node(syspath: &Path, dev_dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(syspath)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .find(|name| name.to_string_lossy().starts_with("event"))
+        .map(|name| dev_dir.join(name))
+        .filter(|devnode| devnode.exists())
+}
+
 // 後方互換性のためのエイリアス
 pub type VirtualMouse = VirtualDevice;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_capabilities_are_minimal() {
+        let caps = Capabilities::from_config(&Config::default());
+
+        assert_eq!(caps.mouse_buttons, vec![Key::BTN_LEFT, Key::BTN_RIGHT]);
+        assert_eq!(caps.keyboard_keys, vec![Key::KEY_F24]);
+    }
+
+    #[test]
+    fn capabilities_follow_mappings_and_trigger() {
+        let config = Config::parse(
+            r#"
+            [trigger]
+            key = "KEY_F13"
+
+            [[mappings]]
+            key = "KEY_J"
+            button = "middle"
+
+            [[mappings]]
+            key = "KEY_K"
+            button = "middle"
+            "#,
+        )
+        .unwrap();
+        let caps = Capabilities::from_config(&config);
+
+        assert_eq!(caps.mouse_buttons, vec![Key::BTN_MIDDLE]);
+        assert_eq!(caps.keyboard_keys, vec![Key::KEY_F13]);
+    }
+
+    #[test]
+    fn reading_keyboard_forwards_all_keys() {
+        let config = Config::parse("[device]\nkeyboard = \"AT Translated\"").unwrap();
+        let caps = Capabilities::from_config(&config);
+
+        assert!(caps.keyboard_keys.contains(&Key::KEY_J));
+        assert!(caps.keyboard_keys.contains(&Key::KEY_F24));
+        assert!(!caps.keyboard_keys.contains(&Key::BTN_LEFT));
+    }
+
+    #[test]
+    fn find_event_node_waits_for_devnode() {
+        let root = std::env::temp_dir().join(format!("way-thumbsense-node-{}", std::process::id()));
+        let syspath = root.join("sys/input42");
+        let dev_dir = root.join("dev");
+        std::fs::create_dir_all(syspath.join("event17")).unwrap();
+        std::fs::create_dir_all(&dev_dir).unwrap();
+
+        // sysfsにはあるが /dev にまだない
+        assert_eq!(find_event_node(&syspath, &dev_dir), None);
+
+        std::fs::write(dev_dir.join("event17"), b"").unwrap();
+        assert_eq!(
+            find_event_node(&syspath, &dev_dir),
+            Some(dev_dir.join("event17"))
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
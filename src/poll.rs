@@ -0,0 +1,63 @@
+//! 複数fdの読み込み待ち（poll(2)）
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// いずれかのfdが読み込み可能になるまで待つ
+///
+/// 戻り値は各fdが読み込み可能（またはエラー/切断）かどうか。
+/// シグナルで中断された場合とタイムアウト時はすべて false
+pub fn wait_readable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<Vec<bool>> {
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+
+    let ret = unsafe {
+        libc::poll(
+            pollfds.as_mut_ptr(),
+            pollfds.len() as libc::nfds_t,
+            timeout_ms,
+        )
+    };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok(vec![false; fds.len()]);
+        }
+        return Err(err);
+    }
+
+    Ok(pollfds.iter().map(|p| p.revents != 0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn reports_only_readable_fds() {
+        let (mut a, b) = UnixStream::pair().unwrap();
+        let (_c, d) = UnixStream::pair().unwrap();
+
+        let ready = wait_readable(&[b.as_raw_fd(), d.as_raw_fd()], Some(Duration::ZERO)).unwrap();
+        assert_eq!(ready, vec![false, false]);
+
+        a.write_all(b"x").unwrap();
+        let ready = wait_readable(
+            &[b.as_raw_fd(), d.as_raw_fd()],
+            Some(Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert_eq!(ready, vec![true, false]);
+    }
+}
@@ -0,0 +1,362 @@
+//! タッチパッド・キーボードのイベント列の記録と再生
+//!
+//! 形式はJSON Lines。1行目にデバイス情報のヘッダ、以降は1行1イベント:
+//!
+//! ```text
+//! {"version":1,"devices":[{"source":"touchpad","name":"...","dimensions":{...}}]}
+//! {"t":0,"src":"touchpad","type":3,"code":0,"value":512}
+//! ```
+//!
+//! `t` は最初のイベントからの経過時間（マイクロ秒）
+
+use crate::core::{KeyCode, KeyEvent, OutputAction};
+use crate::engine::{Engine, TouchEvent};
+use crate::input::{Frame, FrameSplitter, TouchpadDimensions};
+use evdev::{EventType, InputEvent};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+use std::time::SystemTime;
+
+/// 記録形式のバージョン
+pub const RECORDING_VERSION: u32 = 1;
+
+/// イベントの取得元
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Touchpad,
+    Keyboard,
+}
+
+/// 記録したデバイスの情報
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub source: Source,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<TouchpadDimensions>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    devices: Vec<DeviceInfo>,
+}
+
+/// 記録された1イベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    #[serde(rename = "t")]
+    pub time_us: u64,
+    #[serde(rename = "src")]
+    pub source: Source,
+    #[serde(rename = "type")]
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RecordedEvent {
+    pub fn to_input_event(&self) -> InputEvent {
+        InputEvent::new(EventType(self.event_type), self.code, self.value)
+    }
+}
+
+/// 読み込んだ記録
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub devices: Vec<DeviceInfo>,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    /// 記録を読み込む
+    pub fn read(reader: impl BufRead) -> Result<Self, RecordingError> {
+        let mut lines = reader.lines().enumerate();
+
+        let header: Header = match lines.next() {
+            Some((_, line)) => parse_line(1, &line?)?,
+            None => return Err(RecordingError::Parse(1, "empty recording".to_string())),
+        };
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::Parse(
+                1,
+                format!("unsupported version {}", header.version),
+            ));
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(parse_line(index + 1, &line)?);
+        }
+
+        Ok(Self {
+            devices: header.devices,
+            events,
+        })
+    }
+
+    /// 指定した取得元のデバイス情報
+    pub fn device(&self, source: Source) -> Option<&DeviceInfo> {
+        self.devices.iter().find(|d| d.source == source)
+    }
+}
+
+fn parse_line<T: serde::de::DeserializeOwned>(
+    line_no: usize,
+    line: &str,
+) -> Result<T, RecordingError> {
+    serde_json::from_str(line).map_err(|e| RecordingError::Parse(line_no, e.to_string()))
+}
+
+/// イベントを記録ファイルへ書き出す
+pub struct RecordingWriter<W: Write> {
+    writer: W,
+    start: Option<SystemTime>,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    /// ヘッダを書き込んで記録を開始
+    pub fn new(mut writer: W, devices: Vec<DeviceInfo>) -> io::Result<Self> {
+        let header = Header {
+            version: RECORDING_VERSION,
+            devices,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        Ok(Self {
+            writer,
+            start: None,
+        })
+    }
+
+    /// イベントを1行書き込む（時刻は最初のイベントからの経過時間）
+    pub fn write(&mut self, source: Source, event: &InputEvent) -> io::Result<()> {
+        let timestamp = event.timestamp();
+        let start = *self.start.get_or_insert(timestamp);
+        let recorded = RecordedEvent {
+            time_us: timestamp
+                .duration_since(start)
+                .unwrap_or_default()
+                .as_micros() as u64,
+            source,
+            event_type: event.event_type().0,
+            code: event.code(),
+            value: event.value(),
+        };
+        serde_json::to_writer(&mut self.writer, &recorded)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// 再生中にエンジンが出した出力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayAction {
+    /// タッチパッドによるレイヤーの状態変化
+    Touch(TouchEvent),
+    /// キーボードのキーに対する変換結果
+    Key(OutputAction),
+}
+
+/// 出力とその時刻
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayOutput {
+    pub time_us: u64,
+    pub action: ReplayAction,
+}
+
+/// 記録をエンジンに流し、出力を時刻順に返す
+///
+/// タッチパッドのイベントはデバイスから読んだときと同じくSYN_REPORTまでを
+/// まとめて処理する。SYN_DROPPEDで取りこぼした区間は捨てる。キーリピート（value=2）は無視する
+pub fn replay(recording: &Recording, engine: &mut Engine) -> Vec<ReplayOutput> {
+    let mut outputs = Vec::new();
+    let mut splitter = FrameSplitter::new();
+
+    for event in &recording.events {
+        match event.source {
+            Source::Touchpad => {
+                if let Some(Frame::Events(frame)) = splitter.push(event.to_input_event()) {
+                    outputs.extend(engine.process_frame(&frame).into_iter().map(|e| {
+                        ReplayOutput {
+                            time_us: event.time_us,
+                            action: ReplayAction::Touch(e),
+                        }
+                    }));
+                }
+            }
+            Source::Keyboard => {
+                if event.event_type != EventType::KEY.0 {
+                    continue;
+                }
+                let key = KeyCode(event.code);
+                let key_event = match event.value {
+                    1 => KeyEvent::Press(key),
+                    0 => KeyEvent::Release(key),
+                    _ => continue,
+                };
+                outputs.push(ReplayOutput {
+                    time_us: event.time_us,
+                    action: ReplayAction::Key(engine.map_key(key_event)),
+                });
+            }
+        }
+    }
+
+    outputs
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Parse(usize, String),
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(e: io::Error) -> Self {
+        RecordingError::Io(e)
+    }
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::Io(e) => write!(f, "Cannot read recording: {}", e),
+            RecordingError::Parse(line, msg) => {
+                write!(f, "Invalid recording at line {}: {}", line, msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MouseButton;
+    use crate::tracker::{ExclusionZones, TouchTracker};
+
+    /// 下端で触れたあと中央で触れてJを押す記録
+    const RECORDING: &str = r#"{"version":1,"devices":[{"source":"touchpad","name":"Test Pad","dimensions":{"min_x":0,"max_x":1000,"min_y":0,"max_y":1000}},{"source":"keyboard","name":"Test Keyboard"}]}
+{"t":0,"src":"touchpad","type":3,"code":0,"value":500}
+{"t":0,"src":"touchpad","type":3,"code":1,"value":950}
+{"t":0,"src":"touchpad","type":1,"code":330,"value":1}
+{"t":0,"src":"touchpad","type":0,"code":0,"value":0}
+{"t":8000,"src":"touchpad","type":1,"code":330,"value":0}
+{"t":8000,"src":"touchpad","type":0,"code":0,"value":0}
+{"t":500000,"src":"touchpad","type":3,"code":1,"value":500}
+{"t":500000,"src":"touchpad","type":1,"code":330,"value":1}
+{"t":500000,"src":"touchpad","type":0,"code":0,"value":0}
+{"t":600000,"src":"keyboard","type":1,"code":36,"value":1}
+{"t":620000,"src":"keyboard","type":1,"code":36,"value":2}
+{"t":650000,"src":"keyboard","type":1,"code":36,"value":0}
+{"t":700000,"src":"touchpad","type":1,"code":330,"value":0}
+{"t":700000,"src":"touchpad","type":0,"code":0,"value":0}
+"#;
+
+    fn engine(recording: &Recording) -> Engine {
+        let dims = recording
+            .device(Source::Touchpad)
+            .unwrap()
+            .dimensions
+            .unwrap();
+        Engine::new(TouchTracker::new(
+            dims,
+            ExclusionZones::new(0.0, 10.0, 0.0, 0.0),
+        ))
+    }
+
+    #[test]
+    fn replay_recorded_session() {
+        let recording = Recording::read(RECORDING.as_bytes()).unwrap();
+        assert_eq!(recording.devices[1].name, "Test Keyboard");
+
+        let mut engine = engine(&recording);
+        let outputs: Vec<_> = replay(&recording, &mut engine)
+            .into_iter()
+            .map(|o| (o.time_us, o.action))
+            .collect();
+
+        assert_eq!(
+            outputs,
+            vec![
+                (
+                    0,
+                    ReplayAction::Touch(TouchEvent::Excluded {
+                        x: 500,
+                        y: 950,
+                        edge: crate::tracker::Edge::Bottom
+                    })
+                ),
+                (
+                    500000,
+                    ReplayAction::Touch(TouchEvent::Activated {
+                        x: None,
+                        y: Some(500)
+                    })
+                ),
+                (
+                    600000,
+                    ReplayAction::Key(OutputAction::MouseClick(MouseButton::Left))
+                ),
+                (
+                    650000,
+                    ReplayAction::Key(OutputAction::MouseRelease(MouseButton::Left))
+                ),
+                (700000, ReplayAction::Touch(TouchEvent::Deactivated)),
+            ]
+        );
+        // 最後にレイヤーが残っていない
+        assert!(!engine.is_active());
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let devices = vec![DeviceInfo {
+            source: Source::Touchpad,
+            name: "Pad".to_string(),
+            dimensions: None,
+        }];
+        let mut buf = Vec::new();
+        let mut writer = RecordingWriter::new(&mut buf, devices.clone()).unwrap();
+        writer
+            .write(
+                Source::Touchpad,
+                &InputEvent::new(EventType::ABSOLUTE, 0, 42),
+            )
+            .unwrap();
+        writer.flush().unwrap();
+
+        let recording = Recording::read(buf.as_slice()).unwrap();
+        assert_eq!(recording.devices, devices);
+        assert_eq!(
+            recording.events,
+            vec![RecordedEvent {
+                time_us: 0,
+                source: Source::Touchpad,
+                event_type: EventType::ABSOLUTE.0,
+                code: 0,
+                value: 42
+            }]
+        );
+    }
+
+    #[test]
+    fn reject_bad_lines() {
+        let text = "{\"version\":1,\"devices\":[]}\n{\"t\":0}\n";
+        let err = Recording::read(text.as_bytes()).unwrap_err();
+        assert!(matches!(err, RecordingError::Parse(2, _)));
+
+        let err = Recording::read("{\"version\":9,\"devices\":[]}".as_bytes()).unwrap_err();
+        assert!(matches!(err, RecordingError::Parse(1, _)));
+    }
+}
@@ -0,0 +1,112 @@
+//! シグナルをself-pipe経由でイベントループに届ける
+//!
+//! ハンドラはパイプに1バイト書くだけで、処理はイベントループ側で行う。
+//! イベントループは読み取り側を `AsyncFd` で待ち、他の待機と同列に扱う。
+//! SA_RESTART を付けるため、ブロッキングなシステムコールが EINTR で失敗することもない
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub use libc::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGWINCH};
+
+/// ハンドラから書き込むパイプのfd
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_signal(signo: libc::c_int) {
+    // 割り込まれた側の errno を write で上書きしないよう退避する
+    let saved = unsafe { *libc::__errno_location() };
+    let fd = WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let byte = signo as u8;
+        // async-signal-safe な write のみ。パイプが満杯なら捨てる
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+    unsafe { *libc::__errno_location() = saved };
+}
+
+/// シグナル受信用のパイプ
+pub struct SignalPipe {
+    read_fd: RawFd,
+}
+
+impl SignalPipe {
+    /// 指定シグナルのハンドラを登録
+    pub fn install(signals: &[libc::c_int]) -> io::Result<Self> {
+        let mut fds = [0 as RawFd; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let old = WRITE_FD.swap(fds[1], Ordering::SeqCst);
+        if old >= 0 {
+            unsafe { libc::close(old) };
+        }
+
+        for &signo in signals {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction =
+                    handle_signal as extern "C" fn(libc::c_int) as *const () as usize;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(signo, &action, std::ptr::null_mut()) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(Self { read_fd: fds[0] })
+    }
+
+    /// イベントループが待つ読み取り側のfd
+    pub fn fd(&self) -> RawFd {
+        self.read_fd
+    }
+
+    /// 届いたシグナルを到着順に取り出す
+    pub fn drain(&self) -> Vec<libc::c_int> {
+        let mut signals = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.read_fd,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            signals.extend(buf[..n as usize].iter().map(|&b| b as libc::c_int));
+        }
+        signals
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.read_fd) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::wait_readable;
+    use std::time::Duration;
+
+    #[test]
+    fn raised_signal_wakes_poll() {
+        let pipe = SignalPipe::install(&[SIGUSR1]).unwrap();
+        assert!(pipe.drain().is_empty());
+
+        unsafe { libc::raise(SIGUSR1) };
+
+        let ready = wait_readable(&[pipe.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(ready, vec![true]);
+        assert_eq!(pipe.drain(), vec![SIGUSR1]);
+    }
+}
@@ -0,0 +1,220 @@
+//! keyd / kanata の設定断片の生成（gen-config 用）
+//!
+//! トリガーキーとクリックのキーは、デーモンと同じ `Config::mappings()` から作る
+
+use crate::config::{BackendKind, Config};
+use crate::core::MouseButton;
+use evdev::Key;
+use std::fmt::Write;
+
+/// 生成する設定の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Target {
+    /// keydの設定ファイル（/etc/keyd/*.conf）
+    Keyd,
+    /// kanataの設定ファイル（includeで読み込む）
+    Kanata,
+}
+
+/// 設定に合わせた断片を生成
+pub fn generate(config: &Config, target: Target) -> String {
+    match target {
+        Target::Keyd => keyd(config),
+        Target::Kanata => kanata(config),
+    }
+}
+
+/// keydの設定で使うキー名（KEY_F24 → f24）
+pub fn keyd_key_name(key: Key) -> String {
+    match key {
+        Key::KEY_LEFTCTRL => "leftcontrol".to_string(),
+        Key::KEY_RIGHTCTRL => "rightcontrol".to_string(),
+        _ => kernel_name(key),
+    }
+}
+
+/// kanataの設定で使うキー名
+pub fn kanata_key_name(key: Key) -> String {
+    let name = match key {
+        Key::KEY_SEMICOLON => ";",
+        Key::KEY_COMMA => ",",
+        Key::KEY_DOT => ".",
+        Key::KEY_SLASH => "/",
+        Key::KEY_SPACE => "spc",
+        Key::KEY_LEFTCTRL => "lctl",
+        Key::KEY_RIGHTCTRL => "rctl",
+        Key::KEY_LEFTSHIFT => "lsft",
+        Key::KEY_RIGHTSHIFT => "rsft",
+        Key::KEY_LEFTALT => "lalt",
+        Key::KEY_RIGHTALT => "ralt",
+        Key::KEY_LEFTMETA => "lmet",
+        Key::KEY_RIGHTMETA => "rmet",
+        _ => return kernel_name(key),
+    };
+    name.to_string()
+}
+
+/// input-event-codes.h の名前から KEY_ を除いて小文字にしたもの
+fn kernel_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("KEY_").unwrap_or(&name).to_lowercase()
+}
+
+fn keyd_button(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "leftmouse",
+        MouseButton::Right => "rightmouse",
+        MouseButton::Middle => "middlemouse",
+    }
+}
+
+fn kanata_button(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "mlft",
+        MouseButton::Right => "mrgt",
+        MouseButton::Middle => "mmid",
+    }
+}
+
+fn keyd(config: &Config) -> String {
+    let layer = &config.backend.keyd.layer;
+    let keyboard = &config.virtual_device.keyboard;
+    let mut out = String::new();
+
+    out.push_str(
+        "# Generated by way-thumbsense gen-config --target keyd\n\
+         # Save as /etc/keyd/way-thumbsense.conf (or merge into an existing\n\
+         # config with the same [ids]) and run: sudo keyd reload\n\n",
+    );
+
+    writeln!(out, "[ids]").unwrap();
+    writeln!(out, "*").unwrap();
+    if config.backend.kind == BackendKind::Uinput {
+        // 仮想キーボードはキーボードに見えないことがあるので明示する
+        writeln!(out, "{:04x}:{:04x}", keyboard.vendor, keyboard.product).unwrap();
+    }
+    writeln!(out).unwrap();
+
+    if config.backend.kind == BackendKind::Uinput {
+        writeln!(out, "[main]").unwrap();
+        writeln!(
+            out,
+            "{} = layer({})",
+            keyd_key_name(config.trigger.key),
+            layer
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+    }
+
+    writeln!(out, "[{}]", layer).unwrap();
+    for mapping in config.mappings() {
+        writeln!(
+            out,
+            "{} = {}",
+            keyd_key_name(Key::new(mapping.key.0)),
+            keyd_button(mapping.button)
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn kanata(config: &Config) -> String {
+    let layer = &config.backend.kanata.layer;
+    let mut out = String::new();
+
+    out.push_str(
+        ";; Generated by way-thumbsense gen-config --target kanata\n\
+         ;; Include from your kanata config: (include way-thumbsense.kbd)\n",
+    );
+    if config.backend.kind != BackendKind::Kanata {
+        // kanataバックエンド以外はトリガーキーでレイヤーに入る
+        let trigger = kanata_key_name(config.trigger.key);
+        writeln!(
+            out,
+            ";; Add {} to defsrc and put @thumbsense at its position in your base layer.",
+            trigger
+        )
+        .unwrap();
+        writeln!(
+            out,
+            ";; kanata must also read \"{}\" (see linux-dev-names-include).",
+            config.virtual_device.keyboard.name
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "(defalias thumbsense (layer-while-held {}))", layer).unwrap();
+    }
+    writeln!(out).unwrap();
+
+    writeln!(out, "(deflayermap ({})", layer).unwrap();
+    for mapping in config.mappings() {
+        writeln!(
+            out,
+            "  {} {}",
+            kanata_key_name(Key::new(mapping.key.0)),
+            kanata_button(mapping.button)
+        )
+        .unwrap();
+    }
+    writeln!(out, ")").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doctor::inspect_keyd_config;
+
+    #[test]
+    fn keyd_snippet_passes_doctor_check() {
+        let config = Config::default();
+        let snippet = generate(&config, Target::Keyd);
+        assert!(snippet.contains("\n[main]\nf24 = layer(mousenav)\n"));
+        assert!(snippet.contains("\n[mousenav]\nj = leftmouse\nk = rightmouse\n"));
+
+        // doctor が同じ断片を正しい設定と判定する
+        let keyboard = &config.virtual_device.keyboard;
+        let report = inspect_keyd_config(&snippet, "f24", keyboard.vendor, keyboard.product);
+        assert!(report.is_ok(), "{:?}", report);
+    }
+
+    #[test]
+    fn snippets_follow_mappings() {
+        let config = Config::parse(
+            r#"
+            [trigger]
+            key = "KEY_F23"
+
+            [[mappings]]
+            key = "KEY_SEMICOLON"
+            button = "middle"
+
+            [backend]
+            kind = "kanata"
+
+            [backend.kanata]
+            layer = "mouse"
+            "#,
+        )
+        .unwrap();
+
+        let snippet = generate(&config, Target::Kanata);
+        assert!(snippet.contains("(deflayermap (mouse)\n  ; mmid\n)"));
+        // kanataバックエンドはTCPでレイヤーを切り替えるのでトリガーは不要
+        assert!(!snippet.contains("layer-while-held"));
+
+        let snippet = generate(&config, Target::Keyd);
+        assert!(snippet.contains("[mousenav]\nsemicolon = middlemouse\n"));
+        assert!(!snippet.contains("[main]"));
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(keyd_key_name(Key::KEY_F24), "f24");
+        assert_eq!(keyd_key_name(Key::KEY_LEFTCTRL), "leftcontrol");
+        assert_eq!(kanata_key_name(Key::KEY_J), "j");
+        assert_eq!(kanata_key_name(Key::KEY_LEFTCTRL), "lctl");
+    }
+}
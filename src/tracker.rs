@@ -1,9 +1,11 @@
 //! タッチ位置の追跡と除外領域の判定
 
 use crate::input::TouchpadDimensions;
+use serde::{Deserialize, Serialize};
 
 /// 除外領域の設定(パーセンテージ)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ExclusionZones {
     pub top: f32,    // 上端の除外割合 (0.0 - 100.0)
     pub bottom: f32, // 下端の除外割合 (0.0 - 100.0)
@@ -25,6 +27,49 @@ impl ExclusionZones {
     pub fn none() -> Self {
         Self::new(0.0, 0.0, 0.0, 0.0)
     }
+
+    /// いずれかの辺に除外領域があるか
+    pub fn is_enabled(&self) -> bool {
+        self.top > 0.0 || self.bottom > 0.0 || self.left > 0.0 || self.right > 0.0
+    }
+
+    /// 割合が 0-100 の範囲内で、対辺と合わせて100未満かチェック
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("top", self.top),
+            ("bottom", self.bottom),
+            ("left", self.left),
+            ("right", self.right),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(format!("{} exclusion must be 0-100%: {}", name, value));
+            }
+        }
+        if self.top + self.bottom >= 100.0 || self.left + self.right >= 100.0 {
+            return Err("exclusion zones cover the whole touchpad".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// タッチ位置が属する除外領域の辺
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+            Edge::Left => "left",
+            Edge::Right => "right",
+        }
+    }
 }
 
 /// タッチ追跡と除外領域判定
@@ -62,27 +107,62 @@ impl TouchTracker {
         self.current_y = None;
     }
 
+    /// 除外領域の設定
+    pub fn exclusion_zones(&self) -> &ExclusionZones {
+        &self.exclusion_zones
+    }
+
+    /// 除外領域を差し替え
+    pub fn set_exclusion_zones(&mut self, zones: ExclusionZones) {
+        self.exclusion_zones = zones;
+    }
+
+    /// 現在の座標
+    pub fn position(&self) -> (Option<i32>, Option<i32>) {
+        (self.current_x, self.current_y)
+    }
+
+    /// タッチパッドの寸法
+    pub fn dimensions(&self) -> &TouchpadDimensions {
+        &self.dimensions
+    }
+
     /// 現在のタッチ位置が除外領域にあるかチェック
     pub fn is_in_exclusion_zone(&self) -> bool {
-        let Some(x) = self.current_x else {
-            return false;
-        };
-        let Some(y) = self.current_y else {
-            return false;
-        };
+        self.excluded_edge().is_some()
+    }
+
+    /// 現在のタッチ位置が属する除外領域の辺（除外されていなければ None）
+    pub fn excluded_edge(&self) -> Option<Edge> {
+        let x = self.current_x?;
+        let y = self.current_y?;
 
         // min_x/min_y から max_x/max_y の範囲を使用
         let width = self.dimensions.width() as f32;
         let height = self.dimensions.height() as f32;
 
         // 各端からの閾値を計算（min_x/min_yを基準に）
-        let left_threshold = self.dimensions.min_x + (width * self.exclusion_zones.left / 100.0) as i32;
-        let right_threshold = self.dimensions.min_x + (width * (100.0 - self.exclusion_zones.right) / 100.0) as i32;
-        let top_threshold = self.dimensions.min_y + (height * self.exclusion_zones.top / 100.0) as i32;
-        let bottom_threshold = self.dimensions.min_y + (height * (100.0 - self.exclusion_zones.bottom) / 100.0) as i32;
+        let left_threshold =
+            self.dimensions.min_x + (width * self.exclusion_zones.left / 100.0) as i32;
+        let right_threshold =
+            self.dimensions.min_x + (width * (100.0 - self.exclusion_zones.right) / 100.0) as i32;
+        let top_threshold =
+            self.dimensions.min_y + (height * self.exclusion_zones.top / 100.0) as i32;
+        let bottom_threshold =
+            self.dimensions.min_y + (height * (100.0 - self.exclusion_zones.bottom) / 100.0) as i32;
 
         // 除外領域内かどうか判定
-        x < left_threshold || x > right_threshold || y < top_threshold || y > bottom_threshold
+        if x < left_threshold {
+            Some(Edge::Left)
+        } else if x > right_threshold {
+            Some(Edge::Right)
+        } else if y < top_threshold {
+            Some(Edge::Top)
+        } else if y > bottom_threshold {
+            Some(Edge::Bottom)
+        } else {
+            None
+        }
     }
 
     /// デバッグ用: 現在の座標と除外判定を表示
@@ -162,6 +242,20 @@ mod tests {
         tracker.update_x(500);
         tracker.update_y(950);
         assert!(tracker.is_in_exclusion_zone());
+        assert_eq!(tracker.excluded_edge(), Some(Edge::Bottom));
+
+        tracker.update_x(50);
+        tracker.update_y(500);
+        assert_eq!(tracker.excluded_edge(), Some(Edge::Left));
+    }
+
+    #[test]
+    fn test_validate_zones() {
+        assert!(ExclusionZones::new(10.0, 20.0, 0.0, 0.0).validate().is_ok());
+        assert!(ExclusionZones::new(-1.0, 0.0, 0.0, 0.0).validate().is_err());
+        assert!(ExclusionZones::new(0.0, 0.0, 60.0, 40.0)
+            .validate()
+            .is_err());
     }
 
     #[test]
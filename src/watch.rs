@@ -0,0 +1,229 @@
+//! 設定ファイルと `/dev/input` の変更監視（inotify）
+//!
+//! 設定ファイルはエディタの「一時ファイルに書いてrename」にも対応するため、
+//! ファイルではなく親ディレクトリを監視してファイル名で絞り込む。
+//! シンボリックリンクならリンク先のディレクトリも監視する。
+//! 作成・切り詰めの途中で読まないよう、書き終わり（close）とrenameだけを見る。
+//! `/dev/input` はデバイスの接続（イベントノードの作成と権限の変更）を見る
+
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+/// struct inotify_event の固定部分（wd, mask, cookie, len）
+const EVENT_HEADER_LEN: usize = 16;
+
+/// ディレクトリのエントリの変化を監視する
+pub struct DirWatcher {
+    fd: RawFd,
+}
+
+impl DirWatcher {
+    /// `mask` は `libc::IN_CREATE` などの組み合わせ
+    pub fn new(dir: &Path, mask: u32) -> io::Result<Self> {
+        let watcher = Self::init()?;
+        watcher.add(dir, mask)?;
+        Ok(watcher)
+    }
+
+    fn init() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// 別のディレクトリも同じfdで監視する（戻り値はwatch descriptor）
+    fn add(&self, dir: &Path, mask: u32) -> io::Result<i32> {
+        let dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid path to watch"))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd, dir.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    /// poll用のfd
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// 溜まったイベントを読み、変化したエントリの名前を返す（重複あり）
+    pub fn drain(&self) -> Vec<OsString> {
+        self.read_events()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect()
+    }
+
+    /// 溜まったイベントを読み、watch descriptorとエントリの名前を返す
+    fn read_events(&self) -> Vec<(i32, OsString)> {
+        let mut names = Vec::new();
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let n =
+                unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            let n = n as usize;
+
+            let mut offset = 0;
+            while offset + EVENT_HEADER_LEN <= n {
+                let wd = i32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap());
+                let len =
+                    u32::from_ne_bytes(buf[offset + 12..offset + 16].try_into().unwrap()) as usize;
+                let name =
+                    &buf[offset + EVENT_HEADER_LEN..(offset + EVENT_HEADER_LEN + len).min(n)];
+                // 名前はNULでパディングされている
+                let name = name.split(|&b| b == 0).next().unwrap_or(&[]);
+                names.push((wd, OsString::from_vec(name.to_vec())));
+                offset += EVENT_HEADER_LEN + len;
+            }
+        }
+
+        names
+    }
+}
+
+impl Drop for DirWatcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// 1ファイルの変更を監視する
+pub struct FileWatcher {
+    dir: DirWatcher,
+    /// 監視するディレクトリのwatch descriptorとファイル名（リンクとリンク先）
+    targets: Vec<(i32, OsString)>,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let dir = DirWatcher::init()?;
+        // リンク先が編集されてもリンクの置き換えでも気づけるよう、両方を監視する
+        let canonical = path.canonicalize().ok();
+        let paths = std::iter::once(path).chain(canonical.as_deref().filter(|c| *c != path));
+        let mut targets = Vec::new();
+        for path in paths {
+            let name = path
+                .file_name()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "invalid path to watch")
+                })?
+                .to_os_string();
+            let parent = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let wd = dir.add(parent, libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO)?;
+            targets.push((wd, name));
+        }
+        Ok(Self { dir, targets })
+    }
+
+    /// poll用のfd
+    pub fn fd(&self) -> RawFd {
+        self.dir.fd()
+    }
+
+    /// 溜まったイベントを読み、監視対象のファイルが変更されていれば true
+    pub fn changed(&self) -> bool {
+        self.dir
+            .read_events()
+            .iter()
+            .any(|event| self.targets.contains(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::wait_readable;
+    use std::time::Duration;
+
+    #[test]
+    fn detects_write_and_rename_of_watched_file() {
+        let dir = std::env::temp_dir().join(format!("way-thumbsense-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let watcher = FileWatcher::new(&path).unwrap();
+
+        // 関係ないファイル
+        std::fs::write(dir.join("other.toml"), "x").unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(!watcher.changed());
+
+        // 直接書き込み
+        std::fs::write(&path, "a").unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(watcher.changed());
+
+        // 削除は変更として扱わない
+        std::fs::remove_file(&path).unwrap();
+        let ready = wait_readable(&[watcher.fd()], Some(Duration::from_millis(100))).unwrap();
+        assert!(!ready[0] && !watcher.changed());
+
+        // 一時ファイルからのrename
+        std::fs::write(dir.join(".config.toml.swp"), "b").unwrap();
+        std::fs::rename(dir.join(".config.toml.swp"), &path).unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_edits_through_a_symlink() {
+        let dir =
+            std::env::temp_dir().join(format!("way-thumbsense-watch-link-{}", std::process::id()));
+        let real = dir.join("dotfiles");
+        let config = dir.join("config");
+        std::fs::create_dir_all(&real).unwrap();
+        std::fs::create_dir_all(&config).unwrap();
+        let target = real.join("way-thumbsense.toml");
+        std::fs::write(&target, "a").unwrap();
+        let link = config.join("config.toml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let watcher = FileWatcher::new(&link).unwrap();
+
+        // リンクを通した書き込みはリンク先のディレクトリで起きる
+        std::fs::write(&link, "b").unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(watcher.changed());
+
+        // リンク先をエディタのようにrenameで置き換える
+        std::fs::write(real.join(".swp"), "c").unwrap();
+        std::fs::rename(real.join(".swp"), &target).unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(watcher.changed());
+
+        // リンク先と同じ名前でも別のディレクトリなら無関係
+        std::fs::write(config.join("way-thumbsense.toml"), "d").unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert!(!watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_watcher_reports_created_entries() {
+        let dir =
+            std::env::temp_dir().join(format!("way-thumbsense-dirwatch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let watcher = DirWatcher::new(&dir, libc::IN_CREATE).unwrap();
+        assert!(watcher.drain().is_empty());
+
+        std::fs::write(dir.join("event7"), "").unwrap();
+        wait_readable(&[watcher.fd()], Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(watcher.drain(), vec![OsString::from("event7")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
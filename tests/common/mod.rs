@@ -0,0 +1,167 @@
+//! uinputで仮想の入力デバイスを作る結合テスト用ハーネス
+//!
+//! `/dev/uinput` に書き込めないとデバイスを作るところで失敗する
+
+#![allow(dead_code)]
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, Key};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use way_thumbsense::input::evemu::EvemuDevice;
+use way_thumbsense::input::find_device;
+use way_thumbsense::poll::wait_readable;
+
+/// デバイスが現れる・イベントが届くまでの待ち時間
+pub const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 他のデバイスやテストと重ならない名前
+///
+/// 入力側の仮想デバイスに "way-thumbsense" を含めると自動検出で除外される
+pub fn unique_name(prefix: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "{} {}-{:03}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// MTプロトコルBのタッチパッド（BTN_TOUCH・MT軸・AbsInfoは実機の記録と同じ）
+pub struct FakeTouchpad {
+    pub name: String,
+    device: VirtualDevice,
+    tracking_id: i32,
+    max: (i32, i32),
+}
+
+impl FakeTouchpad {
+    pub fn new() -> Self {
+        let mut description =
+            EvemuDevice::parse(include_str!("../fixtures/touchpad.evemu")).unwrap();
+        description.name = unique_name("thumbsense-test touchpad");
+        let axis_max = |axis: AbsoluteAxisType| description.axes[&axis.0].max;
+        let max = (
+            axis_max(AbsoluteAxisType::ABS_X),
+            axis_max(AbsoluteAxisType::ABS_Y),
+        );
+        let device = description.create_device().expect("create touchpad");
+        let pad = Self {
+            name: description.name,
+            device,
+            tracking_id: 0,
+            max,
+        };
+        wait_for_device(&pad.name);
+        pad
+    }
+
+    /// 座標範囲（フィクスチャのABS_X/ABS_Y）
+    pub fn max(&self) -> (i32, i32) {
+        self.max
+    }
+
+    /// 1本指で触れる
+    pub fn touch(&mut self, x: i32, y: i32) {
+        self.tracking_id += 1;
+        self.emit(&[
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, self.tracking_id),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, x),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_Y, y),
+            key(Key::BTN_TOUCH, 1),
+            key(Key::BTN_TOOL_FINGER, 1),
+            abs(AbsoluteAxisType::ABS_X, x),
+            abs(AbsoluteAxisType::ABS_Y, y),
+        ]);
+    }
+
+    /// 指を離す
+    pub fn lift(&mut self) {
+        self.emit(&[
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+            key(Key::BTN_TOUCH, 0),
+            key(Key::BTN_TOOL_FINGER, 0),
+        ]);
+    }
+
+    fn emit(&mut self, events: &[InputEvent]) {
+        // emit がSYN_REPORTを付ける
+        self.device.emit(events).expect("emit touchpad events");
+    }
+}
+
+/// J/Kを含む一般的なキーボード
+pub struct FakeKeyboard {
+    pub name: String,
+    _device: VirtualDevice,
+}
+
+impl FakeKeyboard {
+    pub fn new() -> Self {
+        let name = unique_name("thumbsense-test keyboard");
+        let keys: AttributeSet<Key> = (Key::KEY_ESC.code()..=Key::KEY_KPDOT.code())
+            .map(Key::new)
+            .collect();
+        let device = VirtualDeviceBuilder::new()
+            .and_then(|b| b.name(&name).with_keys(&keys))
+            .and_then(|b| b.build())
+            .expect("create keyboard");
+        wait_for_device(&name);
+        Self {
+            name,
+            _device: device,
+        }
+    }
+}
+
+pub fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
+
+pub fn key(k: Key, value: i32) -> InputEvent {
+    InputEvent::new(EventType::KEY, k.code(), value)
+}
+
+/// 名前で見つかるまで待つ（udevがノードを作るまで少しかかる）
+pub fn wait_for_device(name: &str) -> Device {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        match find_device(name) {
+            Ok(device) => return device,
+            Err(e) if Instant::now() > deadline => panic!("{} did not appear: {}", name, e),
+            Err(_) => std::thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+/// デバイスから読んだキーイベントを、条件を満たすか時間切れになるまで集める
+pub fn read_keys(
+    device: &mut Device,
+    timeout: Duration,
+    mut done: impl FnMut(&[(Key, i32)]) -> bool,
+) -> Vec<(Key, i32)> {
+    use std::os::unix::io::AsRawFd;
+
+    let deadline = Instant::now() + timeout;
+    let mut keys = Vec::new();
+    while !done(&keys) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let ready = wait_readable(&[device.as_raw_fd()], Some(remaining)).unwrap();
+        if ready[0] {
+            keys.extend(
+                device
+                    .fetch_events()
+                    .unwrap()
+                    .filter(|e| e.event_type() == EventType::KEY)
+                    .map(|e| (Key::new(e.code()), e.value())),
+            );
+        }
+    }
+    keys
+}
@@ -0,0 +1,232 @@
+//! 仮想タッチパッド・キーボードを使った結合テスト
+//!
+//! デバイス検出とデーモン本体を実際に動かし、仮想出力デバイスが出すイベントを確認する。
+//! `/dev/uinput` が必要なので既定では実行せず、`--ignored` を付けて動かす
+
+mod common;
+
+use common::{read_keys, wait_for_device, FakeKeyboard, FakeTouchpad, TIMEOUT};
+use evdev::Key;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use way_thumbsense::config::Config;
+use way_thumbsense::control::{send_request, Request, Status};
+use way_thumbsense::input::{find_device, find_keyboard, find_touchpad, get_touchpad_dimensions};
+
+/// 何も出力されないことを確かめる待ち時間
+const QUIET: Duration = Duration::from_millis(300);
+
+/// テスト用の設定でデーモンを起動し、Dropで終了させる
+struct Daemon {
+    child: Child,
+    dir: PathBuf,
+    socket: PathBuf,
+    keyboard_name: String,
+}
+
+impl Daemon {
+    fn spawn(touchpad: &str, extra_config: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(common::unique_name("way-thumbsense-it").replace(' ', "-"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("control.sock");
+        let keyboard_name = common::unique_name("way-thumbsense it keyboard");
+
+        let config = format!(
+            r#"
+            [device]
+            touchpad = "{touchpad}"
+
+            [virtual_device.mouse]
+            name = "{mouse}"
+
+            [virtual_device.keyboard]
+            name = "{keyboard_name}"
+
+            {extra_config}
+            "#,
+            mouse = common::unique_name("way-thumbsense it mouse"),
+        );
+        Config::parse(&config).expect("test config");
+        let config_path = dir.join("config.toml");
+        std::fs::write(&config_path, config).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_way-thumbsense"))
+            .arg("--config")
+            .arg(&config_path)
+            .arg("--socket")
+            .arg(&socket)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("spawn daemon");
+
+        let mut daemon = Self {
+            child,
+            dir,
+            socket,
+            keyboard_name,
+        };
+        daemon.wait_ready();
+        daemon
+    }
+
+    /// 制御ソケットが応答するまで待つ
+    fn wait_ready(&mut self) {
+        let deadline = Instant::now() + TIMEOUT;
+        while send_request(&self.socket, &Request::Status).is_err() {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                panic!("daemon exited during startup: {}", status);
+            }
+            assert!(Instant::now() < deadline, "daemon did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn request(&self, request: Request) -> Status {
+        let response = send_request(&self.socket, &request).unwrap();
+        assert!(response.ok, "{:?}", response.error);
+        response.status.unwrap()
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM) };
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn contains(key: Key, value: i32) -> impl Fn(&[(Key, i32)]) -> bool {
+    move |keys| keys.contains(&(key, value))
+}
+
+#[test]
+#[ignore = "requires writable /dev/uinput"]
+fn discovers_fake_devices() {
+    let pad = FakeTouchpad::new();
+    let keyboard = FakeKeyboard::new();
+
+    let found = find_device(&pad.name).unwrap();
+    let dims = get_touchpad_dimensions(&found).unwrap();
+    assert_eq!((dims.max_x, dims.max_y), pad.max());
+    assert!(find_device(&keyboard.name).is_ok());
+
+    // 実機がつながっている場合はそちらが選ばれることもあるので条件だけ確認する
+    let touchpad = find_touchpad().unwrap();
+    assert!(touchpad.supported_keys().unwrap().contains(Key::BTN_TOUCH));
+    let keyboard = find_keyboard().unwrap();
+    assert!(keyboard.supported_keys().unwrap().contains(Key::KEY_J));
+}
+
+#[test]
+#[ignore = "requires writable /dev/uinput"]
+fn daemon_holds_trigger_key_while_touching() {
+    let mut pad = FakeTouchpad::new();
+    let daemon = Daemon::spawn(&pad.name, "[zones]\nbottom = 20");
+    let mut output = wait_for_device(&daemon.keyboard_name);
+    let (max_x, max_y) = pad.max();
+
+    // 中央: トリガーキーを押し、離すと戻す
+    pad.touch(max_x / 2, max_y / 2);
+    let keys = read_keys(&mut output, TIMEOUT, contains(Key::KEY_F24, 1));
+    assert_eq!(keys, vec![(Key::KEY_F24, 1)]);
+    assert!(daemon.request(Request::Status).active);
+
+    pad.lift();
+    let keys = read_keys(&mut output, TIMEOUT, contains(Key::KEY_F24, 0));
+    assert_eq!(keys, vec![(Key::KEY_F24, 0)]);
+
+    // 下端の除外領域: 何も出さない
+    pad.touch(max_x / 2, max_y - 10);
+    let keys = read_keys(&mut output, QUIET, |keys| !keys.is_empty());
+    assert!(keys.is_empty(), "unexpected output {:?}", keys);
+    let status = daemon.request(Request::Status);
+    assert!(status.touching && !status.active);
+    pad.lift();
+}
+
+#[test]
+#[ignore = "requires writable /dev/uinput"]
+fn disabling_releases_held_trigger_key() {
+    let mut pad = FakeTouchpad::new();
+    let daemon = Daemon::spawn(&pad.name, "");
+    let mut output = wait_for_device(&daemon.keyboard_name);
+    let (max_x, max_y) = pad.max();
+
+    pad.touch(max_x / 2, max_y / 2);
+    read_keys(&mut output, TIMEOUT, contains(Key::KEY_F24, 1));
+
+    // タッチ中に無効化するとすぐに離す
+    let status = daemon.request(Request::Disable);
+    assert!(!status.enabled && !status.active);
+    let keys = read_keys(&mut output, TIMEOUT, contains(Key::KEY_F24, 0));
+    assert_eq!(keys, vec![(Key::KEY_F24, 0)]);
+
+    // 無効中のタッチは何も出さない
+    pad.lift();
+    pad.touch(max_x / 2, max_y / 2);
+    let keys = read_keys(&mut output, QUIET, |keys| !keys.is_empty());
+    assert!(keys.is_empty(), "unexpected output {:?}", keys);
+    pad.lift();
+}
+
+#[test]
+#[ignore = "requires writable /dev/uinput"]
+fn daemon_starts_with_finger_already_on_pad() {
+    let mut pad = FakeTouchpad::new();
+    let (max_x, max_y) = pad.max();
+
+    // 起動前から触れている: BTN_TOUCH=1 の変化は届かないが、デバイスの状態から有効化する
+    pad.touch(max_x / 2, max_y / 2);
+    let daemon = Daemon::spawn(&pad.name, "");
+    let mut output = wait_for_device(&daemon.keyboard_name);
+    let status = daemon.request(Request::Status);
+    assert!(status.touching && status.active);
+
+    pad.lift();
+    let keys = read_keys(&mut output, TIMEOUT, contains(Key::KEY_F24, 0));
+    assert_eq!(keys, vec![(Key::KEY_F24, 0)]);
+    assert!(!daemon.request(Request::Status).active);
+}
+
+#[test]
+#[ignore = "requires writable /dev/uinput"]
+fn bench_latency_measures_touch_and_key_paths() {
+    let dir =
+        std::env::temp_dir().join(common::unique_name("way-thumbsense-bench").replace(' ', "-"));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_path = dir.join("config.toml");
+    std::fs::write(&config_path, "").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_way-thumbsense"))
+        .arg("--config")
+        .arg(&config_path)
+        .args(["bench-latency", "--iterations", "5", "--interval-ms", "1"])
+        .output()
+        .expect("run bench-latency");
+    let _ = std::fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // タッチ開始と終了、マッピングしたキーの押下と解放でそれぞれ10回
+    let count = |label: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(label))
+            .and_then(|rest| rest.split_whitespace().next())
+            .map(|n| n.to_string())
+    };
+    assert_eq!(
+        count("touch -> trigger").as_deref(),
+        Some("10"),
+        "{}",
+        stdout
+    );
+    assert_eq!(count("key -> click").as_deref(), Some("10"), "{}", stdout);
+}